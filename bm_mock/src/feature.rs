@@ -0,0 +1,93 @@
+// mock 的特征值: f32 小端数组，base64 编码，和盒子上传的 feature 文件格式一致
+// 同一个 seed 生成的特征完全相同，比对得分 100
+
+pub const FEATURE_DIM: usize = 256;
+
+/// 按 seed 生成归一化的特征
+pub fn synthetic(seed: u64, dim: usize) -> Vec<f32> {
+    // xorshift64*, seed 为 0 时换一个值
+    let mut x = if seed == 0 {
+        0x9E37_79B9_7F4A_7C15
+    } else {
+        seed
+    };
+    let mut fea = Vec::with_capacity(dim);
+    for _ in 0..dim {
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        let v = x.wrapping_mul(0x2545_F491_4F6C_DD1D);
+        // 映射到 [-1, 1)
+        fea.push(((v >> 40) as f32 / (1u64 << 23) as f32) - 1.0);
+    }
+    normalize(&mut fea);
+    fea
+}
+
+/// 按内容生成特征(detect/get_features/compare 使用)，同样的图片得到同样的特征
+pub fn from_content(content: &[u8]) -> Vec<f32> {
+    synthetic(fnv1a(content), FEATURE_DIM)
+}
+
+pub fn encode(fea: &[f32]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(fea.len() * 4);
+    for v in fea {
+        buf.extend_from_slice(&v.to_le_bytes());
+    }
+    buf
+}
+
+pub fn encode_base64(fea: &[f32]) -> String {
+    base64::encode(encode(fea))
+}
+
+/// 解码失败返回 None
+pub fn decode_base64(s: &str) -> Option<Vec<f32>> {
+    let buf = base64::decode(s).ok()?;
+    if buf.is_empty() || buf.len() % 4 != 0 {
+        return None;
+    }
+    Some(
+        buf.chunks_exact(4)
+            .map(|x| f32::from_le_bytes([x[0], x[1], x[2], x[3]]))
+            .collect(),
+    )
+}
+
+/// 余弦相似度换算成 0~100 的得分，维度不一致为 0
+pub fn score(a: &[f32], b: &[f32]) -> i64 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0;
+    }
+
+    let mut dot = 0_f64;
+    let mut na = 0_f64;
+    let mut nb = 0_f64;
+    for (x, y) in a.iter().zip(b.iter()) {
+        dot += (*x as f64) * (*y as f64);
+        na += (*x as f64) * (*x as f64);
+        nb += (*y as f64) * (*y as f64);
+    }
+    if na == 0.0 || nb == 0.0 {
+        return 0;
+    }
+
+    let cos = dot / (na.sqrt() * nb.sqrt());
+    (cos.max(0.0) * 100.0).round() as i64
+}
+
+fn normalize(fea: &mut [f32]) {
+    let norm = fea.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        fea.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+fn fnv1a(content: &[u8]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+    for b in content {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
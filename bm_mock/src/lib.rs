@@ -0,0 +1,7 @@
+//! 分析/识别引擎(bm_api)的模拟程序，只在 SE5 上运行的引擎不可用时，
+//! 用于 box_agent、sync_client、track_warehouse 的联调
+
+pub mod feature;
+pub mod rpc;
+pub mod state;
+pub mod upload;
@@ -0,0 +1,125 @@
+use std::net::SocketAddr;
+
+use bm_mock::rpc;
+use bm_mock::state::MockState;
+use bm_mock::upload::TrackUploader;
+use clap::{arg, Command};
+use tokio::sync::watch;
+use tracing::{error, info};
+
+use fy_base::util::logger;
+
+const APP_NAME: &str = "bm_mock";
+const APP_VER_NUM: &str = "0.1.0";
+
+#[tokio::main]
+async fn main() {
+    // 命令行解析
+    let cli_matches = Command::new(APP_NAME)
+        .version(APP_VER_NUM)
+        .about("mock of analysis/recognition api")
+        .arg(
+            arg!(-l --level <level>)
+                .required(false)
+                .default_value("info"),
+        )
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("serve").about("serve json-rpc api").arg(
+                arg!(-a --addr <addr>)
+                    .required(false)
+                    .default_value("0.0.0.0:7001"),
+            ),
+        )
+        .subcommand(
+            Command::new("upload")
+                .about("post synthetic tracks to /trackupload")
+                .arg(
+                    arg!(-u --url <url>)
+                        .required(false)
+                        .default_value("http://127.0.0.1:7002/trackupload"),
+                )
+                .arg(
+                    arg!(-t --type <type> "face or car")
+                        .required(false)
+                        .default_value("face"),
+                )
+                .arg(arg!(-n --count <count>).required(false).default_value("1"))
+                .arg(arg!(-s --seed <seed>).required(false).default_value("1"))
+                .arg(
+                    arg!(--source <source>)
+                        .required(false)
+                        .default_value("mock_source"),
+                )
+                .arg(
+                    arg!(--plate <plate>)
+                        .required(false)
+                        .default_value("粤B12345"),
+                ),
+        )
+        .get_matches();
+
+    let level = cli_matches.value_of("level").unwrap();
+    if let Err(e) = logger::init_console_logger_str(level) {
+        eprintln!("error, init log, err: {:?}", e);
+        return;
+    }
+
+    match cli_matches.subcommand() {
+        Some(("serve", m)) => {
+            let addr = m.value_of("addr").unwrap();
+            let addr = match addr.parse::<SocketAddr>() {
+                Ok(v) => v,
+                Err(e) => {
+                    eprintln!("error, parse addr: {}, err: {:?}", addr, e);
+                    return;
+                }
+            };
+            serve(addr).await;
+        }
+        Some(("upload", m)) => {
+            let count: u64 = m.value_of_t("count").unwrap_or(1);
+            let seed: u64 = m.value_of_t("seed").unwrap_or(1);
+            upload(
+                m.value_of("url").unwrap(),
+                m.value_of("type").unwrap(),
+                m.value_of("source").unwrap(),
+                m.value_of("plate").unwrap(),
+                count,
+                seed,
+            )
+            .await;
+        }
+        _ => {}
+    }
+}
+
+async fn serve(addr: SocketAddr) {
+    let (exit_tx, exit_rx) = watch::channel(0);
+    let state = MockState::new_shared();
+    if let Err(e) = rpc::spawn(addr, state, exit_rx) {
+        error!("error, bm_mock, bind: {}, err: {:?}", addr, e);
+        return;
+    }
+
+    let _ = tokio::signal::ctrl_c().await;
+    let _ = exit_tx.send(100);
+    info!("bm_mock exit.");
+}
+
+async fn upload(url: &str, track_type: &str, source: &str, plate: &str, count: u64, seed: u64) {
+    let uploader = TrackUploader::new(url);
+    let ts = chrono::Local::now().timestamp_millis();
+
+    for i in 0..count {
+        let id = format!("mock_{}_{}", ts, i);
+        let rst = match track_type {
+            "car" => uploader.post_car(source, &id, seed + i, plate).await,
+            _ => uploader.post_face(source, &id, seed + i, 3).await,
+        };
+        match rst {
+            Ok(v) => info!("upload, {}, return: {}", id, v),
+            Err(e) => error!("error, upload, {}, err: {:?}", id, e),
+        }
+    }
+}
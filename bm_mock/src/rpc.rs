@@ -0,0 +1,118 @@
+use std::net::SocketAddr;
+
+use axum::routing::post;
+use axum::{Extension, Json, Router, Server};
+use jsonrpc_core::{Error, Failure, MethodCall, Output, Params, Success};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::watch::Receiver;
+use tracing::{debug, info, warn};
+
+use crate::state::{MockState, SharedState};
+
+//----------------------------------------------
+/// 分析/识别接口共用一个地址，按 method 区分
+pub fn init_router(state: SharedState) -> Router {
+    Router::new()
+        .route("/", post(handle_rpc))
+        .fallback(post(handle_rpc))
+        .layer(Extension(state))
+}
+
+/// 绑定地址(端口可以为0)，后台运行，返回实际的地址。
+/// 集成测试可以直接在进程内启动
+pub fn spawn(
+    addr: SocketAddr,
+    state: SharedState,
+    mut exit_rx: Receiver<i64>,
+) -> hyper::Result<SocketAddr> {
+    let server = Server::try_bind(&addr)?.serve(init_router(state).into_make_service());
+    let local_addr = server.local_addr();
+    info!("bm_mock, listen: {}", local_addr);
+
+    let graceful = server.with_graceful_shutdown(async move {
+        let _ = exit_rx.changed().await;
+        info!("bm_mock, recv signal, will exit");
+    });
+    tokio::spawn(async move {
+        if let Err(e) = graceful.await {
+            warn!("warn, bm_mock, server, err: {:?}", e);
+        }
+    });
+    Ok(local_addr)
+}
+
+async fn handle_rpc(
+    Extension(state): Extension<SharedState>,
+    Json(call): Json<MethodCall>,
+) -> Json<Output> {
+    debug!("bm_mock, <- {}", call.method);
+
+    let rst = {
+        let mut state = state.lock().unwrap();
+        dispatch(&mut state, call.method.as_str(), call.params)
+    };
+
+    let output = match rst {
+        Ok(result) => Output::Success(Success {
+            jsonrpc: call.jsonrpc,
+            result,
+            id: call.id,
+        }),
+        Err(error) => {
+            warn!("warn, bm_mock, {}, err: {:?}", call.method, error);
+            Output::Failure(Failure {
+                jsonrpc: call.jsonrpc,
+                error,
+                id: call.id,
+            })
+        }
+    };
+    Json(output)
+}
+
+fn dispatch(state: &mut MockState, method: &str, params: Params) -> Result<Value, Error> {
+    match method {
+        // analysis
+        "create_source" => call(params, |x| state.create_source(x)),
+        "update_source" => call(params, |x| state.update_source(x)),
+        "delete_source" => call(params, |x| state.delete_source(x)),
+        "get_sources" => call(params, |x| state.get_sources(x)),
+        "get_source_info" => call(params, |x| state.get_source_info(x)),
+
+        // recognition
+        "detect" => call(params, |x| state.detect(x)),
+        "get_features" => call(params, |x| state.get_features(x)),
+        "create_db" => call(params, |x| state.create_db(x)),
+        "get_db_info" => call(params, |x| state.get_db_info(x)),
+        "delete_db" => call(params, |x| state.delete_db(x)),
+        "flush_db" => call(params, |x| state.flush_db(x)),
+        "get_dbs" => call(params, |x| state.get_dbs(x)),
+        "create_persons" => call(params, |x| state.create_persons(x)),
+        "delete_person" => call(params, |x| state.delete_person(x)),
+        "get_db_persons" => call(params, |x| state.get_db_persons(x)),
+        "move_persons" => call(params, |x| state.move_persons(x)),
+        "get_person_info" => call(params, |x| state.get_person_info(x)),
+        "add_features_to_person" => call(params, |x| state.add_features_to_person(x)),
+        "add_aggregate_feature_to_person" => {
+            call(params, |x| state.add_aggregate_feature_to_person(x))
+        }
+        "delete_person_feature" => call(params, |x| state.delete_person_feature(x)),
+        "search" => call(params, |x| state.search(x)),
+        "compare" => call(params, |x| state.compare(x)),
+        "compare_N" => call(params, |x| state.compare_n(x)),
+
+        _ => Err(Error::method_not_found()),
+    }
+}
+
+fn call<R, T, F>(params: Params, f: F) -> Result<Value, Error>
+where
+    R: DeserializeOwned,
+    T: Serialize,
+    F: FnOnce(R) -> T,
+{
+    let req = params.parse::<R>()?;
+    serde_json::to_value(f(req)).map_err(|_| Error::internal_error())
+}
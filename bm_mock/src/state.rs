@@ -0,0 +1,574 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use chrono::Local;
+use fy_base::api::bm_api::*;
+
+use crate::feature;
+
+// 返回的 code，真实引擎的错误码不公开，这里只区分几类
+pub const CODE_OK: i64 = 0;
+pub const CODE_PARAM: i64 = 1;
+pub const CODE_NOT_FOUND: i64 = 2;
+pub const CODE_EXISTS: i64 = 3;
+pub const CODE_FULL: i64 = 4;
+
+pub type SharedState = Arc<Mutex<MockState>>;
+
+//----------------------------------------------
+struct MockSource {
+    url: String,
+    config: CreateSourceReqConfig,
+    create_ts: i64,
+}
+
+struct MockFace {
+    id: i64,
+    raw: String,
+    fea: Vec<f32>,
+    quality: f64,
+}
+
+#[derive(Default)]
+struct MockPerson {
+    faces: Vec<MockFace>,
+    next_face_id: i64,
+    aggregate: Option<(String, Vec<f32>)>,
+}
+
+struct MockDb {
+    volume: i64,
+    persons: BTreeMap<String, MockPerson>,
+}
+
+/// 模拟分析/识别引擎的内存状态，不持久化
+#[derive(Default)]
+pub struct MockState {
+    sources: BTreeMap<String, MockSource>,
+    dbs: BTreeMap<String, MockDb>,
+    next_id: u64,
+}
+
+macro_rules! res_err {
+    ($t:ident, $code:expr, $msg:expr) => {{
+        // 有的 Res 只有 code/msg 两个字段
+        #[allow(clippy::needless_update)]
+        let res = $t {
+            code: $code,
+            msg: $msg.to_string(),
+            ..Default::default()
+        };
+        res
+    }};
+}
+
+impl MockState {
+    pub fn new_shared() -> SharedState {
+        Arc::new(Mutex::new(Self::default()))
+    }
+
+    fn gen_id(&mut self, prefix: &str) -> String {
+        self.next_id += 1;
+        format!("{}_{}", prefix, self.next_id)
+    }
+
+    //------------------ analysis ------------------
+    pub fn create_source(&mut self, req: CreateSourceReq) -> CreateSourceRes {
+        let id = match req.id {
+            Some(v) if !v.is_empty() => v,
+            _ => self.gen_id("src"),
+        };
+        if self.sources.contains_key(&id) {
+            return res_err!(CreateSourceRes, CODE_EXISTS, "source exists");
+        }
+
+        self.sources.insert(
+            id.clone(),
+            MockSource {
+                url: req.url,
+                config: req.config,
+                create_ts: Local::now().timestamp(),
+            },
+        );
+        CreateSourceRes {
+            code: CODE_OK,
+            msg: "".to_string(),
+            id: Some(id),
+        }
+    }
+
+    pub fn update_source(&mut self, req: UpdateSourceReq) -> UpdateSourceRes {
+        match self.sources.get_mut(&req.id) {
+            Some(v) => {
+                v.url = req.url;
+                v.config = req.config;
+                res_err!(UpdateSourceRes, CODE_OK, "")
+            }
+            None => res_err!(UpdateSourceRes, CODE_NOT_FOUND, "source not found"),
+        }
+    }
+
+    pub fn delete_source(&mut self, req: DeleteSourceReq) -> DeleteSourceRes {
+        match self.sources.remove(&req.id) {
+            Some(_) => res_err!(DeleteSourceRes, CODE_OK, ""),
+            None => res_err!(DeleteSourceRes, CODE_NOT_FOUND, "source not found"),
+        }
+    }
+
+    pub fn get_sources(&mut self, _req: GetSourcesReq) -> GetSourcesRes {
+        let sources = self
+            .sources
+            .keys()
+            .map(|x| GetSourcesResSource { id: x.clone() })
+            .collect();
+        GetSourcesRes {
+            code: CODE_OK,
+            msg: "".to_string(),
+            sources: Some(sources),
+        }
+    }
+
+    pub fn get_source_info(&mut self, req: GetSourceInfoReq) -> GetSourceInfoRes {
+        let src = match self.sources.get(&req.id) {
+            Some(v) => v,
+            None => {
+                return res_err!(GetSourceInfoRes, CODE_NOT_FOUND, "source not found");
+            }
+        };
+
+        let now = Local::now().timestamp();
+        GetSourceInfoRes {
+            code: CODE_OK,
+            msg: "".to_string(),
+            state: Some(1),
+            url: Some(src.url.clone()),
+            config: Some(src.config.clone()),
+            last_active_time: Some(now),
+            duration: Some(now - src.create_ts),
+        }
+    }
+
+    //------------------ recognition: image ------------------
+    pub fn detect(&mut self, req: DetectReq) -> DetectRes {
+        let content = match base64::decode(&req.image) {
+            Ok(v) => v,
+            Err(_) => {
+                return res_err!(DetectRes, CODE_PARAM, "image is not base64");
+            }
+        };
+        let fea = feature::from_content(&content);
+
+        let face = ApiDetectFace {
+            aligned: req.image,
+            api_box: ApiRect {
+                x: 0,
+                y: 0,
+                w: 112,
+                h: 112,
+            },
+            pts: [(38, 51), (73, 51), (56, 71), (41, 92), (70, 92)]
+                .iter()
+                .map(|(x, y)| ApiPoint { x: *x, y: *y })
+                .collect(),
+            score: 0.99,
+            feature: req.retfeat.then(|| feature::encode_base64(&fea)),
+            attr: req.retattr.then(mock_attr),
+        };
+        DetectRes {
+            code: CODE_OK,
+            msg: "".to_string(),
+            faces: Some(vec![face]),
+        }
+    }
+
+    pub fn get_features(&mut self, req: GetFeaturesReq) -> GetFeaturesRes {
+        let mut features = vec![];
+        for img in req.images.iter() {
+            match base64::decode(img) {
+                Ok(v) => features.push(feature::encode_base64(&feature::from_content(&v))),
+                Err(_) => {
+                    return res_err!(GetFeaturesRes, CODE_PARAM, "image is not base64");
+                }
+            }
+        }
+
+        let attrs = req
+            .retattr
+            .then(|| req.images.iter().map(|_| mock_attr()).collect());
+        GetFeaturesRes {
+            code: CODE_OK,
+            msg: "".to_string(),
+            features: Some(features),
+            attrs,
+        }
+    }
+
+    pub fn compare(&mut self, req: CompareReq) -> CompareRes {
+        let (a, b) = match (base64::decode(&req.a), base64::decode(&req.b)) {
+            (Ok(a), Ok(b)) => (a, b),
+            _ => {
+                return res_err!(CompareRes, CODE_PARAM, "image is not base64");
+            }
+        };
+
+        let score = feature::score(&feature::from_content(&a), &feature::from_content(&b));
+        CompareRes {
+            code: CODE_OK,
+            msg: "".to_string(),
+            score: Some(score),
+        }
+    }
+
+    pub fn compare_n(&mut self, req: CompareNReq) -> CompareNRes {
+        let a = decode_features(&req.a);
+        let scores = req
+            .b
+            .iter()
+            .map(|x| max_score(&a, decode_features(x).iter()))
+            .collect();
+        CompareNRes {
+            code: CODE_OK,
+            msg: "".to_string(),
+            scores: Some(scores),
+        }
+    }
+
+    //------------------ recognition: db ------------------
+    pub fn create_db(&mut self, req: CreateDbReq) -> CreateDbRes {
+        let id = match req.id {
+            Some(v) if !v.is_empty() => v,
+            _ => self.gen_id("db"),
+        };
+        if self.dbs.contains_key(&id) {
+            return res_err!(CreateDbRes, CODE_EXISTS, "db exists");
+        }
+
+        self.dbs.insert(
+            id.clone(),
+            MockDb {
+                volume: req.volume,
+                persons: BTreeMap::new(),
+            },
+        );
+        CreateDbRes {
+            code: CODE_OK,
+            msg: "".to_string(),
+            id: Some(id),
+        }
+    }
+
+    pub fn get_db_info(&mut self, req: GetDbInfoReq) -> GetDbInfoRes {
+        match self.dbs.get(&req.id) {
+            Some(v) => GetDbInfoRes {
+                code: CODE_OK,
+                msg: "".to_string(),
+                volume: Some(v.volume),
+                usage: Some(v.persons.len() as i64),
+            },
+            None => res_err!(GetDbInfoRes, CODE_NOT_FOUND, "db not found"),
+        }
+    }
+
+    pub fn delete_db(&mut self, req: DeleteDbReq) -> DeleteDbRes {
+        match self.dbs.remove(&req.id) {
+            Some(_) => res_err!(DeleteDbRes, CODE_OK, ""),
+            None => res_err!(DeleteDbRes, CODE_NOT_FOUND, "db not found"),
+        }
+    }
+
+    pub fn flush_db(&mut self, req: FlushDbReq) -> FlushDbRes {
+        match self.dbs.get_mut(&req.id) {
+            Some(v) => {
+                v.persons.clear();
+                res_err!(FlushDbRes, CODE_OK, "")
+            }
+            None => res_err!(FlushDbRes, CODE_NOT_FOUND, "db not found"),
+        }
+    }
+
+    pub fn get_dbs(&mut self, _req: GetDbsReq) -> GetDbsRes {
+        GetDbsRes {
+            code: CODE_OK,
+            msg: "".to_string(),
+            dbs: Some(self.dbs.keys().cloned().collect()),
+        }
+    }
+
+    //------------------ recognition: person ------------------
+    pub fn create_persons(&mut self, req: CreatePersonsReq) -> CreatePersonsRes {
+        if req.ids.len() != req.features.len() {
+            return res_err!(CreatePersonsRes, CODE_PARAM, "ids and features not match");
+        }
+        let db = match self.dbs.get_mut(&req.db) {
+            Some(v) => v,
+            None => {
+                return res_err!(CreatePersonsRes, CODE_NOT_FOUND, "db not found");
+            }
+        };
+        if let Some(id) = req.ids.iter().find(|x| db.persons.contains_key(*x)) {
+            return res_err!(
+                CreatePersonsRes,
+                CODE_EXISTS,
+                format!("person exists: {}", id)
+            );
+        }
+        if db.volume > 0 && (db.persons.len() + req.ids.len()) as i64 > db.volume {
+            return res_err!(CreatePersonsRes, CODE_FULL, "db is full");
+        }
+
+        let mut persons = vec![];
+        for (id, feas) in req.ids.into_iter().zip(req.features) {
+            let mut person = MockPerson::default();
+            let faces = person.add_faces(feas);
+            db.persons.insert(id.clone(), person);
+            persons.push(CreatePersonsResPerson { id, faces });
+        }
+        CreatePersonsRes {
+            code: CODE_OK,
+            msg: "".to_string(),
+            persons: Some(persons),
+        }
+    }
+
+    pub fn delete_person(&mut self, req: DeletePersonReq) -> DeletePersonRes {
+        match self.dbs.get_mut(&req.db) {
+            Some(db) => match db.persons.remove(&req.id) {
+                Some(_) => res_err!(DeletePersonRes, CODE_OK, ""),
+                None => res_err!(DeletePersonRes, CODE_NOT_FOUND, "person not found"),
+            },
+            None => res_err!(DeletePersonRes, CODE_NOT_FOUND, "db not found"),
+        }
+    }
+
+    pub fn get_db_persons(&mut self, req: GetDbPersonsReq) -> GetDbPersonsRes {
+        let db = match self.dbs.get(&req.id) {
+            Some(v) => v,
+            None => {
+                return res_err!(GetDbPersonsRes, CODE_NOT_FOUND, "db not found");
+            }
+        };
+
+        let persons = db
+            .persons
+            .keys()
+            .skip(req.offset.max(0) as usize)
+            .take(req.count.max(0) as usize)
+            .cloned()
+            .collect();
+        GetDbPersonsRes {
+            code: CODE_OK,
+            msg: "".to_string(),
+            persons: Some(persons),
+        }
+    }
+
+    pub fn move_persons(&mut self, req: MovePersonsReq) -> MovePersonsRes {
+        if !self.dbs.contains_key(&req.db_src) || !self.dbs.contains_key(&req.db_dst) {
+            return res_err!(MovePersonsRes, CODE_NOT_FOUND, "db not found");
+        }
+
+        let src = self.dbs.get_mut(&req.db_src).unwrap();
+        let moved: Vec<_> = req
+            .ids
+            .iter()
+            .filter_map(|x| src.persons.remove(x).map(|p| (x.clone(), p)))
+            .collect();
+        let dst = self.dbs.get_mut(&req.db_dst).unwrap();
+        dst.persons.extend(moved);
+        res_err!(MovePersonsRes, CODE_OK, "")
+    }
+
+    pub fn get_person_info(&mut self, req: GetPersonInfoReq) -> GetPersonInfoRes {
+        let person = match self.find_person(&req.db, &req.id) {
+            Ok(v) => v,
+            Err((code, msg)) => {
+                return res_err!(GetPersonInfoRes, code, msg);
+            }
+        };
+
+        let faces = person
+            .faces
+            .iter()
+            .map(|x| GetPersonInfoResFace {
+                id: x.id,
+                feature: x.raw.clone(),
+                quality: x.quality,
+            })
+            .collect();
+        GetPersonInfoRes {
+            code: CODE_OK,
+            msg: "".to_string(),
+            aggregate_feature: person.aggregate.as_ref().map(|x| x.0.clone()),
+            faces: Some(faces),
+        }
+    }
+
+    pub fn add_features_to_person(
+        &mut self,
+        req: AddFeaturesToPersonReq,
+    ) -> AddFeaturesToPersonRes {
+        match self.find_person(&req.db, &req.id) {
+            Ok(person) => {
+                let ids = person.add_faces(req.features);
+                AddFeaturesToPersonRes {
+                    code: CODE_OK,
+                    msg: "".to_string(),
+                    ids: Some(ids),
+                }
+            }
+            Err((code, msg)) => res_err!(AddFeaturesToPersonRes, code, msg),
+        }
+    }
+
+    pub fn add_aggregate_feature_to_person(
+        &mut self,
+        req: AddAggregateFeatureToPersonReq,
+    ) -> AddAggregateFeatureToPersonRes {
+        let fea = match feature::decode_base64(&req.feature) {
+            Some(v) => v,
+            None => {
+                return res_err!(AddAggregateFeatureToPersonRes, CODE_PARAM, "bad feature");
+            }
+        };
+        match self.find_person(&req.db, &req.id) {
+            Ok(person) => {
+                person.aggregate = Some((req.feature, fea));
+                res_err!(AddAggregateFeatureToPersonRes, CODE_OK, "")
+            }
+            Err((code, msg)) => res_err!(AddAggregateFeatureToPersonRes, code, msg),
+        }
+    }
+
+    pub fn delete_person_feature(&mut self, req: DeletePersonFeatureReq) -> DeletePersonFeatureRes {
+        let person = match self.find_person(&req.db, &req.person) {
+            Ok(v) => v,
+            Err((code, msg)) => {
+                return res_err!(DeletePersonFeatureRes, code, msg);
+            }
+        };
+
+        let len = person.faces.len();
+        person.faces.retain(|x| x.id != req.id);
+        if person.faces.len() == len {
+            return res_err!(DeletePersonFeatureRes, CODE_NOT_FOUND, "feature not found");
+        }
+        res_err!(DeletePersonFeatureRes, CODE_OK, "")
+    }
+
+    //------------------ recognition: search ------------------
+    /// 每个待比对的人和库中每个人比对，取特征之间的最高分。
+    /// top/threshold 按 db 的顺序对应，不够的使用最后一个
+    pub fn search(&mut self, req: SearchReq) -> SearchRes {
+        if req.top.is_empty() || req.threshold.is_empty() {
+            return res_err!(SearchRes, CODE_PARAM, "top or threshold is empty");
+        }
+        if let Some(db) = req.db.iter().find(|x| !self.dbs.contains_key(*x)) {
+            return res_err!(SearchRes, CODE_NOT_FOUND, format!("db not found: {}", db));
+        }
+
+        let mut result = vec![];
+        for feas in req.features.iter() {
+            let query = decode_features(feas);
+
+            let mut matches = vec![];
+            for (i, db_id) in req.db.iter().enumerate() {
+                let top = *req.top.get(i).or_else(|| req.top.last()).unwrap();
+                let threshold = *req
+                    .threshold
+                    .get(i)
+                    .or_else(|| req.threshold.last())
+                    .unwrap();
+
+                let mut db_matches: Vec<_> = self.dbs[db_id]
+                    .persons
+                    .iter()
+                    .map(|(id, person)| (max_score(&query, person.features()), id))
+                    .filter(|(score, _)| *score >= threshold)
+                    .collect();
+                // 得分相同按 id 排序，保证结果稳定
+                db_matches.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+                db_matches.truncate(top.max(0) as usize);
+
+                matches.extend(db_matches.into_iter().map(|(score, id)| SearchResPerson {
+                    id: id.clone(),
+                    score,
+                    db: db_id.clone(),
+                }));
+            }
+            matches.sort_by_key(|x| Reverse(x.score));
+            result.push(matches);
+        }
+
+        SearchRes {
+            code: CODE_OK,
+            msg: "".to_string(),
+            persons: Some(result),
+        }
+    }
+
+    //------------------
+    fn find_person(&mut self, db: &str, id: &str) -> Result<&mut MockPerson, (i64, &'static str)> {
+        let db = self
+            .dbs
+            .get_mut(db)
+            .ok_or((CODE_NOT_FOUND, "db not found"))?;
+        db.persons
+            .get_mut(id)
+            .ok_or((CODE_NOT_FOUND, "person not found"))
+    }
+}
+
+impl MockPerson {
+    fn add_faces(&mut self, feas: Vec<ApiFeatureQuality>) -> Vec<i64> {
+        let mut ids = vec![];
+        for x in feas {
+            // 解码失败的特征也保存，只是比对时不参与
+            let fea = feature::decode_base64(&x.feature).unwrap_or_default();
+            self.next_face_id += 1;
+            ids.push(self.next_face_id);
+            self.faces.push(MockFace {
+                id: self.next_face_id,
+                raw: x.feature,
+                fea,
+                quality: x.quality,
+            });
+        }
+        ids
+    }
+
+    fn features(&self) -> impl Iterator<Item = &Vec<f32>> {
+        self.faces
+            .iter()
+            .map(|x| &x.fea)
+            .chain(self.aggregate.iter().map(|x| &x.1))
+    }
+}
+
+//----------------------------------------------
+fn decode_features(feas: &[ApiFeatureQuality]) -> Vec<Vec<f32>> {
+    feas.iter()
+        .filter_map(|x| feature::decode_base64(&x.feature))
+        .collect()
+}
+
+fn max_score<'a, I>(query: &[Vec<f32>], target: I) -> i64
+where
+    I: Iterator<Item = &'a Vec<f32>>,
+{
+    let target: Vec<_> = target.collect();
+    query
+        .iter()
+        .flat_map(|a| target.iter().map(move |b| feature::score(a, b)))
+        .max()
+        .unwrap_or(0)
+}
+
+fn mock_attr() -> ApiDetectFaceAttr {
+    ApiDetectFaceAttr {
+        age: 30,
+        gender: 1,
+        glasses: 0,
+        direction: 0,
+    }
+}
@@ -0,0 +1,243 @@
+use std::io::Cursor;
+use std::time::Duration;
+
+use chrono::Local;
+use fy_base::api::bm_api::{
+    ApiCarPlateBit, ApiCarPlateInfo, ApiCarPlateType, ApiFaceProps, ApiPosition, ApiRect,
+    CarNotifyParams, FaceNotifyParams, NotifyBackground, NotifyCar, NotifyFace,
+};
+use image::{ImageOutputFormat, Rgb, RgbImage};
+use reqwest::multipart::{Form, Part};
+use reqwest::{Client, Error};
+
+use crate::feature;
+
+//----------------------------------------------
+/// 模拟分析引擎，向 box_agent 的 /trackupload 上报 track。
+/// 图片是纯色 jpg，人脸特征由 seed 生成，和 feature::synthetic 一致
+pub struct TrackUploader {
+    url: String,
+    client: Client,
+}
+
+impl TrackUploader {
+    pub fn new(url: &str) -> Self {
+        let client = Client::builder()
+            .connect_timeout(Duration::from_secs(3))
+            .build()
+            .unwrap();
+
+        Self {
+            url: url.to_string(),
+            client,
+        }
+    }
+
+    /// 上报一个人脸track，faces 张人脸的特征都由 seed 生成，返回 box_agent 的应答内容
+    pub async fn post_face(
+        &self,
+        source: &str,
+        id: &str,
+        seed: u64,
+        faces: usize,
+    ) -> Result<String, Error> {
+        let fea = feature::encode(&feature::synthetic(seed, feature::FEATURE_DIM));
+
+        let mut notify = FaceNotifyParams {
+            background: mock_background(),
+            faces: vec![],
+            id: id.to_string(),
+            index: 0,
+            position: mock_position(),
+            props: Some(ApiFaceProps {
+                age: 30,
+                gender: 1,
+                glasses: 0,
+                move_direction: 0,
+            }),
+            source: source.to_string(),
+            trip: None,
+            version: "mock".to_string(),
+        };
+        for i in 0..faces {
+            notify.faces.push(NotifyFace {
+                aligned: None,
+                aligned_file: format!("aligned_{}.jpg", i),
+                angles: [0.0, 0.0, 0.0],
+                display: None,
+                display_file: format!("display_{}.jpg", i),
+                feature_file: Some(format!("feature_{}.bin", i)),
+                frame_num: i as i64,
+                quality: 0.9 - 0.1 * i as f64,
+                rect: mock_rect(112, 112),
+                aligned_buf: Default::default(),
+                display_buf: Default::default(),
+                feature_buf: None,
+            });
+        }
+
+        let mut form = Form::new()
+            .text("type", "facetrack")
+            .text("json", serde_json::to_string(&notify).unwrap());
+        form = form.part(
+            notify.background.image_file.clone(),
+            jpg_part(&notify.background.image_file, 640, 360, seed),
+        );
+        for face in notify.faces.iter() {
+            form = form
+                .part(
+                    face.aligned_file.clone(),
+                    jpg_part(&face.aligned_file, 112, 112, seed),
+                )
+                .part(
+                    face.display_file.clone(),
+                    jpg_part(&face.display_file, 160, 200, seed),
+                );
+            if let Some(ref name) = face.feature_file {
+                let part = Part::bytes(fea.clone())
+                    .file_name(name.clone())
+                    .mime_str("application/octet-stream")?;
+                form = form.part(name.clone(), part);
+            }
+        }
+
+        self.post(form).await
+    }
+
+    /// 上报一个车辆track，plate 为空则没有车牌
+    pub async fn post_car(
+        &self,
+        source: &str,
+        id: &str,
+        seed: u64,
+        plate: &str,
+    ) -> Result<String, Error> {
+        let plate_info = if plate.is_empty() {
+            None
+        } else {
+            Some(ApiCarPlateInfo {
+                binary_file: None,
+                text: Some(plate.to_string()),
+                image: None,
+                image_file: Some("plate.jpg".to_string()),
+                plate_type: Some(ApiCarPlateType {
+                    value: "blue".to_string(),
+                    conf: 0.95,
+                }),
+                bits: Some(
+                    plate
+                        .chars()
+                        .map(|c| {
+                            vec![ApiCarPlateBit {
+                                value: c.to_string(),
+                                conf: 0.95,
+                            }]
+                        })
+                        .collect(),
+                ),
+                img_buf: Default::default(),
+                binary_buf: Default::default(),
+            })
+        };
+
+        let notify = CarNotifyParams {
+            id: id.to_string(),
+            index: 0,
+            source: source.to_string(),
+            vehicles: vec![NotifyCar {
+                image: None,
+                image_file: "vehicle_0.jpg".to_string(),
+                frame_num: 0,
+                rect: mock_rect(320, 240),
+                img_buf: Default::default(),
+            }],
+            plate_info,
+            background: mock_background(),
+            position: mock_position(),
+            trip: None,
+            props: None,
+            version: "mock".to_string(),
+        };
+
+        let mut form = Form::new()
+            .text("type", "vehicletrack")
+            .text("json", serde_json::to_string(&notify).unwrap());
+        form = form.part(
+            notify.background.image_file.clone(),
+            jpg_part(&notify.background.image_file, 640, 360, seed),
+        );
+        for car in notify.vehicles.iter() {
+            form = form.part(
+                car.image_file.clone(),
+                jpg_part(&car.image_file, 320, 240, seed),
+            );
+        }
+        if let Some(ref v) = notify.plate_info {
+            if let Some(ref name) = v.image_file {
+                form = form.part(name.clone(), jpg_part(name, 140, 40, seed));
+            }
+        }
+
+        self.post(form).await
+    }
+
+    async fn post(&self, form: Form) -> Result<String, Error> {
+        let res = self
+            .client
+            .post(self.url.as_str())
+            .multipart(form)
+            .send()
+            .await?
+            .error_for_status()?;
+        res.text().await
+    }
+}
+
+//----------------------------------------------
+/// 纯色 jpg，颜色由 seed 决定
+pub fn synthetic_jpg(width: u32, height: u32, seed: u64) -> Vec<u8> {
+    let color = Rgb([seed as u8, (seed >> 8) as u8, (seed >> 16) as u8]);
+    let img = RgbImage::from_pixel(width, height, color);
+
+    let mut buf = Vec::new();
+    img.write_to(&mut Cursor::new(&mut buf), ImageOutputFormat::Jpeg(85))
+        .unwrap();
+    buf
+}
+
+fn jpg_part(name: &str, width: u32, height: u32, seed: u64) -> Part {
+    Part::bytes(synthetic_jpg(width, height, seed))
+        .file_name(name.to_string())
+        .mime_str("image/jpeg")
+        .unwrap()
+}
+
+fn mock_rect(w: i64, h: i64) -> ApiRect {
+    ApiRect { x: 0, y: 0, w, h }
+}
+
+fn mock_background() -> NotifyBackground {
+    NotifyBackground {
+        frame_num: 0,
+        height: 360,
+        width: 640,
+        image: None,
+        image_file: "background.jpg".to_string(),
+        rect: mock_rect(640, 360),
+        video_width: 1920,
+        video_height: 1080,
+        image_buf: Default::default(),
+    }
+}
+
+fn mock_position() -> ApiPosition {
+    let now = Local::now().timestamp_millis();
+    ApiPosition {
+        end: 0,
+        end_frame: 0,
+        end_real_time: now,
+        start: 0,
+        start_frame: 0,
+        start_real_time: now,
+    }
+}
@@ -0,0 +1,86 @@
+//! 在进程内启动 bm_mock，通过 bm_api 的客户端端到端调用
+
+use std::net::SocketAddr;
+
+use bm_mock::feature;
+use bm_mock::rpc;
+use bm_mock::state::MockState;
+use fy_base::api::bm_api::{AnalysisApi, ApiFeatureQuality, CreateSourceReqConfig, RecognitionApi};
+use fy_base::util::tls::ClientTls;
+use tokio::sync::watch;
+
+// 返回 url，Sender 被 drop 之前服务一直运行
+fn start() -> (String, watch::Sender<i64>) {
+    let (tx, rx) = watch::channel(0);
+    let addr: SocketAddr = "127.0.0.1:0".parse().unwrap();
+    let addr = rpc::spawn(addr, MockState::new_shared(), rx).unwrap();
+    (format!("http://{}", addr), tx)
+}
+
+fn features(seed: u64) -> Vec<ApiFeatureQuality> {
+    let fea = feature::synthetic(seed, feature::FEATURE_DIM);
+    vec![ApiFeatureQuality {
+        feature: feature::encode_base64(&fea),
+        quality: 1.0,
+    }]
+}
+
+#[tokio::test]
+async fn recognition_create_and_search() {
+    let (url, _tx) = start();
+    let api = RecognitionApi::new(&url, &ClientTls::default());
+
+    let res = api.create_db(Some("db_1".to_string()), 100).await.unwrap();
+    assert_eq!(res.code, 0, "{}", res.msg);
+    assert_eq!(res.id.as_deref(), Some("db_1"));
+
+    let ids = vec!["p_1".to_string(), "p_2".to_string()];
+    let res = api
+        .create_persons("db_1".to_string(), ids, vec![features(1), features(2)])
+        .await
+        .unwrap();
+    assert_eq!(res.code, 0, "{}", res.msg);
+    assert_eq!(res.persons.map(|x| x.len()), Some(2));
+
+    let res = api
+        .search(
+            vec!["db_1".to_string()],
+            vec![1],
+            vec![80],
+            vec![features(2)],
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.code, 0, "{}", res.msg);
+    let persons = res.persons.unwrap();
+    assert_eq!(persons.len(), 1);
+    assert_eq!(persons[0].len(), 1);
+    assert_eq!(persons[0][0].id, "p_2");
+    assert_eq!(persons[0][0].db, "db_1");
+    assert_eq!(persons[0][0].score, 100);
+}
+
+#[tokio::test]
+async fn analysis_create_and_get_sources() {
+    let (url, _tx) = start();
+    let api = AnalysisApi::new(&url, &ClientTls::default());
+
+    let config: CreateSourceReqConfig =
+        serde_json::from_str(include_str!("data/source_config.json")).unwrap();
+    let res = api
+        .create_source(
+            Some("cam_1".to_string()),
+            "rtsp://127.0.0.1/cam_1".to_string(),
+            "http://127.0.0.1:7002/trackupload".to_string(),
+            config,
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.code, 0, "{}", res.msg);
+    assert_eq!(res.id.as_deref(), Some("cam_1"));
+
+    let res = api.get_sources().await.unwrap();
+    assert_eq!(res.code, 0, "{}", res.msg);
+    let ids: Vec<String> = res.sources.unwrap().into_iter().map(|x| x.id).collect();
+    assert_eq!(ids, vec!["cam_1".to_string()]);
+}
@@ -1,6 +1,11 @@
 use crate::error::{AppError, AppResult};
 
+use fy_base::util::image::{ImageProfile, RedactMethod};
+use fy_base::util::sign::Secret;
+use fy_base::util::tls::TlsClientCfg;
+
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
 use std::net::SocketAddr;
 use std::path::Path;
@@ -23,6 +28,47 @@ pub struct AppCfgLog {
 pub struct AppCfgApi {
     pub grab_url: String,
     pub recg_url: String,
+    pub tls: TlsClientCfg, // 算法服务为 https 时使用
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AppCfgTrackFaceBestShot {
+    pub quality_weight: f64,
+    pub frontal_weight: f64,
+    pub size_weight: f64,
+
+    pub max_angle: f64, // yaw/pitch 超过该角度，正脸分为0
+    pub full_size: i64, // 人脸宽高达到该像素，尺寸分为1
+
+    pub keep: u64,   // 每个track保留的人脸数
+    pub search: u64, // 参与比对的特征数
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AppCfgTrackFaceAlarm {
+    pub enable: bool,
+    pub default_score: i64,
+    // 按db设置告警分数，没有设置的用 default_score
+    pub db_scores: HashMap<String, i64>,
+    pub cooldown: u64, // second, 同一人员在该时间内只告警一次
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DedupMode {
+    Local, // 盒子本地计算特征相似度
+    Api,   // 调用 compare_N
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AppCfgTrackFaceDedup {
+    pub enable: bool,
+    pub mode: DedupMode,
+    pub window: u64,     // second, 只和该时间内上传过的比较
+    pub threshold: i64,  // 相似度达到该分数视为重复
+    pub max_recent: u64, // 最多保留的最近track数
+    // 摄像头组，组内的摄像头之间去重，不在任何组中的摄像头自己为一组
+    pub groups: Vec<Vec<String>>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -35,7 +81,21 @@ pub struct AppCfgTrackFace {
 
     pub search_top: u64,
     pub search_threshold: u64,
+    pub search_worker: u64,
+    pub search_batch: u64,
     pub cache_ttl: u64,
+
+    pub best_shot: AppCfgTrackFaceBestShot,
+    pub alarm: AppCfgTrackFaceAlarm,
+    pub dedup: AppCfgTrackFaceDedup,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AppCfgTrackCarWatchlist {
+    pub enable: bool,
+    pub file: String, // sync_client 同步下来的车牌布控名单
+    pub reload: u64,  // second, 检查名单文件是否有更新的间隔
+    pub fuzzy: bool,  // 易混淆字符(0/D, 8/B 等)视为相同
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -44,6 +104,65 @@ pub struct AppCfgTrackCar {
     pub ready_delay: u64,
     pub count: u64,
     pub conf: f64,
+
+    pub watchlist: AppCfgTrackCarWatchlist,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AppCfgTrackFilter {
+    pub enable: bool,
+    pub file: String, // sync_client 同步下来的摄像头过滤规则
+    pub reload: u64,  // second, 检查规则文件是否有更新的间隔
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AppCfgTrackSchedule {
+    pub enable: bool,
+    pub file: String, // sync_client 同步下来的摄像头采集时间表
+    pub reload: u64,  // second, 检查时间表文件是否有更新的间隔
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PrivacyRedact {
+    None,
+    OutsideSubject, // 目标框以外全部脱敏
+    Faces,          // 只对 detect 检测到的人脸脱敏(人脸track的目标人脸除外)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PrivacyBackground {
+    Keep,
+    Downscale, // 缩小到 max_width
+    Drop,      // 不上传背景图
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AppCfgTrackPrivacyRule {
+    pub redact: PrivacyRedact,
+    pub method: RedactMethod,
+    pub strength: u32,
+    pub margin: f64, // 目标框四周多保留的比例
+    pub background: PrivacyBackground,
+    pub max_width: u32,
+    pub quality: u8, // 重新编码jpeg的质量
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AppCfgTrackPrivacy {
+    pub enable: bool,
+    pub default: AppCfgTrackPrivacyRule,
+    // 按摄像头设置，没有设置的用 default
+    pub cameras: HashMap<String, AppCfgTrackPrivacyRule>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AppCfgTrackCapture {
+    pub enable: bool,
+    pub dir: String,
+    pub file_size: u64, // MB, 单个归档文件的大小
+    pub files: u64,     // 保留的归档文件数
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -51,12 +170,72 @@ pub struct AppCfgTrack {
     pub local_address: String,
     pub face: AppCfgTrackFace,
     pub car: AppCfgTrackCar,
+    pub filter: AppCfgTrackFilter,
+    pub schedule: AppCfgTrackSchedule,
+    pub privacy: AppCfgTrackPrivacy,
+    pub capture: AppCfgTrackCapture,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    DropOldest,
+    DropNewest,
+    Reject,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AppCfgUplinkSpool {
+    pub dir: String,
+    pub max_size: u64,  // MB
+    pub retry_max: u64, // second
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AppCfgUplinkImage {
+    pub profiles: HashMap<String, ImageProfile>,
+    // 各类图片使用的 profile 名称，为空则不转码
+    pub aligned: String,
+    pub display: String,
+    pub background: String,
+}
+
+impl AppCfgUplinkImage {
+    pub fn profile(&self, name: &str) -> Option<&ImageProfile> {
+        self.profiles.get(name)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AppCfgUplinkBatch {
+    pub enable: bool,
+    pub server: String, // track_warehouse 的 /upload_batch
+    pub size: u64,      // 每批最多的track数
+    pub linger: u64,    // ms, 不够 size 个时最多等待的时间
+    pub gzip: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AppCfgUplinkSign {
+    pub enable: bool,
+    pub box_id: Option<String>, // 盒子硬件编号，不配置时从设备读取SN
+    pub secret: Secret,         // base_box 中为该盒子配置的 secret
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct AppCfgUplink {
     pub max_queue: u64,
+    pub overflow: OverflowPolicy,
     pub server: String,
+    pub alarm_server: String,
+    pub alarm_queue: u64, // 告警队列长度，满了不再放入新的告警，不受 overflow 影响
+    pub alarm_expire: u64, // second, 告警上报失败时一直重试，超过该时间丢弃
+    pub pool_idle: u64,   // 每个host保留的空闲连接数，0 不复用连接
+    pub spool: AppCfgUplinkSpool,
+    pub batch: AppCfgUplinkBatch,
+    pub sign: AppCfgUplinkSign,
+    pub tls: TlsClientCfg,
+    pub image: AppCfgUplinkImage,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -80,6 +259,133 @@ impl AppCfg {
             return Err(AppError::from_debug(e));
         };
 
+        if self.track.face.search_worker == 0 || self.track.face.search_batch == 0 {
+            return Err(AppError::new(
+                "track.face.search_worker and search_batch must be > 0",
+            ));
+        }
+
+        let best_shot = &self.track.face.best_shot;
+        if best_shot.keep == 0 || best_shot.search == 0 {
+            return Err(AppError::new(
+                "track.face.best_shot.keep and search must be > 0",
+            ));
+        }
+        if best_shot.max_angle <= 0.0 || best_shot.full_size <= 0 {
+            return Err(AppError::new(
+                "track.face.best_shot.max_angle and full_size must be > 0",
+            ));
+        }
+
+        let dedup = &self.track.face.dedup;
+        if dedup.enable
+            && (dedup.window == 0
+                || dedup.max_recent == 0
+                || dedup.threshold <= 0
+                || dedup.threshold > 100)
+        {
+            return Err(AppError::new(
+                "track.face.dedup.window and max_recent must be > 0, threshold must be in 1..=100",
+            ));
+        }
+
+        let watchlist = &self.track.car.watchlist;
+        if watchlist.enable && (watchlist.file.is_empty() || watchlist.reload == 0) {
+            return Err(AppError::new(
+                "track.car.watchlist.file must be set and reload must be > 0",
+            ));
+        }
+
+        let filter = &self.track.filter;
+        if filter.enable && (filter.file.is_empty() || filter.reload == 0) {
+            return Err(AppError::new(
+                "track.filter.file must be set and reload must be > 0",
+            ));
+        }
+
+        let schedule = &self.track.schedule;
+        if schedule.enable && (schedule.file.is_empty() || schedule.reload == 0) {
+            return Err(AppError::new(
+                "track.schedule.file must be set and reload must be > 0",
+            ));
+        }
+
+        let privacy = &self.track.privacy;
+        if privacy.enable {
+            for rule in std::iter::once(&privacy.default).chain(privacy.cameras.values()) {
+                if rule.quality == 0 || rule.quality > 100 {
+                    return Err(AppError::new("track.privacy quality must be in 1..=100"));
+                }
+                if rule.background == PrivacyBackground::Downscale && rule.max_width == 0 {
+                    return Err(AppError::new(
+                        "track.privacy max_width must be > 0 for downscale",
+                    ));
+                }
+            }
+        }
+
+        let capture = &self.track.capture;
+        if capture.enable && (capture.file_size == 0 || capture.files == 0) {
+            return Err(AppError::new(
+                "track.capture.file_size and files must be > 0",
+            ));
+        }
+
+        if self.up_link.max_queue == 0 {
+            return Err(AppError::new("up_link.max_queue must be > 0"));
+        }
+
+        if self.up_link.alarm_queue == 0 {
+            return Err(AppError::new("up_link.alarm_queue must be > 0"));
+        }
+        if self.up_link.alarm_expire == 0 {
+            return Err(AppError::new("up_link.alarm_expire must be > 0"));
+        }
+
+        let spool = &self.up_link.spool;
+        if spool.max_size == 0 {
+            return Err(AppError::new("up_link.spool.max_size must be > 0"));
+        }
+        if spool.retry_max < 2 {
+            return Err(AppError::new("up_link.spool.retry_max must be >= 2"));
+        }
+
+        if let Err(e) = self.api.tls.validate() {
+            return Err(AppError::new(&format!("api.tls: {}", e)));
+        }
+        if let Err(e) = self.up_link.tls.validate() {
+            return Err(AppError::new(&format!("up_link.tls: {}", e)));
+        }
+
+        if self.up_link.sign.enable && self.up_link.sign.secret.is_empty() {
+            return Err(AppError::new("up_link.sign.secret must be set"));
+        }
+
+        let batch = &self.up_link.batch;
+        if batch.enable && (batch.server.is_empty() || batch.size == 0) {
+            return Err(AppError::new(
+                "up_link.batch.server must be set and size must be > 0",
+            ));
+        }
+
+        let image = &self.up_link.image;
+        for (name, profile) in image.profiles.iter() {
+            if let Err(e) = profile.validate() {
+                return Err(AppError::new(&format!(
+                    "up_link.image.profiles.{}, {}",
+                    name, e
+                )));
+            }
+        }
+        for name in [&image.aligned, &image.display, &image.background] {
+            if !name.is_empty() && image.profile(name).is_none() {
+                return Err(AppError::new(&format!(
+                    "up_link.image, profile not found: {}",
+                    name
+                )));
+            }
+        }
+
         Ok(())
     }
 }
@@ -5,6 +5,8 @@ use crate::app_cfg::AppCfg;
 use fy_base::{
     api::bm_api::{AnalysisApi, RecognitionApi},
     util::service::SignalProduce,
+    util::sign::Signer,
+    util::tls::ClientTls,
 };
 
 //---------------------
@@ -15,18 +17,34 @@ pub struct AppCtx {
 
     pub ana_api: AnalysisApi,
     pub recg_api: RecognitionApi,
+
+    // 上传到 track_warehouse 的请求签名，没有开启时为 None
+    pub signer: Option<Signer>,
+    // 上传用的 tls 配置
+    pub tls: ClientTls,
+    // 访问算法服务用的 tls 配置
+    pub api_tls: ClientTls,
 }
 
 impl AppCtx {
-    pub fn new(cfg: AppCfg, exit_rx: Receiver<i64>) -> Self {
-        let ana_api = AnalysisApi::new(&cfg.api.grab_url);
-        let recg_api = RecognitionApi::new(&cfg.api.recg_url);
+    pub fn new(
+        cfg: AppCfg,
+        exit_rx: Receiver<i64>,
+        signer: Option<Signer>,
+        tls: ClientTls,
+        api_tls: ClientTls,
+    ) -> Self {
+        let ana_api = AnalysisApi::new(&cfg.api.grab_url, &api_tls);
+        let recg_api = RecognitionApi::new(&cfg.api.recg_url, &api_tls);
 
         Self {
             cfg,
             exit_rx,
             ana_api,
             recg_api,
+            signer,
+            tls,
+            api_tls,
         }
     }
 
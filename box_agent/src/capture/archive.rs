@@ -0,0 +1,235 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use bytes::{Buf, Bytes, BytesMut};
+use chrono::Local;
+use fy_base::api::upload_codec::{get_buf, put_buf};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::error::{AppError, AppResult};
+
+const EXT_CAPTURE: &str = "cap";
+
+//----------------------------------------------
+/// 一个 multipart 字段，file_name 为 None 的是文本
+#[derive(Debug, Clone)]
+pub struct CapturePart {
+    pub name: String,
+    pub file_name: Option<String>,
+    pub data: Bytes,
+}
+
+/// 原始请求 body，解析之前保存
+#[derive(Debug, Clone)]
+pub struct CaptureRaw {
+    pub content_type: String,
+    pub body: Bytes,
+}
+
+/// 一次 /trackupload 请求。
+/// 老版本的归档按字段保存在 parts 中，新的保存原始 body，解析失败的请求也能回放
+#[derive(Debug, Clone)]
+pub struct CaptureRecord {
+    pub ts: i64, // 接收时间, ms
+    pub parts: Vec<CapturePart>,
+    pub raw: Option<CaptureRaw>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PartHeader {
+    name: String,
+    file_name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RecordHeader {
+    ts: i64,
+    parts: Vec<PartHeader>,
+    // 有值时 parts 之后是原始 body
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content_type: Option<String>,
+}
+
+impl CaptureRecord {
+    pub fn from_raw(content_type: &str, body: Bytes) -> Self {
+        Self {
+            ts: Local::now().timestamp_millis(),
+            parts: vec![],
+            raw: Some(CaptureRaw {
+                content_type: content_type.to_string(),
+                body,
+            }),
+        }
+    }
+
+    // 格式: 总长度 + header json + 按header顺序排列的各个字段内容 + 原始body
+    fn encode(&self) -> AppResult<Bytes> {
+        let header = RecordHeader {
+            ts: self.ts,
+            parts: self
+                .parts
+                .iter()
+                .map(|x| PartHeader {
+                    name: x.name.clone(),
+                    file_name: x.file_name.clone(),
+                })
+                .collect(),
+            content_type: self.raw.as_ref().map(|x| x.content_type.clone()),
+        };
+
+        let mut body = BytesMut::new();
+        put_buf(&mut body, &serde_json::to_vec(&header)?);
+        for part in self.parts.iter() {
+            put_buf(&mut body, &part.data);
+        }
+        if let Some(ref raw) = self.raw {
+            put_buf(&mut body, &raw.body);
+        }
+
+        let mut buf = BytesMut::with_capacity(body.len() + 4);
+        put_buf(&mut buf, &body);
+        Ok(buf.freeze())
+    }
+
+    fn decode(mut body: Bytes) -> AppResult<Self> {
+        let header: RecordHeader = serde_json::from_slice(&get_buf(&mut body)?)?;
+
+        let mut parts = vec![];
+        for x in header.parts {
+            parts.push(CapturePart {
+                name: x.name,
+                file_name: x.file_name,
+                data: get_buf(&mut body)?,
+            });
+        }
+        let raw = match header.content_type {
+            Some(content_type) => Some(CaptureRaw {
+                content_type,
+                body: get_buf(&mut body)?,
+            }),
+            None => None,
+        };
+
+        Ok(Self {
+            ts: header.ts,
+            parts,
+            raw,
+        })
+    }
+}
+
+//----------------------------------------------
+/// 追加写入归档文件，超过 max_size 后换新文件，只保留最新的 max_files 个
+pub struct ArchiveWriter {
+    dir: PathBuf,
+    max_size: u64,
+    max_files: usize,
+    file: Option<fs::File>,
+    size: u64,
+}
+
+impl ArchiveWriter {
+    pub fn open<P: AsRef<Path>>(dir: P, max_size: u64, max_files: usize) -> AppResult<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        Ok(Self {
+            dir,
+            max_size,
+            max_files,
+            file: None,
+            size: 0,
+        })
+    }
+
+    pub fn write(&mut self, record: &CaptureRecord) -> AppResult<()> {
+        let buf = record.encode()?;
+
+        if self.file.is_none() || (self.size > 0 && self.size + buf.len() as u64 > self.max_size) {
+            self.rotate()?;
+        }
+
+        if let Some(ref mut file) = self.file {
+            file.write_all(&buf)?;
+            self.size += buf.len() as u64;
+        }
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> AppResult<()> {
+        let file_name = format!("capture_{}", Local::now().format("%Y%m%d_%H%M%S_%3f"));
+        let path = self.dir.join(file_name).with_extension(EXT_CAPTURE);
+        info!("ArchiveWriter, new file: {:?}", path);
+
+        self.file = Some(
+            fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)?,
+        );
+        self.size = 0;
+
+        // 删除最老的
+        let files = list_archives(&self.dir)?;
+        if files.len() > self.max_files {
+            for x in files.iter().take(files.len() - self.max_files) {
+                info!("ArchiveWriter, remove: {:?}", x);
+                if let Err(e) = fs::remove_file(x) {
+                    warn!("warn, ArchiveWriter, remove: {:?}, err: {:?}", x, e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+//----------------------------------------------
+/// path 为目录时返回其中所有归档文件，按时间排序
+pub fn list_archives(path: &Path) -> AppResult<Vec<PathBuf>> {
+    if path.is_file() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let mut files = vec![];
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|x| x == EXT_CAPTURE) {
+            files.push(path);
+        }
+    }
+    // 文件名中是时间
+    files.sort();
+    Ok(files)
+}
+
+/// 读取一个归档文件，写了一半的最后一条忽略
+pub fn read_archive(path: &Path) -> AppResult<Vec<CaptureRecord>> {
+    let mut buf = Bytes::from(fs::read(path)?);
+
+    let mut records = vec![];
+    while buf.has_remaining() {
+        if buf.remaining() < 4 {
+            warn!("warn, read_archive, {:?} truncated", path);
+            break;
+        }
+        let len = (&buf[..4]).get_u32() as usize;
+        if buf.remaining() < len + 4 {
+            warn!("warn, read_archive, {:?} truncated", path);
+            break;
+        }
+
+        let body = get_buf(&mut buf)?;
+        match CaptureRecord::decode(body) {
+            Ok(v) => records.push(v),
+            Err(e) => {
+                return Err(AppError::new(&format!(
+                    "decode capture: {:?}, err: {:?}",
+                    path, e
+                )));
+            }
+        }
+    }
+    Ok(records)
+}
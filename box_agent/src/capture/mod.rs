@@ -0,0 +1,3 @@
+pub mod archive;
+pub mod recorder;
+pub mod replay;
@@ -0,0 +1,56 @@
+use std::sync::Arc;
+use tracing::{error, info};
+
+use tokio::sync::watch::Receiver;
+use tokio::task::JoinHandle as TkJoinHandle;
+
+use crate::app_ctx::AppCtx;
+use crate::capture::archive::ArchiveWriter;
+use crate::error::AppResult;
+use crate::queue_item::CaptureQueue;
+
+use fy_base::util::service::Service;
+
+/// 把 /trackupload 收到的请求写入归档文件，用于回放。
+/// 写文件在单独的服务中，不影响接口的响应
+pub struct CaptureService {
+    queue: Arc<CaptureQueue>,
+    writer: ArchiveWriter,
+}
+
+impl CaptureService {
+    pub fn new(ctx: Arc<AppCtx>, queue: Arc<CaptureQueue>) -> AppResult<Self> {
+        let cfg = &ctx.cfg.track.capture;
+        let writer = ArchiveWriter::open(
+            cfg.dir.as_str(),
+            cfg.file_size * 1024 * 1024,
+            cfg.files as usize,
+        )?;
+
+        Ok(Self { queue, writer })
+    }
+}
+
+impl Service for CaptureService {
+    fn run(self, rx: Receiver<i64>) -> TkJoinHandle<()> {
+        let mut svc = self;
+        let mut exit_rx = rx;
+
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = exit_rx.changed() => {
+                        info!("CaptureService recv exit");
+                        break;
+                    }
+                    record = svc.queue.pop() => {
+                        if let Err(e) = svc.writer.write(&record) {
+                            error!("error, CaptureService, write, err: {:?}", e);
+                        }
+                    }
+                }
+            }
+            info!("CaptureService exit");
+        })
+    }
+}
@@ -0,0 +1,106 @@
+use std::path::Path;
+use std::time::Duration;
+
+use reqwest::multipart::{Form, Part};
+use reqwest::{header, Client};
+use tracing::{error, info};
+
+use crate::capture::archive::{list_archives, read_archive, CapturePart, CaptureRecord};
+use crate::error::{AppError, AppResult};
+
+/// 把归档中的请求重新提交到 url(box_agent 的 /trackupload 或 track_warehouse 的 /upload)。
+/// speed: 1 为原速，大于1加速，0 不等待
+pub async fn replay(path: &Path, url: &str, speed: f64) -> AppResult<()> {
+    let client = Client::builder()
+        .connect_timeout(Duration::from_secs(10))
+        .build()
+        .map_err(AppError::from_debug)?;
+
+    let files = list_archives(path)?;
+    info!("replay, {:?}, files: {}", path, files.len());
+
+    let mut last_ts = None;
+    let (mut ok_count, mut err_count) = (0, 0);
+    for file in files.iter() {
+        let records = read_archive(file)?;
+        info!("replay, {:?}, records: {}", file, records.len());
+
+        for record in records {
+            // 按原来的间隔等待
+            if let Some(last) = last_ts {
+                let interval = record.ts - last;
+                if speed > 0.0 && interval > 0 {
+                    let wait = (interval as f64 / speed) as u64;
+                    tokio::time::sleep(Duration::from_millis(wait)).await;
+                }
+            }
+            last_ts = Some(record.ts);
+
+            match post_record(&client, url, record).await {
+                Ok(v) => {
+                    ok_count += 1;
+                    info!("replay, return: {}", v);
+                }
+                Err(e) => {
+                    err_count += 1;
+                    error!("error, replay, err: {:?}", e);
+                }
+            }
+        }
+    }
+
+    info!("replay end, ok: {}, err: {}", ok_count, err_count);
+    Ok(())
+}
+
+async fn post_record(client: &Client, url: &str, record: CaptureRecord) -> AppResult<String> {
+    // 原始 body 原样提交
+    let req = match record.raw {
+        Some(raw) => client
+            .post(url)
+            .header(header::CONTENT_TYPE, raw.content_type)
+            .body(raw.body),
+        None => client.post(url).multipart(build_form(record.parts)?),
+    };
+
+    let res = req.send().await.map_err(AppError::from_debug)?;
+    let status = res.status();
+    let body = res.text().await.map_err(AppError::from_debug)?;
+    if !status.is_success() {
+        return Err(AppError::new(&format!("http status: {}, {}", status, body)));
+    }
+    Ok(body)
+}
+
+// 老版本的归档，按字段重新组成表单
+fn build_form(parts: Vec<CapturePart>) -> AppResult<Form> {
+    let mut form = Form::new();
+    for part in parts {
+        form = match part.file_name {
+            Some(file_name) => {
+                let mime = guess_mime(&file_name);
+                let p = Part::bytes(part.data.to_vec())
+                    .file_name(file_name)
+                    .mime_str(mime)
+                    .map_err(AppError::from_debug)?;
+                form.part(part.name, p)
+            }
+            None => form.text(part.name, String::from_utf8_lossy(&part.data).into_owned()),
+        };
+    }
+    Ok(form)
+}
+
+fn guess_mime(file_name: &str) -> &'static str {
+    let ext = Path::new(file_name)
+        .extension()
+        .and_then(|x| x.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    match ext.as_str() {
+        "jpg" | "jpeg" => "image/jpeg",
+        "bmp" => "image/bmp",
+        "png" => "image/png",
+        _ => "application/octet-stream",
+    }
+}
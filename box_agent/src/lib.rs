@@ -1,5 +1,6 @@
 pub mod app_cfg;
 pub mod app_ctx;
+pub mod capture;
 pub mod error;
 
 pub mod queue_item;
@@ -1,21 +1,29 @@
-use box_agent::app_cfg::AppCfg;
+use box_agent::app_cfg::{AppCfg, OverflowPolicy};
 use box_agent::app_ctx::AppCtx;
-use box_agent::queue_item::{CarQueue, FaceQueue};
+use box_agent::capture::recorder::CaptureService;
+use box_agent::capture::replay;
+use box_agent::queue_item::{AlarmQueue, CaptureQueue, CarQueue, FaceQueue, UplinkQueue};
+use box_agent::service::camera_schedule::CameraSchedules;
 use box_agent::service::car::car_notify::CarNotifyService;
+use box_agent::service::face::alarm::AlarmChecker;
+use box_agent::service::face::face_dedup::FaceDedupService;
 use box_agent::service::face::face_notify::FaceNotifyService;
-use box_agent::service::face::face_search::FaceSearchService;
+use box_agent::service::face::face_search::{new_dbs_cache, FaceSearchService};
 use box_agent::service::signal_service::SignalService;
+use box_agent::service::track_filter::CameraFilters;
 use box_agent::service::web::WebService;
+use box_agent::uplink::alarm::AlarmService;
+use box_agent::uplink::privacy::PrivacyService;
 use box_agent::uplink::upload::UplinkService;
 use build_time::build_time_local;
-use clap::{arg, Command};
-use deadqueue::unlimited::Queue;
-use fy_base::api::upload_api::QI;
+use clap::{arg, ArgMatches, Command};
+use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::watch;
-use tracing::info;
+use tracing::{error, info};
 
-use fy_base::util::{logger, service::ServiceRepo};
+use fy_base::util::{logger, se5, service::ServiceRepo, sign::Signer, tls::ClientTls};
 
 const APP_NAME: &str = "box_agent";
 const APP_VER_NUM: &str = "0.1.0";
@@ -34,8 +42,29 @@ async fn main() {
                 .required(false)
                 .default_value("cfg.json"),
         )
+        .subcommand(
+            Command::new("replay")
+                .about("replay captured /trackupload requests")
+                .arg(arg!(<path> "capture file or dir"))
+                .arg(
+                    arg!(-u --url <url>)
+                        .required(false)
+                        .default_value("http://127.0.0.1:8090/trackupload"),
+                )
+                .arg(
+                    arg!(-s --speed <speed> "1: original speed, 0: no wait")
+                        .required(false)
+                        .default_value("1"),
+                ),
+        )
         .get_matches();
 
+    // 回放抓包，不启动服务
+    if let Some(m) = cli_matches.subcommand_matches("replay") {
+        run_replay(m).await;
+        return;
+    }
+
     // 读取config参数
     let config_file = cli_matches
         .value_of("config")
@@ -74,55 +103,235 @@ async fn main() {
         }
     };
 
+    // 上传签名，box_id 没有配置时从设备读取SN
+    let sign_cfg = &app_config.up_link.sign;
+    let signer = if sign_cfg.enable {
+        let box_id = match sign_cfg.box_id {
+            Some(ref v) => v.clone(),
+            None => match se5::get_device_sn() {
+                Ok(v) => v,
+                Err(e) => {
+                    error!("error, read device sn, err:{:?}", e);
+                    return;
+                }
+            },
+        };
+        info!("up_link sign, box_id: {}", box_id);
+        Some(Signer::new(&box_id, &sign_cfg.secret))
+    } else {
+        None
+    };
+
+    let tls = match ClientTls::load(&app_config.up_link.tls) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("error, load up_link tls, err:{}", e);
+            return;
+        }
+    };
+
+    let api_tls = match ClientTls::load(&app_config.api.tls) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("error, load api tls, err:{}", e);
+            return;
+        }
+    };
+
     // 初始化 context
     let (exit_tx, exit_rx) = watch::channel(0);
-    let app_context = Arc::new(AppCtx::new(app_config, exit_rx));
+    let app_context = Arc::new(AppCtx::new(app_config, exit_rx, signer, tls, api_tls));
 
     // 创建服务集
     let mut service_repo = ServiceRepo::new(app_context.clone());
 
     // 创建队列
-    let car_queue: Arc<CarQueue> = Arc::new(Queue::new());
-    let face_queue: Arc<FaceQueue> = Arc::new(Queue::new());
-    let face_search_queue: Arc<FaceQueue> = Arc::new(Queue::new());
-    let uplink_queue: Arc<Queue<QI>> = Arc::new(Queue::new());
+    let max_queue = app_context.cfg.up_link.max_queue as usize;
+    let overflow = app_context.cfg.up_link.overflow;
+    let car_queue = Arc::new(CarQueue::new("car", max_queue, overflow));
+    let face_queue = Arc::new(FaceQueue::new("face", max_queue, overflow));
+    let face_search_queue = Arc::new(FaceQueue::new("face_search", max_queue, overflow));
+    let uplink_queue = Arc::new(UplinkQueue::new("uplink", max_queue, overflow));
+    // 告警不能被大量的 track 挤掉，单独的长度，满了拒绝新的告警(不开始冷却)
+    let alarm_queue = Arc::new(AlarmQueue::new(
+        "alarm",
+        app_context.cfg.up_link.alarm_queue as usize,
+        OverflowPolicy::Reject,
+    ));
+    car_queue.register_metrics();
+    face_queue.register_metrics();
+    face_search_queue.register_metrics();
+    uplink_queue.register_metrics();
+    alarm_queue.register_metrics();
 
     // 初始退出信号服务
     let exit_service = SignalService::new(exit_tx);
 
     // 初始web服务
-    let web_service = WebService::new(app_context.clone(), face_queue.clone(), car_queue.clone());
+    // 抓包模式
+    let capture_queue = if app_context.cfg.track.capture.enable {
+        let queue = Arc::new(CaptureQueue::new(
+            "capture",
+            max_queue,
+            OverflowPolicy::DropNewest,
+        ));
+        queue.register_metrics();
+        Some(queue)
+    } else {
+        None
+    };
+    let capture_service = match capture_queue {
+        Some(ref queue) => match CaptureService::new(app_context.clone(), queue.clone()) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                error!("error, open capture dir, err: {:?}", e);
+                return;
+            }
+        },
+        None => None,
+    };
 
-    // 初始化 人脸track接收 服务
-    let face_notify_service =
-        FaceNotifyService::new(app_context.clone(), face_queue, face_search_queue.clone());
+    let web_service = WebService::new(
+        app_context.clone(),
+        face_queue.clone(),
+        car_queue.clone(),
+        capture_queue,
+    );
+
+    // 摄像头过滤规则，人脸和车辆共用
+    let filter_cfg = &app_context.cfg.track.filter;
+    let camera_filters = if filter_cfg.enable {
+        Some(Arc::new(CameraFilters::new(
+            &filter_cfg.file,
+            Duration::from_secs(filter_cfg.reload),
+        )))
+    } else {
+        None
+    };
 
-    // 初始化 人脸track 比对 服务
-    let face_search_service = FaceSearchService::new(
-        0,
+    // 摄像头采集时间表，人脸和车辆共用
+    let schedule_cfg = &app_context.cfg.track.schedule;
+    let camera_schedules = if schedule_cfg.enable {
+        Some(Arc::new(CameraSchedules::new(
+            &schedule_cfg.file,
+            Duration::from_secs(schedule_cfg.reload),
+        )))
+    } else {
+        None
+    };
+
+    // 初始化 人脸track接收 服务
+    let face_notify_service = FaceNotifyService::new(
         app_context.clone(),
-        face_search_queue,
-        uplink_queue.clone(),
+        face_queue,
+        face_search_queue.clone(),
+        camera_filters.clone(),
+        camera_schedules.clone(),
     );
 
+    // 隐私处理，所有track上传前都经过
+    let (privacy_service, upload_in_queue) = if app_context.cfg.track.privacy.enable {
+        let queue = Arc::new(UplinkQueue::new("privacy", max_queue, overflow));
+        queue.register_metrics();
+        let service = PrivacyService::new(app_context.clone(), queue.clone(), uplink_queue.clone());
+        (Some(service), queue)
+    } else {
+        (None, uplink_queue.clone())
+    };
+
+    // 跨摄像头去重，在比对之后，上传之前
+    let (face_dedup_service, face_out_queue) = if app_context.cfg.track.face.dedup.enable {
+        let queue = Arc::new(UplinkQueue::new("face_dedup", max_queue, overflow));
+        queue.register_metrics();
+        let service =
+            FaceDedupService::new(app_context.clone(), queue.clone(), upload_in_queue.clone());
+        (Some(service), queue)
+    } else {
+        (None, upload_in_queue.clone())
+    };
+
+    // 初始化 人脸track 比对 服务，多个worker共用db列表缓存
+    let dbs_cache = new_dbs_cache(app_context.cfg.track.face.cache_ttl);
+    let alarm_checker = Arc::new(AlarmChecker::new(app_context.clone(), alarm_queue.clone()));
+    let face_search_services: Vec<FaceSearchService> =
+        (0..app_context.cfg.track.face.search_worker)
+            .map(|num| {
+                FaceSearchService::new(
+                    num as i64,
+                    app_context.clone(),
+                    face_search_queue.clone(),
+                    face_out_queue.clone(),
+                    dbs_cache.clone(),
+                    alarm_checker.clone(),
+                )
+            })
+            .collect();
+
     // 初始化 车辆track接收 服务
-    let car_notify_service =
-        CarNotifyService::new(app_context.clone(), car_queue.clone(), uplink_queue.clone());
+    let car_notify_service = CarNotifyService::new(
+        app_context.clone(),
+        car_queue.clone(),
+        upload_in_queue,
+        camera_filters,
+        camera_schedules,
+    );
 
     // 创建 uplink服务
-    let uplink_service = UplinkService::new(app_context.clone(), uplink_queue);
+    let uplink_service = match UplinkService::new(app_context.clone(), uplink_queue) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("error, open uplink spool, err: {:?}", e);
+            return;
+        }
+    };
+
+    // 创建 告警上报服务
+    let alarm_service = AlarmService::new(app_context.clone(), alarm_queue);
 
     // 启动服务
     service_repo.start_service(exit_service);
     service_repo.start_service(web_service);
     service_repo.start_service(face_notify_service);
-    service_repo.start_service(face_search_service);
+    for face_search_service in face_search_services {
+        service_repo.start_service(face_search_service);
+    }
+    if let Some(face_dedup_service) = face_dedup_service {
+        service_repo.start_service(face_dedup_service);
+    }
     service_repo.start_service(car_notify_service);
+    if let Some(privacy_service) = privacy_service {
+        service_repo.start_service(privacy_service);
+    }
 
     service_repo.start_service(uplink_service);
+    service_repo.start_service(alarm_service);
+    if let Some(capture_service) = capture_service {
+        service_repo.start_service(capture_service);
+    }
 
     // 等待退出
     service_repo.join().await;
 
     info!("app end.");
 }
+
+async fn run_replay(m: &ArgMatches) {
+    if let Err(e) = logger::init_console_logger_str("info") {
+        eprintln!("error, init log, err: {:?}", e);
+        return;
+    }
+
+    let path = m.value_of("path").expect("can't find path argument");
+    let url = m.value_of("url").expect("can't find url argument");
+    let speed: f64 = match m.value_of_t("speed") {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("error, speed, err: {:?}", e);
+            return;
+        }
+    };
+
+    if let Err(e) = replay::replay(Path::new(path), url, speed).await {
+        error!("error, replay: {}, err: {:?}", path, e);
+    }
+}
@@ -1,6 +1,115 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
 use deadqueue::unlimited::Queue;
-use fy_base::api::upload_api::{NotifyCarQueueItem, NotifyFaceQueueItem};
+use fy_base::api::upload_api::{NotifyAlarmItem, NotifyCarQueueItem, NotifyFaceQueueItem, QI};
+use fy_base::util::{metrics, utils};
+use tracing::warn;
+
+use crate::app_cfg::OverflowPolicy;
+use crate::capture::archive::CaptureRecord;
 
 //-----------------------
-pub type FaceQueue = Queue<NotifyFaceQueueItem>;
-pub type CarQueue = Queue<NotifyCarQueueItem>;
+pub type FaceQueue = BoundedQueue<NotifyFaceQueueItem>;
+pub type CarQueue = BoundedQueue<NotifyCarQueueItem>;
+pub type UplinkQueue = BoundedQueue<QI>;
+pub type AlarmQueue = BoundedQueue<NotifyAlarmItem>;
+pub type CaptureQueue = BoundedQueue<CaptureRecord>;
+
+/// 有长度限制的队列，超出后按 policy 丢弃。
+/// 要放入的数据没有入队时(DropNewest/Reject) push 返回Err，由调用方决定如何处理(web接口直接返回错误)
+pub struct BoundedQueue<T> {
+    name: String,
+    max: usize,
+    policy: OverflowPolicy,
+    inner: Queue<T>,
+    dropped: AtomicU64,
+}
+
+impl<T: Send + 'static> BoundedQueue<T> {
+    /// 队列长度输出到 /metrics
+    pub fn register_metrics(self: &Arc<Self>) {
+        let queue = self.clone();
+        metrics::register_queue(&self.name, move || queue.len());
+    }
+}
+
+impl<T> BoundedQueue<T> {
+    pub fn new(name: &str, max: usize, policy: OverflowPolicy) -> Self {
+        Self {
+            name: name.to_string(),
+            max,
+            policy,
+            inner: Queue::new(),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    pub fn push(&self, item: T) -> Result<(), T> {
+        match self.policy {
+            OverflowPolicy::DropOldest => {
+                self.inner.push(item);
+                while self.inner.len() > self.max {
+                    if self.inner.try_pop().is_some() {
+                        self.incr_dropped();
+                    }
+                }
+                Ok(())
+            }
+            OverflowPolicy::DropNewest => {
+                if self.inner.len() >= self.max {
+                    self.incr_dropped();
+                    return Err(item);
+                }
+                self.inner.push(item);
+                Ok(())
+            }
+            OverflowPolicy::Reject => {
+                if self.inner.len() >= self.max {
+                    self.incr_dropped();
+                    return Err(item);
+                }
+                self.inner.push(item);
+                Ok(())
+            }
+        }
+    }
+
+    pub async fn pop(&self) -> T {
+        self.inner.pop().await
+    }
+
+    pub fn try_pop(&self) -> Option<T> {
+        self.inner.try_pop()
+    }
+
+    pub async fn pop_batch(&self, max: usize) -> Vec<T> {
+        utils::pop_queue_batch(&self.inner, max).await
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    pub fn policy(&self) -> OverflowPolicy {
+        self.policy
+    }
+
+    /// 因队列满而丢弃(或拒绝)的数量
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    fn incr_dropped(&self) {
+        metrics::inc_queue_dropped(&self.name);
+        let count = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+        warn!(
+            "queue: {} full, max: {}, policy: {:?}, dropped: {}",
+            self.name, self.max, self.policy, count
+        );
+    }
+}
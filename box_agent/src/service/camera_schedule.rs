@@ -0,0 +1,36 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+
+use fy_base::api::sync_api::{CameraSchedule, ScheduleOutside};
+use fy_base::util::json_file::JsonFile;
+
+//----------------------------------------------
+/// 摄像头采集时间表，由 sync_client 从 sync_server 同步到本地文件。
+/// 没有时间表的摄像头全天采集
+pub struct CameraSchedules {
+    file: JsonFile<HashMap<String, CameraSchedule>>,
+}
+
+impl CameraSchedules {
+    pub fn new(file: &str, reload: Duration) -> Self {
+        Self {
+            file: JsonFile::new("CameraSchedules", file, reload, parse_schedules),
+        }
+    }
+
+    /// 在采集时间窗口内返回 None，否则返回窗口外的处理方式
+    pub fn outside(&self, source: &str, now: DateTime<Local>) -> Option<ScheduleOutside> {
+        match self.file.get().get(source) {
+            Some(v) if !v.is_active(now) => Some(v.outside),
+            _ => None,
+        }
+    }
+}
+
+//----------------------------------------------
+fn parse_schedules(content: &[u8]) -> serde_json::Result<HashMap<String, CameraSchedule>> {
+    let list: Vec<CameraSchedule> = serde_json::from_slice(content)?;
+    Ok(list.into_iter().map(|x| (x.uuid.clone(), x)).collect())
+}
@@ -1,222 +1,119 @@
 use std::sync::Arc;
 use std::time::Duration;
 
-use chrono::prelude::*;
-use dashmap::{DashMap, DashSet};
-use deadqueue::unlimited::Queue;
+use chrono::Local;
 use log::{debug, error, info, warn};
 
-use tokio::sync::mpsc::{self, Receiver as TkReceiver, Sender as TkSender};
 use tokio::sync::watch::Receiver;
-use tokio::sync::Mutex;
 use tokio::task::JoinHandle as TkJoinHandle;
-use tokio::time::error::Error as TkTimeError;
-use tokio_util::time::delay_queue::{DelayQueue, Expired};
 
-use fy_base::api::bm_api::CarNotifyParams;
-use fy_base::util::delay_queue::DelayQueueChan;
+use fy_base::api::bm_api::{ApiCarPlateInfo, ApiCarProps};
+use fy_base::api::sync_api::ScheduleOutside;
+use fy_base::util::metrics;
 use fy_base::util::service::Service;
+use fy_base::util::track_aggregator::{TrackAggregator, TrackHandler};
 
 use crate::app_ctx::AppCtx;
-
-use crate::error::AppResult;
-use crate::queue_item::CarQueue;
+use crate::queue_item::{CarQueue, UplinkQueue};
+use crate::service::camera_schedule::CameraSchedules;
+use crate::service::car::car_vote;
+use crate::service::car::plate_watch::PlateWatchlist;
+use crate::service::track_filter::CameraFilters;
 use fy_base::api::upload_api::{NotifyCarQueueItem, QI};
 
-use super::{SerialPool, SpHolder};
-
 // ------------------- structs -------------------
-pub struct Track {
-    uuid: String,
-    ts: DateTime<Local>,
-
-    ready_flag: bool,
-
-    notify: CarNotifyParams,
-}
-
-pub enum TrackEvent {
-    New,
-    APPEND(Box<Track>),
-    DELAY(String),
+// 聚合中的车辆track，保留每一帧的车牌和属性，结束时投票
+pub struct CarTrack {
+    item: NotifyCarQueueItem,
+    plates: Vec<ApiCarPlateInfo>,
+    props: Vec<ApiCarProps>,
 }
 
 pub struct CarHandler {
-    sender: TkSender<String>,
+    ctx: Arc<AppCtx>,
+    watchlist: Option<PlateWatchlist>,
 }
 
 pub struct CarNotifyService {
-    ctx: Arc<AppCtx>,
-    queue: Arc<Queue<NotifyCarQueueItem>>,
-
-    spool: SerialPool,
-    ready_tx: TkSender<(String, Duration)>,
-    ready_rx: TkReceiver<Result<Expired<String>, TkTimeError>>,
-
-    clean_tx: TkSender<(String, Duration)>,
-    clean_rx: TkReceiver<Result<Expired<String>, TkTimeError>>,
-
-    track_map: DashMap<String, Arc<SpHolder>>,
-    track_id_map: DashSet<String>,
-
-    next_rx: TkReceiver<String>,
-
-    out: Arc<Queue<QI>>,
+    queue: Arc<CarQueue>,
+    aggregator: TrackAggregator<CarHandler>,
+    out: Arc<UplinkQueue>,
+    filters: Option<Arc<CameraFilters>>,
+    schedules: Option<Arc<CameraSchedules>>,
 }
 
 // ------------------- impls -------------------
 impl CarNotifyService {
-    pub fn new(ctx: Arc<AppCtx>, queue: Arc<CarQueue>, out: Arc<Queue<QI>>) -> Self {
-        let (next_tx, next_rx) = mpsc::channel(100);
-
-        let handler = CarHandler { sender: next_tx };
-
-        let spool = SerialPool::new(handler);
-
-        let dq_ready = DelayQueue::new();
-        let (ready_tx, ready_rx) = dq_ready.channel();
-
-        let dq_clean = DelayQueue::new();
-        let (clean_tx, clean_rx) = dq_clean.channel();
+    pub fn new(
+        ctx: Arc<AppCtx>,
+        queue: Arc<CarQueue>,
+        out: Arc<UplinkQueue>,
+        filters: Option<Arc<CameraFilters>>,
+        schedules: Option<Arc<CameraSchedules>>,
+    ) -> Self {
+        let ready_delay = Duration::from_millis(ctx.cfg.track.car.ready_delay);
+        let clean_delay = Duration::from_millis(ctx.cfg.track.car.clear_delay);
+
+        let watch_cfg = &ctx.cfg.track.car.watchlist;
+        let watchlist = if watch_cfg.enable {
+            Some(PlateWatchlist::new(
+                &watch_cfg.file,
+                Duration::from_secs(watch_cfg.reload),
+                watch_cfg.fuzzy,
+            ))
+        } else {
+            None
+        };
 
-        let track_map = DashMap::new();
-        let track_id_map = DashSet::new();
+        let handler = CarHandler { ctx, watchlist };
+        let aggregator = TrackAggregator::new(handler, ready_delay, clean_delay);
 
         CarNotifyService {
-            ctx,
             queue,
-            spool,
-            ready_tx,
-            ready_rx,
-            clean_tx,
-            clean_rx,
-            track_map,
-            track_id_map,
-            next_rx,
+            aggregator,
             out,
+            filters,
+            schedules,
         }
     }
 
-    // 检查车牌号的每一个bit的conf
-    fn check_plate_conf(&self, item: &NotifyCarQueueItem) -> bool {
-        let conf = self.ctx.cfg.track.car.conf;
-        if item.notify.plate_info.is_none() {
-            return false;
-        }
-        let plate_info = item.notify.plate_info.as_ref().unwrap();
-        if plate_info.bits.is_none() {
-            return false;
-        }
+    async fn process_item(&mut self, mut item: NotifyCarQueueItem) {
+        debug!("CarNotifyProcSvc, process_item:{:?}", item.uuid);
 
-        let plate_bits = plate_info.bits.as_ref().unwrap();
-        for bit in plate_bits {
-            if let Some(bit_value) = bit.get(0) {
-                if bit_value.conf < conf {
-                    return false;
+        // 按摄像头规则过滤，在聚合之前
+        // 采集时间窗口外的，丢弃或标记为低优先级
+        if let Some(ref schedules) = self.schedules {
+            match schedules.outside(&item.notify.source, Local::now()) {
+                Some(ScheduleOutside::Drop) => {
+                    metrics::inc_filtered("vehicle", "schedule");
+                    return;
+                }
+                Some(ScheduleOutside::LowPriority) => {
+                    item.low_priority = true;
                 }
+                None => {}
             }
         }
 
-        true
-    }
-
-    // 有车牌且conf大于某个值 + 车身图大于count
-    fn check_recv_mode(&self, item: &NotifyCarQueueItem) -> bool {
-        let count = self.ctx.cfg.track.car.count as usize;
-
-        let check_plate = self.check_plate_conf(item);
-        let img_count = item.notify.vehicles.len();
-
-        check_plate && (img_count >= count)
-    }
-
-    async fn process_item(&mut self, item: NotifyCarQueueItem) {
-        debug!("CarNotifyProcSvc, process_item:{:?}", item.uuid);
-
-        let uuid = item.uuid.clone();
-        let ready = self.check_recv_mode(&item);
-        let track = Track {
-            ready_flag: ready,
-            uuid: item.uuid,
-            ts: item.ts,
-
-            notify: item.notify,
-        };
-
-        // 如果在track_id_map中有，但是在 track_map中没有，说明该track的数据已经传递到下一步，这个track的后续数据忽略
-        if self.track_id_map.contains(&uuid) && !self.track_map.contains_key(&uuid) {
-            warn!("warn, CarNotifyProcSvc, {}, has put to next, skip it", uuid);
-            return;
-        }
-
-        if let Some(holder) = self.track_map.get(uuid.as_str()) {
-            // 已经在 spmap中, dispath it
-            self.spool
-                .dispatch(holder.clone(), TrackEvent::APPEND(Box::new(track)))
-                .await;
-            debug!("already in spmap, {}", uuid);
-        } else {
-            // 新的track, 不在spmap中, dispath it
-            debug!("create to spmap, {}", uuid);
-            let holder = Arc::new(SpHolder::new(track));
-            self.track_map.insert(uuid.clone(), holder.clone());
-            self.track_id_map.insert(uuid.clone());
-            self.spool.dispatch(holder.clone(), TrackEvent::New).await;
-
-            // 放入清除/延时队列中
-            let clean_timeout = self.ctx.cfg.track.car.clear_delay;
-            let _ = self
-                .clean_tx
-                .send((uuid.clone(), Duration::from_millis(clean_timeout)))
-                .await;
-            debug!("put to clean_queue, {}", uuid);
-
-            // 如果not ready, 加入 timeout队列
-            if !ready {
-                let ready_timeout = self.ctx.cfg.track.car.ready_delay;
-                let _ = self
-                    .ready_tx
-                    .send((uuid.clone(), Duration::from_millis(ready_timeout)))
-                    .await;
-                debug!("put to timeout_queue, {}", uuid);
+        if let Some(ref filters) = self.filters {
+            if !filters.filter_car(&mut item.notify) {
+                return;
             }
         }
-    }
 
-    async fn process_ready_timeout(&self, uuid: String) {
-        debug!("process ready_timeout: {}", uuid);
-        if let Some(holder) = self.track_map.get(&uuid) {
-            // 已经在map中
-            let holder = holder.value().clone();
-            self.spool.dispatch(holder, TrackEvent::DELAY(uuid)).await;
-        } else if !self.track_id_map.contains(&uuid) {
-            error!("error, uuid not in map, {}", uuid);
+        if let Some(item) = self.aggregator.push(CarTrack::new(item)).await {
+            self.put_to_next(item);
         }
     }
 
-    async fn process_clean_timeout(&self, uuid: String) {
-        debug!("process clean_timeout: {}", uuid);
-        let _ = self.track_id_map.remove(&uuid);
-        debug!("remove uuid: {}", uuid);
-    }
-
-    // 从track_map中删除，放入下一个队列中
-    async fn process_put_next(&self, uuid: String) {
-        debug!("process put_next: {}", uuid);
-        let track = self.track_map.remove(&uuid);
-        if let Some((_, track)) = track {
-            let (ts, notify) = {
-                let guard = track.data.lock().await;
-                (guard.ts, guard.notify.clone())
-            };
-
-            let item = NotifyCarQueueItem {
-                uuid: uuid.clone(),
-                notify,
-                ts,
-            };
-
-            self.out.push(QI::CT(Box::new(item)));
+    // 放入下一个队列中
+    fn put_to_next(&self, item: NotifyCarQueueItem) {
+        let uuid = item.uuid.clone();
+        metrics::inc_processed("car_notify");
+        if self.out.push(QI::CT(Box::new(item))).is_err() {
+            error!("error, CarNotifyService, out queue full, drop: {}", uuid);
+        } else {
+            debug!("CarHandler put_to_next ok, {}", uuid);
         }
     }
 }
@@ -231,22 +128,14 @@ impl Service for CarNotifyService {
             loop {
                 tokio::select! {
                     _ = exit_rx.changed() => {
-
-                            info!("CarNotifyProcSvc recv exit");
-                            break;
-
+                        info!("CarNotifyProcSvc recv exit");
+                        break;
                     }
                     item = svc.queue.pop() => {
                         svc.process_item(item).await;
                     }
-                    Some(Ok(expired)) = svc.ready_rx.recv() => {
-                        svc.process_ready_timeout(expired.into_inner()).await;
-                    }
-                    Some(Ok(expired)) = svc.clean_rx.recv() => {
-                        svc.process_clean_timeout(expired.into_inner()).await;
-                    }
-                    Some(track_id) = svc.next_rx.recv() => {
-                        svc.process_put_next(track_id).await;
+                    Some(item) = svc.aggregator.next_timeout() => {
+                        svc.put_to_next(item);
                     }
                 }
             }
@@ -256,69 +145,124 @@ impl Service for CarNotifyService {
 }
 
 // ------------------- impl Handler -------------------
-impl CarHandler {
-    /// 查询 source
-    /// 生成 FtQI 放入后续队列中
-    async fn put_to_next(&self, track: &Track) -> AppResult<()> {
-        let _ = self.sender.send(track.uuid.clone()).await;
-        Ok(())
+impl CarTrack {
+    fn new(item: NotifyCarQueueItem) -> Self {
+        let plates = item.notify.plate_info.iter().cloned().collect();
+        let props = item.notify.props.iter().cloned().collect();
+        CarTrack {
+            item,
+            plates,
+            props,
+        }
     }
+}
 
-    pub async fn process(&self, holder_data: Arc<Mutex<Track>>, events: Vec<TrackEvent>) {
-        let mut newed = false;
-        let mut appended = false;
-        let mut delayed = false;
-        // let ready_old;
-
-        let mut data = holder_data.lock().await;
-        let ready_old = data.ready_flag;
+impl CarHandler {
+    // 检查车牌号的每一个bit的conf
+    fn check_plate_conf(&self, item: &NotifyCarQueueItem) -> bool {
+        let conf = self.ctx.cfg.track.car.conf;
+        if item.notify.plate_info.is_none() {
+            return false;
+        }
+        let plate_info = item.notify.plate_info.as_ref().unwrap();
+        if plate_info.bits.is_none() {
+            return false;
+        }
 
-        // debug!("sp process: {}, events len: {}, ready_old:{}", data.uuid, events.len(), ready_old);
-        for event in events.into_iter() {
-            match event {
-                TrackEvent::New => {
-                    newed = true;
-                }
-                TrackEvent::APPEND(mut track) => {
-                    appended = true;
-                    // 替换背景图，增加图片，车牌图，属性
-                    data.notify.background = track.notify.background;
-                    data.notify.vehicles.append(&mut track.notify.vehicles);
-                    if track.notify.plate_info.is_some() {
-                        data.notify.plate_info = track.notify.plate_info;
-                    }
-                    if track.notify.props.is_some() {
-                        data.notify.props = track.notify.props;
-                    }
-                }
-                TrackEvent::DELAY(_) => {
-                    delayed = true;
+        let plate_bits = plate_info.bits.as_ref().unwrap();
+        for bit in plate_bits {
+            if let Some(bit_value) = bit.get(0) {
+                if bit_value.conf < conf {
+                    return false;
                 }
             }
         }
 
-        // 新建
-        if newed {
-            debug!("CarHandler, {} is newed", data.uuid);
+        true
+    }
+
+    // 比对车牌布控名单，命中的记录标记在track上，随track一起上传(上传失败会保存在spool中)
+    fn check_watchlist(&self, track: &mut NotifyCarQueueItem) {
+        let watchlist = match self.watchlist {
+            Some(ref v) => v,
+            None => {
+                return;
+            }
+        };
+
+        let plate = match track.notify.get_plate_tuple() {
+            (Some(v), _) => v,
+            _ => {
+                return;
+            }
+        };
+
+        let hits = watchlist.check(&plate, Local::now());
+        if hits.is_empty() {
+            return;
         }
 
-        // 更新
-        if !newed && appended {
-            debug!("CarHandler, {} is appended", data.uuid);
+        for hit in hits.iter() {
+            warn!(
+                "CarHandler, plate watchlist hit, track: {}, plate: {}, watch: {}, tag: {:?}, fuzzy: {}",
+                track.uuid, plate, hit.plate, hit.tag, hit.fuzzy
+            );
         }
+        track.plate_hits = Some(hits);
+    }
+}
+
+impl TrackHandler for CarHandler {
+    type Item = CarTrack;
+    type Output = NotifyCarQueueItem;
 
-        if appended || delayed {
-            data.ready_flag = true;
+    fn name(&self) -> &str {
+        "CarHandler"
+    }
+
+    fn track_id(&self, item: &CarTrack) -> String {
+        item.item.uuid.clone()
+    }
+
+    // 有车牌且conf大于某个值 + 车身图大于count
+    fn is_ready(&self, item: &CarTrack) -> bool {
+        let count = self.ctx.cfg.track.car.count as usize;
+
+        let check_plate = self.check_plate_conf(&item.item);
+        let img_count = item.item.notify.vehicles.len();
+
+        check_plate && (img_count >= count)
+    }
+
+    fn merge(&self, track: &mut CarTrack, item: CarTrack) {
+        // 替换背景图，增加图片，车牌图，属性
+        let mut item = item.item;
+        let notify = &mut track.item.notify;
+        notify.background = item.notify.background;
+        notify.vehicles.append(&mut item.notify.vehicles);
+        track.item.low_priority = track.item.low_priority && item.low_priority;
+        if let Some(plate_info) = item.notify.plate_info {
+            track.plates.push(plate_info.clone());
+            notify.plate_info = Some(plate_info);
         }
-        let ready_new = data.ready_flag;
-
-        if (newed && ready_old) || (!ready_old && ready_new) {
-            // 交给后续队列处理
-            if let Err(e) = self.put_to_next(&data).await {
-                error!("error, CarHandler put_to_next, {}, {:?}", data.uuid, e);
-            } else {
-                debug!("CarHandler put_to_next ok, {}", data.uuid);
-            }
+        if let Some(props) = item.notify.props {
+            track.props.push(props.clone());
+            notify.props = Some(props);
         }
     }
+
+    // 车牌，属性多帧投票，车身图按大小排序
+    fn finalize(&self, track: CarTrack) -> NotifyCarQueueItem {
+        let mut item = track.item;
+        if let Some(plate_info) = car_vote::vote_plate(&track.plates) {
+            item.notify.plate_info = Some(plate_info);
+        }
+        if let Some(props) = car_vote::vote_props(&track.props) {
+            item.notify.props = Some(props);
+        }
+        car_vote::sort_vehicles(&mut item.notify.vehicles);
+
+        self.check_watchlist(&mut item);
+        item
+    }
 }
@@ -0,0 +1,164 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use fy_base::api::bm_api::{
+    ApiCarPlateBit, ApiCarPlateInfo, ApiCarPlateType, ApiCarProps, ApiScoreValue, NotifyCar,
+};
+
+// 累加得票，保持首次出现的顺序，得票相同时结果稳定
+fn add_vote(votes: &mut Vec<(String, f64)>, value: &str, weight: f64) {
+    match votes.iter_mut().find(|x| x.0 == value) {
+        Some(v) => v.1 += weight,
+        None => votes.push((value.to_string(), weight)),
+    }
+}
+
+// 按得票从高到低排序，得票除以帧数作为置信度
+fn sort_votes(mut votes: Vec<(String, f64)>, frames: usize) -> Vec<(String, f64)> {
+    let frames = frames.max(1) as f64;
+    votes.sort_by(|a, b| b.1.total_cmp(&a.1));
+    votes.into_iter().map(|(v, w)| (v, w / frames)).collect()
+}
+
+// 单帧车牌的置信度: 每一位首选字符conf的平均值
+fn plate_weight(plate: &ApiCarPlateInfo) -> f64 {
+    let bits = match plate.bits {
+        Some(ref v) if !v.is_empty() => v,
+        _ => {
+            return 0.0;
+        }
+    };
+    let amount: f64 = bits.iter().filter_map(|x| x.first()).map(|x| x.conf).sum();
+    amount / bits.len() as f64
+}
+
+/// 车牌多帧投票: 每一位按字符累加各帧的conf，取得票最多的字符组成车牌号，
+/// 得票除以帧数作为该位的置信度。位数不同的(如新能源车牌)分组，取总权重最大的一组。
+/// 车牌图取与投票结果一致、置信度最高的一帧
+pub fn vote_plate(plates: &[ApiCarPlateInfo]) -> Option<ApiCarPlateInfo> {
+    let mut groups: HashMap<usize, Vec<&ApiCarPlateInfo>> = HashMap::new();
+    for plate in plates.iter() {
+        if let Some(len) = plate.bits.as_ref().map(|x| x.len()).filter(|x| *x > 0) {
+            groups.entry(len).or_default().push(plate);
+        }
+    }
+
+    // 没有bits的无法投票，用最后一帧
+    let group = match groups.into_values().max_by(|a, b| {
+        let wa: f64 = a.iter().map(|x| plate_weight(x)).sum();
+        let wb: f64 = b.iter().map(|x| plate_weight(x)).sum();
+        wa.total_cmp(&wb).then(a.len().cmp(&b.len()))
+    }) {
+        Some(v) => v,
+        None => {
+            return plates.last().cloned();
+        }
+    };
+
+    let len = group[0].bits.as_ref().map_or(0, |x| x.len());
+    let mut bits = Vec::with_capacity(len);
+    for i in 0..len {
+        let mut votes = vec![];
+        for plate in group.iter() {
+            if let Some(bit) = plate.bits.as_ref().and_then(|x| x.get(i)) {
+                bit.iter()
+                    .for_each(|x| add_vote(&mut votes, &x.value, x.conf));
+            }
+        }
+        let bit: Vec<ApiCarPlateBit> = sort_votes(votes, group.len())
+            .into_iter()
+            .map(|(value, conf)| ApiCarPlateBit { value, conf })
+            .collect();
+        bits.push(bit);
+    }
+    let text: String = bits
+        .iter()
+        .filter_map(|x| x.first())
+        .map(|x| x.value.as_str())
+        .collect();
+
+    let mut votes = vec![];
+    group
+        .iter()
+        .filter_map(|x| x.plate_type.as_ref())
+        .for_each(|x| add_vote(&mut votes, &x.value, x.conf));
+    let plate_type = sort_votes(votes, group.len())
+        .into_iter()
+        .next()
+        .map(|(value, conf)| ApiCarPlateType { value, conf });
+
+    let best = group
+        .iter()
+        .filter(|x| x.text.as_deref() == Some(text.as_str()))
+        .max_by(|a, b| plate_weight(a).total_cmp(&plate_weight(b)))
+        .or_else(|| {
+            group
+                .iter()
+                .max_by(|a, b| plate_weight(a).total_cmp(&plate_weight(b)))
+        })?;
+
+    let mut plate = (*best).clone();
+    plate.text = Some(text);
+    plate.bits = Some(bits);
+    if plate_type.is_some() {
+        plate.plate_type = plate_type;
+    }
+    Some(plate)
+}
+
+// 属性多帧投票: 按value累加各帧的score，得票除以帧数作为score
+fn vote_scores<F>(props: &[ApiCarProps], f: F) -> Option<Vec<ApiScoreValue>>
+where
+    F: Fn(&ApiCarProps) -> &Option<Vec<ApiScoreValue>>,
+{
+    let mut votes = vec![];
+    let mut frames = 0;
+    for list in props.iter().filter_map(|x| f(x).as_ref()) {
+        list.iter()
+            .for_each(|x| add_vote(&mut votes, &x.value, x.score));
+        frames += 1;
+    }
+    if frames == 0 {
+        return None;
+    }
+
+    let list = sort_votes(votes, frames)
+        .into_iter()
+        .map(|(value, score)| ApiScoreValue { value, score })
+        .collect();
+    Some(list)
+}
+
+/// 车辆属性(颜色，品牌，车系等)多帧投票，行驶方向取出现次数最多的
+pub fn vote_props(props: &[ApiCarProps]) -> Option<ApiCarProps> {
+    let last = props.last()?;
+
+    let mut counts: Vec<(i64, usize)> = vec![];
+    for v in props.iter().filter_map(|x| x.move_direction) {
+        match counts.iter_mut().find(|x| x.0 == v) {
+            Some(c) => c.1 += 1,
+            None => counts.push((v, 1)),
+        }
+    }
+    let move_direction = counts
+        .iter()
+        .max_by_key(|x| x.1)
+        .map(|x| x.0)
+        .or(last.move_direction);
+
+    Some(ApiCarProps {
+        move_direction,
+        brand: vote_scores(props, |x| &x.brand),
+        direction: vote_scores(props, |x| &x.direction),
+        color: vote_scores(props, |x| &x.color),
+        mid_type: vote_scores(props, |x| &x.mid_type),
+        series: vote_scores(props, |x| &x.series),
+        top_series: vote_scores(props, |x| &x.top_series),
+        top_type: vote_scores(props, |x| &x.top_type),
+    })
+}
+
+/// 车身图按rect面积从大到小排序，第一张为最佳车身图
+pub fn sort_vehicles(vehicles: &mut [NotifyCar]) {
+    vehicles.sort_by_key(|x| Reverse(x.rect.w * x.rect.h));
+}
@@ -0,0 +1,135 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+
+use fy_base::api::sync_api::PlateInfo;
+use fy_base::api::upload_api::PlateHit;
+use fy_base::util::json_file::JsonFile;
+
+//----------------------------------------------
+struct WatchEntry {
+    info: PlateInfo,
+    pattern: Vec<char>,
+}
+
+/// 车牌布控名单，由 sync_client 从 sync_server 同步到本地文件。
+/// 比对完全在盒子本地进行，不依赖上传通道
+pub struct PlateWatchlist {
+    file: JsonFile<Vec<WatchEntry>>,
+    fuzzy: bool,
+}
+
+impl PlateWatchlist {
+    pub fn new(file: &str, reload: Duration, fuzzy: bool) -> Self {
+        Self {
+            file: JsonFile::new("PlateWatchlist", file, reload, parse_entries),
+            fuzzy,
+        }
+    }
+
+    /// 比对车牌，返回命中的布控记录
+    pub fn check(&self, plate: &str, now: DateTime<Local>) -> Vec<PlateHit> {
+        let text: Vec<char> = plate.trim().to_uppercase().chars().collect();
+        if text.is_empty() {
+            return vec![];
+        }
+
+        let entries = self.file.get();
+        let mut hits = vec![];
+        for entry in entries.iter() {
+            if !is_valid(&entry.info, now) {
+                continue;
+            }
+
+            let fuzzy = if glob_match(&entry.pattern, &text, exact_eq) {
+                false
+            } else if self.fuzzy && glob_match(&entry.pattern, &text, confusable_eq) {
+                true
+            } else {
+                continue;
+            };
+
+            hits.push(PlateHit {
+                uuid: entry.info.uuid.clone(),
+                plate: entry.info.plate.clone(),
+                tag: entry.info.tag.clone(),
+                fuzzy,
+            });
+        }
+        hits
+    }
+}
+
+//----------------------------------------------
+fn parse_entries(content: &[u8]) -> serde_json::Result<Vec<WatchEntry>> {
+    let list: Vec<PlateInfo> = serde_json::from_slice(content)?;
+
+    Ok(list
+        .into_iter()
+        .map(|x| WatchEntry {
+            pattern: x.plate.trim().to_uppercase().chars().collect(),
+            info: x,
+        })
+        .filter(|x| !x.pattern.is_empty())
+        .collect())
+}
+
+fn is_valid(info: &PlateInfo, now: DateTime<Local>) -> bool {
+    if let Some(ref v) = info.valid_from {
+        if now < *v {
+            return false;
+        }
+    }
+    if let Some(ref v) = info.valid_to {
+        if now > *v {
+            return false;
+        }
+    }
+    true
+}
+
+fn exact_eq(a: char, b: char) -> bool {
+    a == b
+}
+
+// 识别时容易混淆的字符，归为同一类
+fn confusable(c: char) -> char {
+    match c {
+        'D' | 'O' | 'Q' => '0',
+        'B' => '8',
+        'I' => '1',
+        'S' => '5',
+        'Z' => '2',
+        _ => c,
+    }
+}
+
+fn confusable_eq(a: char, b: char) -> bool {
+    confusable(a) == confusable(b)
+}
+
+// 通配符比对: * 任意个字符，? 单个字符
+fn glob_match(pattern: &[char], text: &[char], eq: fn(char, char) -> bool) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // 最近一个 * 的位置，以及它匹配到的text位置
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && (pattern[p] == '?' || eq(pattern[p], text[t])) {
+            p += 1;
+            t += 1;
+        } else if let Some((sp, st)) = star {
+            // 回退，让 * 多匹配一个字符
+            p = sp + 1;
+            t = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|x| *x == '*')
+}
@@ -0,0 +1,99 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Local;
+use moka::future::Cache;
+use tracing::{debug, error, info};
+
+use crate::app_ctx::AppCtx;
+use crate::queue_item::AlarmQueue;
+use fy_base::api::upload_api::{NotifyAlarmItem, NotifyFaceQueueItem};
+
+/// 比对结果超过告警分数时，生成告警放入告警队列，
+/// 同一人员在 cooldown 内只告警一次，多个比对worker共用
+pub struct AlarmChecker {
+    ctx: Arc<AppCtx>,
+    queue: Arc<AlarmQueue>,
+    cooldown: Cache<String, ()>,
+}
+
+impl AlarmChecker {
+    pub fn new(ctx: Arc<AppCtx>, queue: Arc<AlarmQueue>) -> Self {
+        let cooldown = Cache::builder()
+            .max_capacity(10000)
+            .time_to_live(Duration::from_secs(ctx.cfg.track.face.alarm.cooldown))
+            .build();
+
+        Self {
+            ctx,
+            queue,
+            cooldown,
+        }
+    }
+
+    fn alert_score(&self, db_id: &str) -> i64 {
+        let cfg = &self.ctx.cfg.track.face.alarm;
+        match cfg.db_scores.get(db_id) {
+            Some(v) => *v,
+            None => cfg.default_score,
+        }
+    }
+
+    pub async fn check(&self, item: &NotifyFaceQueueItem) {
+        if !self.ctx.cfg.track.face.alarm.enable {
+            return;
+        }
+
+        let matches = match item.matches {
+            Some(ref v) => v,
+            None => {
+                return;
+            }
+        };
+
+        // faces已按得分排序，第一张为最佳人脸
+        let face = match item.notify.faces.first() {
+            Some(v) => v,
+            None => {
+                return;
+            }
+        };
+
+        for matched in matches.iter() {
+            if matched.score < self.alert_score(&matched.db_id) {
+                continue;
+            }
+
+            let key = format!("{}:{}", matched.db_id, matched.uuid);
+            if self.cooldown.get(&key).is_some() {
+                debug!(
+                    "AlarmChecker, {}, person: {} in cooldown, skip",
+                    item.uuid, key
+                );
+                continue;
+            }
+            info!(
+                "AlarmChecker, {}, person: {}, db: {}, score: {}",
+                item.uuid, matched.uuid, matched.db_id, matched.score
+            );
+
+            let alarm = NotifyAlarmItem {
+                uuid: format!("{}_{}", item.uuid, matched.uuid),
+                track_uuid: item.uuid.clone(),
+                source: item.notify.source.clone(),
+                matched: matched.clone(),
+                face_file: face.display_file.clone(),
+                face_image: None,
+                ts: Local::now(),
+                face_buf: face.display_buf.clone(),
+            };
+
+            // 放入队列成功后才开始冷却，丢弃的告警下次还能触发
+            if self.queue.push(alarm).is_err() {
+                error!("error, AlarmChecker, alarm queue full, drop: {}", item.uuid);
+                continue;
+            }
+            self.cooldown.insert(key, ()).await;
+        }
+    }
+}
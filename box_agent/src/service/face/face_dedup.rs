@@ -0,0 +1,234 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use tracing::{debug, error, info};
+
+use tokio::sync::watch::Receiver;
+use tokio::task::JoinHandle as TkJoinHandle;
+
+use fy_base::api::bm_api::{ApiFeatureQuality, RecognitionApi};
+use fy_base::api::upload_api::QI;
+use fy_base::util::metrics;
+use fy_base::util::service::Service;
+
+use crate::app_cfg::{AppCfgTrackFaceDedup, DedupMode};
+use crate::app_ctx::AppCtx;
+use crate::error::{AppError, AppResult};
+use crate::queue_item::UplinkQueue;
+
+// ------------------- structs -------------------
+struct RecentTrack {
+    uuid: String,
+    group: String,
+    ts: Instant,
+    feature: Bytes,
+}
+
+/// 跨摄像头去重，在比对之后、上传之前。
+/// 同一摄像头组内，window 内已上传过的相似人脸直接丢弃
+pub struct FaceDedupService {
+    ctx: Arc<AppCtx>,
+    queue: Arc<UplinkQueue>,
+    out: Arc<UplinkQueue>,
+    api: RecognitionApi,
+    recent: VecDeque<RecentTrack>,
+}
+
+// ------------------- impls -------------------
+impl FaceDedupService {
+    pub fn new(ctx: Arc<AppCtx>, queue: Arc<UplinkQueue>, out: Arc<UplinkQueue>) -> Self {
+        let api = RecognitionApi::new(ctx.cfg.api.recg_url.as_str(), &ctx.api_tls);
+
+        Self {
+            ctx,
+            queue,
+            out,
+            api,
+            recent: VecDeque::new(),
+        }
+    }
+
+    fn cfg(&self) -> &AppCfgTrackFaceDedup {
+        &self.ctx.cfg.track.face.dedup
+    }
+
+    // 不在任何组中的摄像头，自己为一组
+    fn group_of(&self, source: &str) -> String {
+        match self
+            .cfg()
+            .groups
+            .iter()
+            .position(|x| x.iter().any(|c| c == source))
+        {
+            Some(v) => format!("group_{}", v),
+            None => source.to_string(),
+        }
+    }
+
+    async fn process_item(&mut self, item: QI) {
+        let item = match item {
+            QI::FT(v) => v,
+            QI::CT(_) => {
+                self.put_to_next(item);
+                return;
+            }
+        };
+
+        // 清除超过时间窗口的
+        let window = Duration::from_secs(self.cfg().window);
+        while let Some(v) = self.recent.front() {
+            if v.ts.elapsed() < window {
+                break;
+            }
+            self.recent.pop_front();
+        }
+
+        // faces已按得分排序，取第一个特征值
+        let feature = match item.notify.faces.iter().find_map(|x| x.feature_buf.clone()) {
+            Some(v) => v,
+            None => {
+                self.put_to_next(QI::FT(item));
+                return;
+            }
+        };
+        let group = self.group_of(&item.notify.source);
+
+        let dup = match self.find_duplicate(&group, &feature).await {
+            Ok(v) => v,
+            Err(e) => {
+                // 比对失败，按不重复处理
+                metrics::inc_failed("face_dedup");
+                error!("error, FaceDedupService, {}, err: {:?}", item.uuid, e);
+                None
+            }
+        };
+
+        match dup {
+            Some((dup_of, score)) => {
+                debug!(
+                    "FaceDedupService, {} duplicate of {}, score: {}",
+                    item.uuid, dup_of, score
+                );
+                metrics::inc_dedup("suppress");
+            }
+            None => {
+                self.recent.push_back(RecentTrack {
+                    uuid: item.uuid.clone(),
+                    group,
+                    ts: Instant::now(),
+                    feature,
+                });
+                while self.recent.len() > self.cfg().max_recent as usize {
+                    self.recent.pop_front();
+                }
+                self.put_to_next(QI::FT(item));
+            }
+        }
+    }
+
+    // 返回最相似的 (uuid, score)，低于阈值返回 None
+    async fn find_duplicate(
+        &self,
+        group: &str,
+        feature: &Bytes,
+    ) -> AppResult<Option<(String, i64)>> {
+        let candidates: Vec<&RecentTrack> =
+            self.recent.iter().filter(|x| x.group == group).collect();
+        if candidates.is_empty() {
+            return Ok(None);
+        }
+
+        let scores = match self.cfg().mode {
+            DedupMode::Local => candidates
+                .iter()
+                .map(|x| local_score(feature, &x.feature))
+                .collect(),
+            DedupMode::Api => {
+                let to_api = |x: &Bytes| ApiFeatureQuality {
+                    feature: base64::encode(x),
+                    quality: 1.0,
+                };
+                let b = candidates
+                    .iter()
+                    .map(|x| vec![to_api(&x.feature)])
+                    .collect();
+                let res = self.api.compare_n(vec![to_api(feature)], b).await?;
+                match res.scores {
+                    Some(v) if res.code == 0 && v.len() == candidates.len() => v,
+                    _ => {
+                        return Err(AppError::new(&format!(
+                            "compare_n return code:{}, msg:{}",
+                            res.code, res.msg
+                        )));
+                    }
+                }
+            }
+        };
+
+        let best = candidates
+            .iter()
+            .zip(scores)
+            .max_by_key(|(_, score)| *score)
+            .map(|(x, score)| (x.uuid.clone(), score));
+        Ok(best.filter(|(_, score)| *score >= self.cfg().threshold))
+    }
+
+    // 放入下一个队列中
+    fn put_to_next(&self, item: QI) {
+        let uuid = item.get_id();
+        metrics::inc_processed("face_dedup");
+        if self.out.push(item).is_err() {
+            error!("error, FaceDedupService, out queue full, drop: {}", uuid);
+        }
+    }
+}
+
+// ------------------- impl Service -------------------
+impl Service for FaceDedupService {
+    fn run(self, rx: Receiver<i64>) -> TkJoinHandle<()> {
+        let mut svc = self;
+        let mut exit_rx = rx;
+
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = exit_rx.changed() => {
+                        info!("FaceDedupService recv exit");
+                        break;
+                    }
+                    item = svc.queue.pop() => {
+                        svc.process_item(item).await;
+                    }
+                }
+            }
+            info!("FaceDedupService exit.");
+        })
+    }
+}
+
+//-----------------------
+// 特征值为 f32 小端数组，余弦相似度换算成 0~100
+fn local_score(a: &[u8], b: &[u8]) -> i64 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0;
+    }
+
+    let mut dot = 0_f64;
+    let mut na = 0_f64;
+    let mut nb = 0_f64;
+    for (x, y) in a.chunks_exact(4).zip(b.chunks_exact(4)) {
+        let x = f32::from_le_bytes([x[0], x[1], x[2], x[3]]) as f64;
+        let y = f32::from_le_bytes([y[0], y[1], y[2], y[3]]) as f64;
+        dot += x * y;
+        na += x * x;
+        nb += y * y;
+    }
+    if na == 0.0 || nb == 0.0 {
+        return 0;
+    }
+
+    let cos = dot / (na.sqrt() * nb.sqrt());
+    (cos.max(0.0) * 100.0).round() as i64
+}
@@ -1,4 +1,3 @@
-use deadqueue::unlimited::Queue;
 use log::{debug, error, info};
 use moka::future::Cache;
 use std::sync::Arc;
@@ -8,12 +7,13 @@ use tokio::sync::watch::Receiver;
 use tokio::task::JoinHandle as TkJoinHandle;
 
 use fy_base::api::bm_api::{ApiFeatureQuality, RecognitionApi, SearchResPerson};
-use fy_base::util::utils;
 
 use crate::app_ctx::AppCtx;
 use crate::error::AppError;
-use crate::queue_item::FaceQueue;
+use crate::queue_item::{FaceQueue, UplinkQueue};
+use crate::service::face::alarm::AlarmChecker;
 use fy_base::api::upload_api::{MatchPerson, NotifyFaceQueueItem, QI};
+use fy_base::util::metrics;
 use fy_base::util::service::Service;
 
 pub struct FaceSearchService {
@@ -23,37 +23,51 @@ pub struct FaceSearchService {
 
     skip_search: bool,
 
-    out: Arc<Queue<QI>>,
+    out: Arc<UplinkQueue>,
     api: RecognitionApi,
 
-    last_cache_ts: Instant,
-    dbs_cache: Cache<String, Vec<String>>,
+    dbs_cache: DbsCache,
+    alarm: Arc<AlarmChecker>,
+}
+
+/// 各个worker共用的db列表缓存
+pub type DbsCache = Cache<String, Vec<String>>;
+
+pub fn new_dbs_cache(ttl_minute: u64) -> DbsCache {
+    Cache::builder()
+        .max_capacity(10)
+        .time_to_live(Duration::from_secs(ttl_minute * 60))
+        .build()
 }
 
 impl FaceSearchService {
-    pub fn new(num: i64, ctx: Arc<AppCtx>, queue: Arc<FaceQueue>, out: Arc<Queue<QI>>) -> Self {
-        let api = RecognitionApi::new(ctx.cfg.api.recg_url.as_str());
+    pub fn new(
+        num: i64,
+        ctx: Arc<AppCtx>,
+        queue: Arc<FaceQueue>,
+        out: Arc<UplinkQueue>,
+        dbs_cache: DbsCache,
+        alarm: Arc<AlarmChecker>,
+    ) -> Self {
+        let api = RecognitionApi::new(ctx.cfg.api.recg_url.as_str(), &ctx.api_tls);
         let skip_search = ctx.cfg.track.face.skip_search;
 
-        let last_cache_ts = Instant::now();
-        let dbs_cache = Cache::new(10);
-
         FaceSearchService {
             num,
             ctx,
             api,
-            last_cache_ts,
             queue,
             skip_search,
 
             out,
             dbs_cache,
+            alarm,
         }
     }
 
     async fn pop_batch(&mut self) -> Vec<NotifyFaceQueueItem> {
-        let max = 4;
-        utils::pop_queue_batch(&self.queue, max).await
+        let max = self.ctx.cfg.track.face.search_batch as usize;
+        self.queue.pop_batch(max).await
     }
 
     fn fill_with_matchinfo(
@@ -122,6 +136,16 @@ impl FaceSearchService {
         item.matches = Some(matches);
     }
 
+    fn put_to_next(&self, item: NotifyFaceQueueItem) {
+        let uuid = item.uuid.clone();
+        if self.out.push(QI::FT(Box::new(item))).is_err() {
+            error!(
+                "error, FaceSearchWorker[{}], out queue full, drop: {}",
+                self.num, uuid
+            );
+        }
+    }
+
     async fn get_dbs_from_api(&self) -> Result<Vec<String>, AppError> {
         let res = self.api.get_dbs().await?;
         if res.code != 0 {
@@ -139,28 +163,11 @@ impl FaceSearchService {
         Ok(ids)
     }
 
-    async fn get_dbs(&mut self) -> Vec<String> {
+    async fn get_dbs(&self) -> Vec<String> {
         // 有数据，且没有过期的，返回
-        // 查询，然后放入cache中
-
+        // 否则查询，然后放入cache中，多个worker同时过期时只查询一次
         let key = "dbs".to_string();
-        let dbs = self.dbs_cache.get(&key);
-        if let Some(v) = dbs {
-            if self
-                .last_cache_ts
-                .elapsed()
-                .lt(&Duration::from_secs(self.ctx.cfg.track.face.cache_ttl*60))
-            {
-                // 在ttl周期内，直接返回
-                return v;
-            }
-        }
-
-        let dbs = self.get_dbs_for_real().await;
-        let now = Instant::now();
-        self.last_cache_ts = now;
-        self.dbs_cache.insert(key, dbs.clone()).await;
-        dbs
+        self.dbs_cache.get_with(key, self.get_dbs_for_real()).await
     }
 
     async fn get_dbs_for_real(&self) -> Vec<String> {
@@ -175,7 +182,18 @@ impl FaceSearchService {
 
     /// api 比对搜索，(有特征值, 并且dbs不为空)
     /// 无论处理成功或失败，都提交到mpsc中
-    async fn process_batch(&mut self, mut items: Vec<NotifyFaceQueueItem>) {
+    async fn process_batch(&mut self, items: Vec<NotifyFaceQueueItem>) {
+        // 采集时间窗口外的低优先级track，不比对，直接上传
+        let (low, mut items): (Vec<_>, Vec<_>) = items.into_iter().partition(|x| x.low_priority);
+        for v in low {
+            debug!("FaceSearchWorker[{}], low priority: {}", self.num, v.uuid);
+            self.put_to_next(v);
+        }
+        if items.is_empty() {
+            return;
+        }
+
+        let start = Instant::now();
         let tops = vec![self.ctx.cfg.track.face.search_top as i64];
         let thresholds = vec![self.ctx.cfg.track.face.search_threshold as i64];
         let dbs = if self.skip_search {
@@ -185,6 +203,7 @@ impl FaceSearchService {
         };
 
         let mut persons = Vec::new();
+        let mut search_failed = false;
 
         debug!(
             "FaceSearchWorker[{}], process_batch: {}",
@@ -192,9 +211,14 @@ impl FaceSearchService {
             items.len()
         );
 
+        // faces 已按得分排序，只取前 best_shot.search 个特征值
+        let search_count = self.ctx.cfg.track.face.best_shot.search as usize;
         items.iter_mut().for_each(|x| {
             let mut feas = Vec::new();
             x.notify.faces.iter_mut().for_each(|f| {
+                if feas.len() >= search_count {
+                    return;
+                }
                 if let Some(ref feature) = f.feature_buf {
                     let fea = base64::encode(&feature);
                     feas.push(ApiFeatureQuality {
@@ -235,6 +259,7 @@ impl FaceSearchService {
                         // 填充 比对结果
                         self.fill_with_matchinfo(&mut items, &res.persons);
                     } else {
+                        search_failed = true;
                         error!(
                             "error, FaceSearchWorker[{}], search, code:{}, msg:{}",
                             self.num, res.code, res.msg
@@ -242,15 +267,32 @@ impl FaceSearchService {
                     }
                 }
                 Err(e) => {
+                    search_failed = true;
                     error!("error, FaceSearchWorker[{}], search, {:?}", self.num, e);
                 }
             }
         }
 
         // 放入 queue 中
+        let count = items.len();
         for v in items {
-            self.out.push(QI::FT(Box::new(v)));
+            if search_failed {
+                metrics::inc_failed("face_search");
+            } else {
+                metrics::inc_processed("face_search");
+            }
+
+            // 超过告警分数的，先单独告警
+            self.alarm.check(&v).await;
+            self.put_to_next(v);
         }
+
+        info!(
+            "FaceSearchWorker[{}], process_batch: {}, use: {} ms",
+            self.num,
+            count,
+            start.elapsed().as_millis()
+        );
     }
 }
 
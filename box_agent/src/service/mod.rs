@@ -1,4 +1,6 @@
+pub mod camera_schedule;
 pub mod car;
 pub mod face;
 pub mod signal_service;
+pub mod track_filter;
 pub mod web;
@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use tracing::debug;
+
+use fy_base::api::bm_api::{ApiRect, CarNotifyParams, FaceNotifyParams, NotifyFace};
+use fy_base::api::sync_api::{CameraFilter, TrackFilter};
+use fy_base::util::json_file::JsonFile;
+use fy_base::util::metrics;
+
+//----------------------------------------------
+/// 按摄像头过滤人脸/车辆，规则由 sync_client 从 sync_server 同步到本地文件。
+/// 没有规则的摄像头不过滤
+pub struct CameraFilters {
+    file: JsonFile<HashMap<String, CameraFilter>>,
+}
+
+impl CameraFilters {
+    pub fn new(file: &str, reload: Duration) -> Self {
+        Self {
+            file: JsonFile::new("CameraFilters", file, reload, parse_filters),
+        }
+    }
+
+    /// 去掉不满足规则的人脸，返回 false 表示整条通知都应丢弃
+    pub fn filter_face(&self, notify: &mut FaceNotifyParams) -> bool {
+        let rule = match self.get(&notify.source, |x| x.face.clone()) {
+            Some(v) => v,
+            None => {
+                return true;
+            }
+        };
+
+        if let Some(ref props) = notify.props {
+            if !check_direction(&rule, props.move_direction) {
+                metrics::inc_filtered("face", "direction");
+                return false;
+            }
+        }
+
+        // 没有人脸的通知(只更新背景等)不处理
+        if notify.faces.is_empty() {
+            return true;
+        }
+
+        notify.faces.retain(|x| match check_face(&rule, x) {
+            Ok(_) => true,
+            Err(reason) => {
+                metrics::inc_filtered("face", reason);
+                false
+            }
+        });
+        if notify.faces.is_empty() {
+            debug!("CameraFilters, drop face: {}, {}", notify.source, notify.id);
+            return false;
+        }
+        true
+    }
+
+    /// 去掉不满足规则的车辆图，返回 false 表示整条通知都应丢弃
+    pub fn filter_car(&self, notify: &mut CarNotifyParams) -> bool {
+        let rule = match self.get(&notify.source, |x| x.vehicle.clone()) {
+            Some(v) => v,
+            None => {
+                return true;
+            }
+        };
+
+        if let Some(direction) = notify.props.as_ref().and_then(|x| x.move_direction) {
+            if !check_direction(&rule, direction) {
+                metrics::inc_filtered("vehicle", "direction");
+                return false;
+            }
+        }
+
+        if notify.vehicles.is_empty() {
+            return true;
+        }
+
+        notify
+            .vehicles
+            .retain(|x| match check_rect(&rule, &x.rect) {
+                Ok(_) => true,
+                Err(reason) => {
+                    metrics::inc_filtered("vehicle", reason);
+                    false
+                }
+            });
+        if notify.vehicles.is_empty() {
+            debug!("CameraFilters, drop car: {}, {}", notify.source, notify.id);
+            return false;
+        }
+        true
+    }
+
+    fn get<F>(&self, source: &str, f: F) -> Option<TrackFilter>
+    where
+        F: Fn(&CameraFilter) -> Option<TrackFilter>,
+    {
+        self.file.get().get(source).and_then(f)
+    }
+}
+
+//----------------------------------------------
+fn parse_filters(content: &[u8]) -> serde_json::Result<HashMap<String, CameraFilter>> {
+    let list: Vec<CameraFilter> = serde_json::from_slice(content)?;
+    Ok(list.into_iter().map(|x| (x.uuid.clone(), x)).collect())
+}
+
+fn check_direction(rule: &TrackFilter, direction: i64) -> bool {
+    rule.directions.is_empty() || rule.directions.contains(&direction)
+}
+
+fn check_face(rule: &TrackFilter, face: &NotifyFace) -> Result<(), &'static str> {
+    check_rect(rule, &face.rect)?;
+
+    // angles: yaw, pitch, roll
+    if let Some(v) = rule.max_yaw {
+        if face.angles[0].abs() > v {
+            return Err("angle");
+        }
+    }
+    if let Some(v) = rule.max_pitch {
+        if face.angles[1].abs() > v {
+            return Err("angle");
+        }
+    }
+    Ok(())
+}
+
+fn check_rect(rule: &TrackFilter, rect: &ApiRect) -> Result<(), &'static str> {
+    if rect.w < rule.min_size || rect.h < rule.min_size {
+        return Err("size");
+    }
+
+    if !rule.rois.is_empty() {
+        let x = rect.x as f64 + rect.w as f64 / 2.0;
+        let y = rect.y as f64 + rect.h as f64 / 2.0;
+        if !rule.rois.iter().any(|roi| in_polygon(roi, x, y)) {
+            return Err("roi");
+        }
+    }
+    Ok(())
+}
+
+// 射线法，顶点少于3个的多边形视为无效
+fn in_polygon(polygon: &[[i64; 2]], x: f64, y: f64) -> bool {
+    if polygon.len() < 3 {
+        return false;
+    }
+
+    let mut inside = false;
+    let mut j = polygon.len() - 1;
+    for i in 0..polygon.len() {
+        let (xi, yi) = (polygon[i][0] as f64, polygon[i][1] as f64);
+        let (xj, yj) = (polygon[j][0] as f64, polygon[j][1] as f64);
+        if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
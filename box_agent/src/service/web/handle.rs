@@ -1,8 +1,8 @@
 use std::sync::Arc;
 
 use axum::body::BoxBody;
-use axum::extract::{ContentLengthLimit, Multipart};
-use axum::http::StatusCode;
+use axum::extract::ContentLengthLimit;
+use axum::http::{header, HeaderMap, StatusCode};
 use axum::response::{IntoResponse, Response};
 use axum::{body, Extension};
 use bytes::Bytes;
@@ -12,8 +12,9 @@ use tracing::{debug, error, info};
 
 use fy_base::api::bm_api::{CarNotifyParams, FaceNotifyParams};
 use fy_base::util::image as image_util;
-use fy_base::util::multipart_form::{parse_multi_form, MultipartFormValues};
+use fy_base::util::multipart_form::{parse_multi_form_bytes, MultipartFormValues};
 
+use crate::capture::archive::CaptureRecord;
 use crate::service::web::WebState;
 use fy_base::api::upload_api::{NotifyCarQueueItem, NotifyFaceQueueItem};
 
@@ -33,9 +34,21 @@ impl IntoResponse for UploadRes {
 //-----------------------------------
 pub async fn track_upload(
     Extension(web_state): Extension<Arc<WebState>>,
-    ContentLengthLimit(parts): ContentLengthLimit<Multipart, { 1024 * 1024 * 10 }>,
+    headers: HeaderMap,
+    ContentLengthLimit(body): ContentLengthLimit<Bytes, { 1024 * 1024 * 10 }>,
 ) -> UploadRes {
-    let part_values = match parse_multi_form(parts).await {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|x| x.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+
+    // 抓包模式，解析前保存原始请求用于回放，格式变化导致解析失败的也能复现
+    if let Some(ref queue) = web_state.capture_queue {
+        let _ = queue.push(CaptureRecord::from_raw(&content_type, body.clone()));
+    }
+
+    let part_values = match parse_multi_form_bytes(&content_type, body).await {
         Ok(v) => v,
         Err(e) => {
             error!("error, track_upload, parse_multi_form, err: {:?}", e);
@@ -122,12 +135,18 @@ async fn handle_face(data: Arc<WebState>, values: MultipartFormValues) -> Upload
                 debug!("{}, has no feature", item.id);
             }
         }
-        data.face_queue.push(NotifyFaceQueueItem {
+        let uuid = item.id.clone();
+        let rst = data.face_queue.push(NotifyFaceQueueItem {
             uuid: item.id.clone(),
             notify: item,
             ts: now,
             matches: None,
+            low_priority: false,
         });
+        if rst.is_err() {
+            error!("error, face queue full, reject: {}", uuid);
+            return UploadRes("error, face queue full".into());
+        }
         debug!("track_upload, end push face");
         UploadRes("ok".into())
     } else {
@@ -207,11 +226,18 @@ async fn handle_car(data: Arc<WebState>, values: MultipartFormValues) -> UploadR
         }
 
         debug!("track_upload, will push car");
-        data.car_queue.push(NotifyCarQueueItem {
+        let uuid = item.id.clone();
+        let rst = data.car_queue.push(NotifyCarQueueItem {
             uuid: item.id.clone(),
             notify: item,
             ts: now,
+            plate_hits: None,
+            low_priority: false,
         });
+        if rst.is_err() {
+            error!("error, car queue full, reject: {}", uuid);
+            return UploadRes("error, car queue full".into());
+        }
         debug!("track_upload, end push car");
         UploadRes("ok".into())
     } else {
@@ -0,0 +1,23 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::response::Response;
+use axum::Extension;
+
+use crate::service::web::WebState;
+use fy_base::util::health;
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+//-----------------------------------
+/// 分析/识别接口都能访问才算 ready
+pub async fn readyz(Extension(web_state): Extension<Arc<WebState>>) -> Response {
+    let ctx = &web_state.ctx;
+
+    tokio::join!(
+        health::probe("analysis_api", PROBE_TIMEOUT, ctx.ana_api.get_sources()),
+        health::probe("recognition_api", PROBE_TIMEOUT, ctx.recg_api.get_dbs()),
+    );
+
+    health::readyz_response()
+}
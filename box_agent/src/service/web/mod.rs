@@ -1,6 +1,7 @@
 pub mod handle;
+pub mod health;
 
-use axum::routing::post;
+use axum::routing::{get, post};
 use axum::{middleware, Router, Server};
 use hyper::server::conn::AddrIncoming;
 use hyper::server::Builder;
@@ -8,9 +9,12 @@ use std::net::{AddrParseError, SocketAddr};
 use std::sync::Arc;
 
 use crate::app_ctx::AppCtx;
-use crate::queue_item::{CarQueue, FaceQueue};
+use crate::queue_item::{CaptureQueue, CarQueue, FaceQueue};
 use crate::service::web::handle::track_upload;
-use fy_base::util::{axum_log::time_use, service::Service};
+use crate::service::web::health::readyz;
+use fy_base::util::{
+    axum_log::time_use, health::healthz_handler, metrics::metrics_handler, service::Service,
+};
 use tokio::sync::watch::Receiver;
 use tokio::task::JoinHandle;
 use tower::limit::GlobalConcurrencyLimitLayer;
@@ -23,20 +27,28 @@ pub struct WebState {
     pub ctx: Arc<AppCtx>,
     pub face_queue: Arc<FaceQueue>,
     pub car_queue: Arc<CarQueue>,
+    pub capture_queue: Option<Arc<CaptureQueue>>,
 }
 
 pub struct WebService {
     pub ctx: Arc<AppCtx>,
     pub face_queue: Arc<FaceQueue>,
     pub car_queue: Arc<CarQueue>,
+    pub capture_queue: Option<Arc<CaptureQueue>>,
 }
 
 impl WebService {
-    pub fn new(ctx: Arc<AppCtx>, face_queue: Arc<FaceQueue>, car_queue: Arc<CarQueue>) -> Self {
+    pub fn new(
+        ctx: Arc<AppCtx>,
+        face_queue: Arc<FaceQueue>,
+        car_queue: Arc<CarQueue>,
+        capture_queue: Option<Arc<CaptureQueue>>,
+    ) -> Self {
         Self {
             ctx,
             face_queue,
             car_queue,
+            capture_queue,
         }
     }
 
@@ -47,10 +59,14 @@ impl WebService {
             ctx: self.ctx.clone(),
             face_queue: self.face_queue.clone(),
             car_queue: self.car_queue.clone(),
+            capture_queue: self.capture_queue.clone(),
         });
 
         Router::new()
             .route("/trackupload", post(track_upload))
+            .route("/metrics", get(metrics_handler))
+            .route("/healthz", get(healthz_handler))
+            .route("/readyz", get(readyz))
             .layer(
                 ServiceBuilder::new()
                     // 限制请求的并发数量
@@ -0,0 +1,103 @@
+use chrono::Local;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, error, info};
+
+use tokio::sync::watch::Receiver;
+use tokio::task::JoinHandle as TkJoinHandle;
+
+use crate::app_ctx::AppCtx;
+use crate::queue_item::AlarmQueue;
+use crate::uplink::uplink_api::UplinkApi;
+use fy_base::api::upload_api::NotifyAlarmItem;
+
+use fy_base::util::metrics;
+use fy_base::util::service::Service;
+
+/// 告警上报，与track上传分开，不用排在积压的track后面
+pub struct AlarmService {
+    ctx: Arc<AppCtx>,
+    queue: Arc<AlarmQueue>,
+    api: UplinkApi,
+}
+
+impl AlarmService {
+    pub fn new(ctx: Arc<AppCtx>, queue: Arc<AlarmQueue>) -> Self {
+        AlarmService {
+            api: UplinkApi::new(0, ctx.signer.clone(), &ctx.tls),
+            ctx,
+            queue,
+        }
+    }
+
+    // 失败后按指数退避一直重试，直到成功、超过 alarm_expire 或收到退出信号
+    async fn process_item(&self, item: NotifyAlarmItem, exit_rx: &mut Receiver<i64>) {
+        let url = self.ctx.cfg.up_link.alarm_server.as_str();
+        let expire = chrono::Duration::seconds(self.ctx.cfg.up_link.alarm_expire as i64);
+        let retry_max = self.ctx.cfg.up_link.spool.retry_max;
+        let mut wait = 1;
+
+        loop {
+            match self.api.upload_alarm(url, &item).await {
+                Ok(_) => {
+                    metrics::inc_processed("alarm");
+                    debug!("AlarmService, upload alarm: {}, ok", item.uuid);
+                    return;
+                }
+                Err(e) if e.is_permanent() => {
+                    metrics::inc_failed("alarm");
+                    error!(
+                        "error, AlarmService, alarm: {} rejected, err:{:?}",
+                        item.uuid, e
+                    );
+                    return;
+                }
+                Err(e) => {
+                    error!(
+                        "error, AlarmService, upload alarm: {} fail, retry after {}s, err:{:?}",
+                        item.uuid, wait, e
+                    );
+                }
+            }
+
+            if Local::now() - item.ts > expire {
+                metrics::inc_failed("alarm");
+                error!("error, AlarmService, alarm: {} expired, drop", item.uuid);
+                return;
+            }
+
+            tokio::select! {
+                _ = exit_rx.changed() => {
+                    return;
+                }
+                _ = tokio::time::sleep(Duration::from_secs(wait)) => {}
+            }
+            wait = (wait * 2).min(retry_max);
+        }
+    }
+}
+
+impl Service for AlarmService {
+    fn run(self, rx: Receiver<i64>) -> TkJoinHandle<()> {
+        let svc = self;
+        let mut exit_rx = rx;
+
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = exit_rx.changed() => {
+                        info!("AlarmService recv exit");
+                        break;
+                    }
+                    item = svc.queue.pop() => {
+                        svc.process_item(item, &mut exit_rx).await;
+                        if svc.ctx.is_exit() {
+                            break;
+                        }
+                    }
+                }
+            }
+            info!("AlarmService exit");
+        })
+    }
+}
@@ -1,2 +1,5 @@
+pub mod alarm;
+pub mod privacy;
+pub mod spool;
 pub mod uplink_api;
 pub mod upload;
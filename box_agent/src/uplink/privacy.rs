@@ -0,0 +1,214 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tracing::{debug, error, info, warn};
+
+use tokio::sync::watch::Receiver;
+use tokio::task::JoinHandle as TkJoinHandle;
+
+use fy_base::api::bm_api::{NotifyBackground, RecognitionApi};
+use fy_base::api::upload_api::QI;
+use fy_base::util::image::{self, ImageRect};
+use fy_base::util::metrics;
+use fy_base::util::service::Service;
+
+use crate::app_cfg::{AppCfgTrackPrivacyRule, PrivacyBackground, PrivacyRedact};
+use crate::app_ctx::AppCtx;
+use crate::error::{AppError, AppResult};
+use crate::queue_item::UplinkQueue;
+
+/// 上传前对背景图做隐私处理: 目标以外区域或其他人脸做模糊/马赛克，缩小或去掉背景图。
+/// 处理失败时不上传背景图
+pub struct PrivacyService {
+    ctx: Arc<AppCtx>,
+    queue: Arc<UplinkQueue>,
+    out: Arc<UplinkQueue>,
+    api: RecognitionApi,
+}
+
+impl PrivacyService {
+    pub fn new(ctx: Arc<AppCtx>, queue: Arc<UplinkQueue>, out: Arc<UplinkQueue>) -> Self {
+        let api = RecognitionApi::new(ctx.cfg.api.recg_url.as_str(), &ctx.api_tls);
+
+        Self {
+            ctx,
+            queue,
+            out,
+            api,
+        }
+    }
+
+    fn rule(&self, source: &str) -> &AppCfgTrackPrivacyRule {
+        let cfg = &self.ctx.cfg.track.privacy;
+        cfg.cameras.get(source).unwrap_or(&cfg.default)
+    }
+
+    async fn process_item(&self, mut item: QI) {
+        let uuid = item.get_id();
+        let (source, background, is_face) = match item {
+            QI::FT(ref mut v) => (&v.notify.source, &mut v.notify.background, true),
+            QI::CT(ref mut v) => (&v.notify.source, &mut v.notify.background, false),
+        };
+
+        let rule = self.rule(source);
+        if let Err(e) = self.process_background(rule, background, is_face).await {
+            metrics::inc_failed("privacy");
+            error!(
+                "error, PrivacyService, {}, drop background, err: {:?}",
+                uuid, e
+            );
+            drop_background(background);
+        }
+
+        metrics::inc_processed("privacy");
+        if self.out.push(item).is_err() {
+            error!("error, PrivacyService, out queue full, drop: {}", uuid);
+        }
+    }
+
+    async fn process_background(
+        &self,
+        rule: &AppCfgTrackPrivacyRule,
+        bg: &mut NotifyBackground,
+        is_face: bool,
+    ) -> AppResult<()> {
+        if bg.image_buf.is_empty() {
+            return Ok(());
+        }
+
+        if rule.background == PrivacyBackground::Drop {
+            drop_background(bg);
+            return Ok(());
+        }
+        if rule.redact == PrivacyRedact::None && rule.background == PrivacyBackground::Keep {
+            return Ok(());
+        }
+
+        // background.rect 为目标在背景图上的位置
+        let subject = ImageRect {
+            x: bg.rect.x,
+            y: bg.rect.y,
+            w: bg.rect.w,
+            h: bg.rect.h,
+        }
+        .expand(rule.margin);
+
+        // 检测失败时，退化为目标以外全部脱敏
+        let faces = if rule.redact == PrivacyRedact::Faces {
+            match self.detect_faces(&bg.image_buf).await {
+                Ok(v) => Some(v),
+                Err(e) => {
+                    warn!(
+                        "warn, PrivacyService, detect, fallback to outside_subject, err: {:?}",
+                        e
+                    );
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let content = bg.image_buf.clone();
+        let redact = rule.redact;
+        let method = rule.method;
+        let strength = rule.strength;
+        let max_width = match rule.background {
+            PrivacyBackground::Downscale => rule.max_width,
+            _ => 0,
+        };
+        let quality = rule.quality;
+
+        let (buf, width, height) = tokio::task::spawn_blocking(move || {
+            let mut img = image::decode_rgb(&content).map_err(AppError::from_debug)?;
+            match (redact, faces) {
+                (PrivacyRedact::None, _) => {}
+                (PrivacyRedact::Faces, Some(faces)) => {
+                    // 人脸track的目标人脸保留
+                    let regions: Vec<ImageRect> = faces
+                        .into_iter()
+                        .filter(|x| !(is_face && contains_center(&subject, x)))
+                        .collect();
+                    image::redact_regions(&mut img, &regions, method, strength);
+                }
+                _ => {
+                    image::redact_outside(&mut img, &[subject], method, strength);
+                }
+            }
+
+            let img = image::downscale(img, max_width);
+            let (width, height) = img.dimensions();
+            let buf = image::encode_jpeg(&img, quality).map_err(AppError::from_debug)?;
+            Ok::<_, AppError>((buf, width, height))
+        })
+        .await??;
+
+        debug!(
+            "PrivacyService, background {}x{} -> {}x{}",
+            bg.width, bg.height, width, height
+        );
+        bg.image_buf = buf;
+        bg.resized(width as i64, height as i64);
+        Ok(())
+    }
+
+    async fn detect_faces(&self, content: &Bytes) -> AppResult<Vec<ImageRect>> {
+        let res = self
+            .api
+            .detect(base64::encode(content), false, false)
+            .await?;
+        if res.code != 0 {
+            return Err(AppError::new(&format!(
+                "detect return code:{}, msg:{}",
+                res.code, res.msg
+            )));
+        }
+
+        Ok(res
+            .faces
+            .unwrap_or_default()
+            .into_iter()
+            .map(|x| ImageRect {
+                x: x.api_box.x,
+                y: x.api_box.y,
+                w: x.api_box.w,
+                h: x.api_box.h,
+            })
+            .collect())
+    }
+}
+
+impl Service for PrivacyService {
+    fn run(self, rx: Receiver<i64>) -> TkJoinHandle<()> {
+        let svc = self;
+        let mut exit_rx = rx;
+
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = exit_rx.changed() => {
+                        info!("PrivacyService recv exit");
+                        break;
+                    }
+                    item = svc.queue.pop() => {
+                        svc.process_item(item).await;
+                    }
+                }
+            }
+            info!("PrivacyService exit.");
+        })
+    }
+}
+
+//-----------------------
+// 去掉背景图，上传时不再带背景图字段
+fn drop_background(bg: &mut NotifyBackground) {
+    bg.image_buf = Bytes::new();
+    bg.image_file = "".to_string();
+}
+
+fn contains_center(outer: &ImageRect, inner: &ImageRect) -> bool {
+    let x = inner.x + inner.w / 2;
+    let y = inner.y + inner.h / 2;
+    x >= outer.x && x < outer.x + outer.w && y >= outer.y && y < outer.y + outer.h
+}
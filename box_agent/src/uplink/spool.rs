@@ -0,0 +1,300 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use bytes::Bytes;
+use tracing::{debug, error, info, warn};
+
+use crate::error::{AppError, AppResult};
+use fy_base::api::upload_api::QI;
+use fy_base::api::upload_codec::{decode_car, decode_face, encode_car, encode_face};
+use fy_base::util::metrics;
+
+const EXT_FACE: &str = "ft";
+const EXT_CAR: &str = "ct";
+const EXT_TMP: &str = "tmp";
+
+// 服务端拒收的track移到该子目录，只保留最新的 MAX_DEAD 个，便于排查
+const DEAD_DIR: &str = "dead";
+const MAX_DEAD: usize = 100;
+
+//----------------------------------------------
+#[derive(Debug)]
+pub struct SpoolEntry {
+    pub seq: u64,
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+/// 本地持久化的上传队列，每个track一个文件，文件名为递增序号。
+/// 超出 max_size 后，从最老的开始删除
+pub struct Spool {
+    dir: PathBuf,
+    max_size: u64,
+    total_size: u64,
+    next_seq: u64,
+    entries: VecDeque<SpoolEntry>,
+}
+
+impl Spool {
+    pub fn open<P: AsRef<Path>>(dir: P, max_size: u64) -> AppResult<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let mut entries = Vec::new();
+        for dir_entry in fs::read_dir(&dir)? {
+            let dir_entry = dir_entry?;
+            let path = dir_entry.path();
+            if !path.is_file() {
+                continue;
+            }
+
+            let ext = path
+                .extension()
+                .and_then(|x| x.to_str())
+                .unwrap_or_default();
+
+            // 写了一半的临时文件，直接删除
+            if ext == EXT_TMP {
+                warn!("Spool, remove partial file: {:?}", path);
+                let _ = fs::remove_file(&path);
+                continue;
+            }
+            if ext != EXT_FACE && ext != EXT_CAR {
+                continue;
+            }
+
+            let seq = match file_seq(&path) {
+                Some(v) => v,
+                None => {
+                    continue;
+                }
+            };
+
+            let size = dir_entry.metadata()?.len();
+            entries.push(SpoolEntry { seq, path, size });
+        }
+        entries.sort_by_key(|x| x.seq);
+
+        let total_size = entries.iter().map(|x| x.size).sum();
+        // 队列为空时序号也要接着 dead 目录中的往下排，
+        // 否则移到 dead 目录时会覆盖同名的旧文件，清理时也会先删掉新的
+        let next_seq = entries
+            .last()
+            .map(|x| x.seq)
+            .max(max_dead_seq(&dir.join(DEAD_DIR)))
+            .map_or(0, |x| x + 1);
+
+        info!(
+            "Spool, open: {:?}, entries: {}, size: {}",
+            dir,
+            entries.len(),
+            total_size
+        );
+        metrics::set_queue_depth("spool", entries.len());
+
+        Ok(Self {
+            dir,
+            max_size,
+            total_size,
+            next_seq,
+            entries: entries.into(),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.total_size
+    }
+
+    /// 写入一个track，先写临时文件再rename，避免断电留下不完整的文件
+    pub fn push(&mut self, item: &QI) -> AppResult<()> {
+        let (ext, buf) = match item {
+            QI::FT(v) => (EXT_FACE, encode_face(v)?),
+            QI::CT(v) => (EXT_CAR, encode_car(v)?),
+        };
+
+        let seq = self.next_seq;
+        let file_name = format!("{:016}", seq);
+        let tmp_path = self.dir.join(&file_name).with_extension(EXT_TMP);
+        let path = self.dir.join(&file_name).with_extension(ext);
+
+        {
+            let mut file = fs::File::create(&tmp_path)?;
+            file.write_all(&buf)?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp_path, &path)?;
+        self.next_seq += 1;
+
+        let size = buf.len() as u64;
+        self.total_size += size;
+        self.entries.push_back(SpoolEntry { seq, path, size });
+
+        self.evict();
+        metrics::set_queue_depth("spool", self.entries.len());
+        Ok(())
+    }
+
+    /// 读取最老的一个track
+    pub fn load_front(&self) -> Option<AppResult<QI>> {
+        self.entries.front().map(|x| load_entry(&x.path))
+    }
+
+    /// 读取最老的 n 个track，返回 (seq, track)
+    pub fn load_batch(&self, n: usize) -> Vec<(u64, AppResult<QI>)> {
+        self.entries
+            .iter()
+            .take(n)
+            .map(|x| (x.seq, load_entry(&x.path)))
+            .collect()
+    }
+
+    /// 删除指定的track，批量上传时只删除成功的
+    pub fn remove(&mut self, seq: u64) {
+        if let Some(pos) = self.entries.iter().position(|x| x.seq == seq) {
+            if let Some(entry) = self.entries.remove(pos) {
+                self.remove_entry(entry);
+            }
+        }
+    }
+
+    /// 删除最老的一个track
+    pub fn pop_front(&mut self) {
+        if let Some(entry) = self.entries.pop_front() {
+            self.remove_entry(entry);
+        }
+    }
+
+    /// 服务端拒收的track，移出队列放到 dead 目录，不再重传
+    pub fn dead_letter(&mut self, seq: u64) {
+        let pos = match self.entries.iter().position(|x| x.seq == seq) {
+            Some(v) => v,
+            None => {
+                return;
+            }
+        };
+        let entry = match self.entries.remove(pos) {
+            Some(v) => v,
+            None => {
+                return;
+            }
+        };
+        metrics::inc_failed("spool_dead");
+
+        let dead_dir = self.dir.join(DEAD_DIR);
+        let rst = fs::create_dir_all(&dead_dir).and_then(|_| {
+            let file_name = entry.path.file_name().unwrap_or_default();
+            let dead_path = dead_dir.join(file_name);
+            // 不覆盖已有的 dead letter
+            if dead_path.exists() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    "dead letter exists",
+                ));
+            }
+            fs::rename(&entry.path, dead_path)
+        });
+        match rst {
+            Ok(_) => {
+                warn!("Spool, dead letter: {:?}", entry.path);
+                self.total_size -= entry.size;
+                metrics::set_queue_depth("spool", self.entries.len());
+                clean_dead_dir(&dead_dir);
+            }
+            Err(e) => {
+                error!("error, Spool, dead letter: {:?}, err: {:?}", entry.path, e);
+                self.remove_entry(entry);
+            }
+        }
+    }
+
+    /// 最老的一个track的序号
+    pub fn front_seq(&self) -> Option<u64> {
+        self.entries.front().map(|x| x.seq)
+    }
+
+    fn remove_entry(&mut self, entry: SpoolEntry) {
+        self.total_size -= entry.size;
+        metrics::set_queue_depth("spool", self.entries.len());
+        if let Err(e) = fs::remove_file(&entry.path) {
+            error!("error, Spool, remove: {:?}, err: {:?}", entry.path, e);
+        }
+    }
+
+    fn evict(&mut self) {
+        // 至少保留最新的一个
+        while self.total_size > self.max_size && self.entries.len() > 1 {
+            if let Some(entry) = self.entries.front() {
+                warn!(
+                    "Spool, size: {} > {}, evict: {:?}",
+                    self.total_size, self.max_size, entry.path
+                );
+            }
+            self.pop_front();
+        }
+    }
+}
+
+//----------------------------------------------
+// 文件名为序号
+fn file_seq(path: &Path) -> Option<u64> {
+    path.file_stem()
+        .and_then(|x| x.to_str())
+        .and_then(|x| x.parse::<u64>().ok())
+}
+
+fn max_dead_seq(dir: &Path) -> Option<u64> {
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|x| x.ok())
+        .filter_map(|x| file_seq(&x.path()))
+        .max()
+}
+
+// 文件名为递增序号，按文件名删除最老的
+fn clean_dead_dir(dir: &Path) {
+    let mut files: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(v) => v.filter_map(|x| x.ok()).map(|x| x.path()).collect(),
+        Err(e) => {
+            error!("error, Spool, read dead dir: {:?}, err: {:?}", dir, e);
+            return;
+        }
+    };
+    if files.len() <= MAX_DEAD {
+        return;
+    }
+
+    files.sort();
+    for path in files.iter().take(files.len() - MAX_DEAD) {
+        if let Err(e) = fs::remove_file(path) {
+            error!("error, Spool, remove: {:?}, err: {:?}", path, e);
+        }
+    }
+}
+
+fn load_entry(path: &Path) -> AppResult<QI> {
+    let buf = Bytes::from(fs::read(path)?);
+    let ext = path
+        .extension()
+        .and_then(|x| x.to_str())
+        .unwrap_or_default();
+
+    let item = match ext {
+        EXT_FACE => QI::FT(Box::new(decode_face(buf)?)),
+        EXT_CAR => QI::CT(Box::new(decode_car(buf)?)),
+        _ => {
+            return Err(AppError::new(&format!("unknown spool file: {:?}", path)));
+        }
+    };
+    debug!("Spool, load: {:?}, {}", path, item.get_id());
+    Ok(item)
+}
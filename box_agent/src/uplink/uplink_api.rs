@@ -1,16 +1,23 @@
 use std::{fmt::Debug, time::Duration};
 
 use axum::http::StatusCode;
-use bytes::Buf;
-use reqwest::multipart::{Form, Part};
+use bytes::{Buf, Bytes};
+use serde::de::DeserializeOwned;
 use std::fmt::{Display, Formatter};
-use std::ops::Deref;
 
 use reqwest::header;
-use reqwest::Client;
+use reqwest::{Client, Response};
 use tracing::debug;
 
-use fy_base::api::upload_api::{NotifyCarQueueItem, NotifyFaceQueueItem, ResponseData};
+use fy_base::api::upload_api::{
+    BatchItemResult, BatchResponseData, NotifyAlarmItem, NotifyCarQueueItem, NotifyFaceQueueItem,
+    ResponseData, QI, RES_STATUS_REJECTED,
+};
+use fy_base::api::upload_codec;
+use fy_base::util::image;
+use fy_base::util::multipart_form::MultipartFormBuilder;
+use fy_base::util::sign::Signer;
+use fy_base::util::tls::ClientTls;
 
 #[derive(Debug)]
 pub enum ApiError {
@@ -18,6 +25,8 @@ pub enum ApiError {
     IoErr(String),
     NetErr(String),
     HttpErr(u16),
+    AuthErr(String),  // 签名校验失败
+    Rejected(String), // 服务端拒收，重传也不会成功
     BizErr(String),
 }
 
@@ -36,6 +45,12 @@ impl Display for ApiError {
             ApiError::HttpErr(code) => {
                 write!(f, "HttpErr:(http code:{})", code)
             }
+            ApiError::AuthErr(v) => {
+                write!(f, "AuthErr:{}", v)
+            }
+            ApiError::Rejected(v) => {
+                write!(f, "Rejected:{}", v)
+            }
             ApiError::BizErr(v) => {
                 write!(f, "BizErr:{}", v)
             }
@@ -43,6 +58,17 @@ impl Display for ApiError {
     }
 }
 
+impl ApiError {
+    /// 重传也不会成功的错误: 服务端拒收，或 4xx(超时、限流除外)
+    pub fn is_permanent(&self) -> bool {
+        match self {
+            ApiError::Rejected(_) => true,
+            ApiError::HttpErr(code) => (400..500).contains(code) && *code != 408 && *code != 429,
+            _ => false,
+        }
+    }
+}
+
 impl std::error::Error for ApiError {}
 
 impl From<serde_json::Error> for ApiError {
@@ -67,10 +93,19 @@ pub type ApiResult<T> = std::result::Result<T, ApiError>;
 
 pub struct UplinkApi {
     pub client: Client,
+    // 配置了签名时，请求带上签名头
+    pub signer: Option<Signer>,
 }
 
 impl Default for UplinkApi {
     fn default() -> Self {
+        Self::new(0, None, &ClientTls::default())
+    }
+}
+
+impl UplinkApi {
+    /// pool_idle 为每个host保留的空闲连接数，0 每次请求都新建连接
+    pub fn new(pool_idle: usize, signer: Option<Signer>, tls: &ClientTls) -> Self {
         let mut headers = header::HeaderMap::new();
         headers.insert(
             header::USER_AGENT,
@@ -86,116 +121,154 @@ impl Default for UplinkApi {
             header::HeaderValue::from_static("keep-alive"),
         );
 
-        let client = Client::builder()
+        let client = tls
+            .builder()
             .connect_timeout(Duration::from_secs(10))
-            .pool_max_idle_per_host(0)
+            .pool_max_idle_per_host(pool_idle)
             .default_headers(headers)
-            .danger_accept_invalid_certs(true)
             .build()
             .unwrap();
 
-        Self { client }
+        Self { client, signer }
+    }
+
+    // 批量上传，返回每个track的结果
+    pub async fn upload_batch(
+        &self,
+        url: &str,
+        items: &[QI],
+        gzip: bool,
+    ) -> ApiResult<Vec<BatchItemResult>> {
+        let mut body = upload_codec::encode_batch(items).map_err(ApiError::IoErr)?;
+        let mut headers = header::HeaderMap::new();
+        if gzip {
+            body = upload_codec::gzip(&body).map_err(ApiError::IoErr)?;
+            headers.insert(
+                header::CONTENT_ENCODING,
+                header::HeaderValue::from_static("gzip"),
+            );
+        }
+        debug!("--> batch, tracks: {}, size: {}", items.len(), body.len());
+
+        let res = self
+            .post(url, "application/octet-stream", headers, body)
+            .await?;
+        let res_data: BatchResponseData = parse_response(res).await?;
+
+        if res_data.status != 0 {
+            return Err(ApiError::BizErr(format!(
+                "return status:{}, message:{:?}",
+                res_data.status, res_data.message
+            )));
+        }
+
+        Ok(res_data.results)
     }
-}
 
-impl UplinkApi {
     // 上传人脸
     pub async fn upload_face(&self, url: &str, item: NotifyFaceQueueItem) -> ApiResult<()> {
         let json_content = serde_json::to_string(&item)?;
         debug!("--> face json: {}", json_content);
 
-        let mut form = Form::new().text("json", json_content);
-        form = form.text("type", "facetrack");
+        let mut form = MultipartFormBuilder::default()
+            .text("json", &json_content)
+            .text("type", "facetrack");
 
-        // 背景图
-        let bg_part = fill_part(
-            &item.notify.background.image_buf,
-            item.notify.background.image_file.clone(),
-            "image/jpeg",
-        )?;
-        form = form.part(item.notify.background.image_file.clone(), bg_part);
+        // 背景图，隐私模式下可能没有
+        let bg = &item.notify.background;
+        if !bg.image_file.is_empty() {
+            let mime = image::guess_content_type(&bg.image_buf);
+            form = form.file(&bg.image_file, &bg.image_file, mime, &bg.image_buf);
+        }
 
         // 人脸图/特征
         for face in item.notify.faces.iter() {
             // 小图
-            let part = fill_part(&face.aligned_buf, face.aligned_file.clone(), "image/bmp")?;
-            form = form.part(face.aligned_file.clone(), part);
+            let mime = image::guess_content_type(&face.aligned_buf);
+            form = form.file(
+                &face.aligned_file,
+                &face.aligned_file,
+                mime,
+                &face.aligned_buf,
+            );
 
             // 显示图
-            let part = fill_part(&face.display_buf, face.display_file.clone(), "image/bmp")?;
-            form = form.part(face.display_file.clone(), part);
+            let mime = image::guess_content_type(&face.display_buf);
+            form = form.file(
+                &face.display_file,
+                &face.display_file,
+                mime,
+                &face.display_buf,
+            );
 
             // 特征值，如果有
             if let Some(ref fea) = face.feature_file {
                 if let Some(ref fea_buf) = face.feature_buf {
-                    let part = fill_part(fea_buf.deref(), fea.clone(), "application/octet-stream")?;
-                    form = form.part(fea.clone(), part);
+                    form = form.file(fea, fea, "application/octet-stream", fea_buf);
                 }
             }
         }
 
-        let res = self.client.post(url).multipart(form).send().await?;
-
-        if res.status() != StatusCode::OK {
-            return Err(ApiError::HttpErr(res.status().as_u16()));
-        }
-
-        let body = res.bytes().await?;
-        let res_data: ResponseData = serde_json::from_reader(body.reader())?;
-
-        if res_data.status != 0 {
-            return Err(ApiError::BizErr(format!(
-                "return status:{}, message:{:?}",
-                res_data.status, res_data.message
-            )));
-        }
-
-        Ok(())
+        self.post_form(url, form).await
     }
 
     // 上传车辆
     pub async fn upload_car(&self, url: &str, item: NotifyCarQueueItem) -> ApiResult<()> {
         let json_content = serde_json::to_string(&item)?;
-        let mut form = Form::new().text("json", json_content);
-
-        form = form.text("type", "vehicletrack");
-
-        // 背景图
-        let bg_part = fill_part(
-            &item.notify.background.image_buf,
-            item.notify.background.image_file.clone(),
-            "image/jpeg",
-        )?;
-        form = form.part(item.notify.background.image_file.clone(), bg_part);
+        let mut form = MultipartFormBuilder::default()
+            .text("json", &json_content)
+            .text("type", "vehicletrack");
+
+        // 背景图，隐私模式下可能没有
+        let bg = &item.notify.background;
+        if !bg.image_file.is_empty() {
+            let mime = image::guess_content_type(&bg.image_buf);
+            form = form.file(&bg.image_file, &bg.image_file, mime, &bg.image_buf);
+        }
 
         // 车身图
         for car in item.notify.vehicles.iter() {
-            let part = fill_part(&car.img_buf, car.image_file.clone(), "image/jpeg")?;
-            form = form.part(car.image_file.clone(), part);
+            form = form.file(&car.image_file, &car.image_file, "image/jpeg", &car.img_buf);
         }
 
         // 车牌图
         if let Some(ref plate) = item.notify.plate_info {
             if let Some(ref img_fn) = plate.image_file {
-                let part = fill_part(&plate.img_buf, img_fn.clone(), "image/bmp")?;
-                form = form.part(img_fn.clone(), part);
+                form = form.file(img_fn, img_fn, "image/bmp", &plate.img_buf);
             }
 
             if let Some(ref img_fn) = plate.binary_file {
-                let part = fill_part(&plate.binary_buf, img_fn.clone(), "image/bmp")?;
-                form = form.part(img_fn.clone(), part);
+                form = form.file(img_fn, img_fn, "image/bmp", &plate.binary_buf);
             }
         }
 
-        let res = self.client.post(url).multipart(form).send().await?;
+        self.post_form(url, form).await
+    }
 
-        if res.status() != StatusCode::OK {
-            return Err(ApiError::HttpErr(res.status().as_u16()));
-        }
+    // 上传告警
+    pub async fn upload_alarm(&self, url: &str, item: &NotifyAlarmItem) -> ApiResult<()> {
+        let json_content = serde_json::to_string(item)?;
 
-        let body = res.bytes().await?;
-        let res_data: ResponseData = serde_json::from_reader(body.reader())?;
+        // 人脸图，引擎输出的原图，一般为 bmp
+        let mime = image::guess_content_type(&item.face_buf);
+        let form = MultipartFormBuilder::default()
+            .text("json", &json_content)
+            .text("type", "alarm")
+            .file(&item.face_file, &item.face_file, mime, &item.face_buf);
+
+        self.post_form(url, form).await
+    }
 
+    async fn post_form(&self, url: &str, form: MultipartFormBuilder) -> ApiResult<()> {
+        let (content_type, body) = form.finish();
+        let res = self
+            .post(url, &content_type, header::HeaderMap::new(), body)
+            .await?;
+        let res_data: ResponseData = parse_response(res).await?;
+
+        if res_data.status == RES_STATUS_REJECTED {
+            return Err(ApiError::Rejected(format!("{:?}", res_data.message)));
+        }
         if res_data.status != 0 {
             return Err(ApiError::BizErr(format!(
                 "return status:{}, message:{:?}",
@@ -205,13 +278,42 @@ impl UplinkApi {
 
         Ok(())
     }
+
+    // 配置了签名时，对完整的body签名
+    async fn post(
+        &self,
+        url: &str,
+        content_type: &str,
+        mut headers: header::HeaderMap,
+        body: Bytes,
+    ) -> ApiResult<Response> {
+        if let Ok(v) = header::HeaderValue::from_str(content_type) {
+            headers.insert(header::CONTENT_TYPE, v);
+        }
+        if let Some(ref signer) = self.signer {
+            headers.extend(signer.headers("POST", url, &body));
+        }
+
+        let res = self
+            .client
+            .post(url)
+            .headers(headers)
+            .body(body)
+            .send()
+            .await?;
+        Ok(res)
+    }
 }
 
-fn fill_part(buf: &[u8], meta_fn: String, mime_str: &str) -> ApiResult<Part> {
-    let buf = Vec::from(buf);
-    let part = Part::bytes(buf).file_name(meta_fn).mime_str(mime_str);
-    match part {
-        Ok(v) => Ok(v),
-        Err(e) => Err(ApiError::IoErr(format!("{:?}", e))),
+async fn parse_response<T: DeserializeOwned>(res: Response) -> ApiResult<T> {
+    if res.status() == StatusCode::UNAUTHORIZED {
+        let body = res.text().await.unwrap_or_default();
+        return Err(ApiError::AuthErr(body));
+    }
+    if res.status() != StatusCode::OK {
+        return Err(ApiError::HttpErr(res.status().as_u16()));
     }
+
+    let body = res.bytes().await?;
+    Ok(serde_json::from_reader(body.reader())?)
 }
@@ -1,56 +1,313 @@
-use deadqueue::unlimited::Queue;
+use std::collections::HashSet;
 use std::sync::Arc;
+use std::time::Duration;
 use tracing::{debug, error, info};
 
 use tokio::sync::watch::Receiver;
 use tokio::task::JoinHandle as TkJoinHandle;
+use tokio::time::Instant;
 
 use crate::app_ctx::AppCtx;
-use crate::uplink::uplink_api::UplinkApi;
-use fy_base::api::upload_api::QI;
+use crate::error::AppResult;
+use crate::queue_item::UplinkQueue;
+use crate::uplink::spool::Spool;
+use crate::uplink::uplink_api::{ApiResult, UplinkApi};
+use bytes::Bytes;
+use fy_base::api::upload_api::{QI, RES_STATUS_REJECTED};
 
+use fy_base::util::image::{self, ImageProfile};
+use fy_base::util::metrics;
 use fy_base::util::service::Service;
 
 pub struct UplinkService {
     ctx: Arc<AppCtx>,
-    queue: Arc<Queue<QI>>,
+    queue: Arc<UplinkQueue>,
     api: UplinkApi,
+    spool: Spool,
+    wait: u64,                 // retry interval, second
+    retry_at: Option<Instant>, // 上传失败后，下次重试的时间
+    flush_at: Option<Instant>, // 批量模式下，不够一批时最晚的上传时间
 }
 
 impl UplinkService {
-    pub fn new(ctx: Arc<AppCtx>, queue: Arc<Queue<QI>>) -> Self {
-        UplinkService {
+    pub fn new(ctx: Arc<AppCtx>, queue: Arc<UplinkQueue>) -> AppResult<Self> {
+        let spool_cfg = &ctx.cfg.up_link.spool;
+        let spool = Spool::open(spool_cfg.dir.as_str(), spool_cfg.max_size * 1024 * 1024)?;
+
+        // 上次未上传完的，启动后马上重传
+        let retry_at = if spool.is_empty() {
+            None
+        } else {
+            Some(Instant::now())
+        };
+
+        Ok(UplinkService {
+            api: UplinkApi::new(
+                ctx.cfg.up_link.pool_idle as usize,
+                ctx.signer.clone(),
+                &ctx.tls,
+            ),
             ctx,
             queue,
-            api: Default::default(),
+            spool,
+            wait: 2,
+            retry_at,
+            flush_at: None,
+        })
+    }
+
+    fn increate_wait(&mut self) -> u64 {
+        let max = self.ctx.cfg.up_link.spool.retry_max;
+        self.wait *= 2;
+        if self.wait > max {
+            self.wait = max;
+        }
+        self.wait
+    }
+
+    fn reset_wait(&mut self) -> u64 {
+        self.wait = 2;
+        self.wait
+    }
+
+    // 按 up_link.image 配置转码，落盘和上传的都是转码后的图片
+    async fn transcode_item(&self, mut item: QI) -> QI {
+        let cfg = &self.ctx.cfg.up_link.image;
+        let uuid = item.get_id();
+
+        let background = match item {
+            QI::FT(ref mut v) => {
+                for face in v.notify.faces.iter_mut() {
+                    let profile = cfg.profile(&cfg.aligned);
+                    transcode_image(&uuid, &mut face.aligned_buf, profile).await;
+                    let profile = cfg.profile(&cfg.display);
+                    transcode_image(&uuid, &mut face.display_buf, profile).await;
+                }
+                &mut v.notify.background
+            }
+            QI::CT(ref mut v) => &mut v.notify.background,
+        };
+
+        let profile = cfg.profile(&cfg.background);
+        if let Some((width, height)) =
+            transcode_image(&uuid, &mut background.image_buf, profile).await
+        {
+            background.resized(width as i64, height as i64);
+        }
+        item
+    }
+
+    // 先落盘，落盘失败则直接上传
+    async fn save_item(&mut self, item: QI) {
+        let item = self.transcode_item(item).await;
+        match self.spool.push(&item) {
+            Ok(_) => {
+                debug!(
+                    "UplinkService, spool: {}, entries: {}, size: {}",
+                    item.get_id(),
+                    self.spool.len(),
+                    self.spool.total_size()
+                );
+            }
+            Err(e) => {
+                let uuid = item.get_id();
+                error!("error, UplinkService, spool: {}, err:{:?}", uuid, e);
+                if let Err(e) = self.upload_item(item).await {
+                    error!("error, UplinkService, upload: {} fail, err:{:?}", uuid, e);
+                }
+            }
         }
     }
-    async fn process_item(&mut self, item: QI) {
+
+    async fn upload_item(&self, item: QI) -> ApiResult<()> {
         let upload_url = self.ctx.cfg.up_link.server.as_str();
         let uuid = item.get_id();
 
-        match item {
-            QI::FT(ft) => {
-                let rst = self.api.upload_face(upload_url, *ft).await;
+        let rst = match item {
+            QI::FT(ft) => self.api.upload_face(upload_url, *ft).await,
+            QI::CT(ct) => self.api.upload_car(upload_url, *ct).await,
+        };
+        match rst {
+            Ok(_) => {
+                metrics::inc_processed("uplink");
+                debug!("UplinkService, upload: {}, ok", uuid);
+            }
+            Err(_) => {
+                metrics::inc_failed("uplink");
+            }
+        }
+        rst
+    }
+
+    // 批量模式下，攒够 size 个或等待 linger 后再上传
+    async fn flush_or_wait(&mut self) {
+        let batch = &self.ctx.cfg.up_link.batch;
+        if batch.enable && self.spool.len() < batch.size as usize {
+            if self.flush_at.is_none() {
+                self.flush_at = Some(Instant::now() + Duration::from_millis(batch.linger));
+            }
+            return;
+        }
+        self.upload_spool().await;
+    }
+
+    // 按先后顺序上传spool中的track，失败则等待重试
+    async fn upload_spool(&mut self) {
+        self.flush_at = None;
+        if self.ctx.cfg.up_link.batch.enable {
+            self.upload_spool_batch().await;
+            return;
+        }
+
+        while let Some(rst) = self.spool.load_front() {
+            if self.ctx.is_exit() {
+                return;
+            }
+
+            let item = match rst {
+                Ok(v) => v,
+                Err(e) => {
+                    error!("error, UplinkService, load spool, err:{:?}", e);
+                    self.spool.pop_front();
+                    continue;
+                }
+            };
+
+            let uuid = item.get_id();
+            match self.upload_item(item).await {
+                Ok(_) => {
+                    self.spool.pop_front();
+                    self.reset_wait();
+                }
+                Err(e) if e.is_permanent() => {
+                    // 服务端拒收的，重传也不会成功，不能堵住后面的track
+                    error!(
+                        "error, UplinkService, upload: {} rejected, err:{:?}",
+                        uuid, e
+                    );
+                    if let Some(seq) = self.spool.front_seq() {
+                        self.spool.dead_letter(seq);
+                    }
+                }
+                Err(e) => {
+                    let wait = self.increate_wait();
+                    error!(
+                        "error, UplinkService, upload: {} fail, pending: {}, retry after {}s, err:{:?}",
+                        uuid,
+                        self.spool.len(),
+                        wait,
+                        e
+                    );
+                    self.retry_at = Some(Instant::now() + Duration::from_secs(wait));
+                    return;
+                }
+            }
+
+            // 上传期间新到的track，也先落盘
+            while let Some(item) = self.queue.try_pop() {
+                self.save_item(item).await;
+            }
+        }
+    }
+}
+
+impl UplinkService {
+    // 每次从spool取最老的 size 个一起上传，只删除成功的，有失败的则等待重试。
+    // 服务端拒收的移到 dead 目录，不参与重试
+    async fn upload_spool_batch(&mut self) {
+        let batch = &self.ctx.cfg.up_link.batch;
+        let (url, size, gzip) = (batch.server.clone(), batch.size as usize, batch.gzip);
+
+        while !self.spool.is_empty() {
+            if self.ctx.is_exit() {
+                return;
+            }
+
+            let mut seqs = vec![];
+            let mut items = vec![];
+            for (seq, rst) in self.spool.load_batch(size) {
                 match rst {
-                    Ok(_) => {
-                        debug!("UplinkService, upload ft: {}, ok", uuid);
+                    Ok(v) => {
+                        seqs.push(seq);
+                        items.push(v);
                     }
                     Err(e) => {
-                        error!("error, UplinkService, upload: {} fail, err:{:?}", uuid, e);
+                        error!("error, UplinkService, load spool, err:{:?}", e);
+                        self.spool.remove(seq);
                     }
                 }
             }
-            QI::CT(ct) => {
-                let rst = self.api.upload_car(upload_url, *ct).await;
-                match rst {
-                    Ok(_) => {
-                        debug!("UplinkService, upload car: {}, ok", uuid);
+            if items.is_empty() {
+                continue;
+            }
+
+            let failed = match self.api.upload_batch(&url, &items, gzip).await {
+                Ok(results) => {
+                    let ok: HashSet<&str> = results
+                        .iter()
+                        .filter(|x| x.status == 0)
+                        .map(|x| x.uuid.as_str())
+                        .collect();
+                    let rejected: HashSet<&str> = results
+                        .iter()
+                        .filter(|x| x.status == RES_STATUS_REJECTED)
+                        .map(|x| x.uuid.as_str())
+                        .collect();
+                    for x in results.iter().filter(|x| x.status != 0) {
+                        error!(
+                            "error, UplinkService, batch upload: {}, status: {}, message: {:?}",
+                            x.uuid, x.status, x.message
+                        );
                     }
-                    Err(e) => {
-                        error!("error, UplinkService, upload: {} fail, err:{:?}", uuid, e);
+
+                    let mut failed = 0;
+                    for (seq, item) in seqs.iter().zip(items.iter()) {
+                        let uuid = item.get_id();
+                        if ok.contains(uuid.as_str()) {
+                            metrics::inc_processed("uplink");
+                            self.spool.remove(*seq);
+                        } else if rejected.contains(uuid.as_str()) {
+                            metrics::inc_failed("uplink");
+                            self.spool.dead_letter(*seq);
+                        } else {
+                            metrics::inc_failed("uplink");
+                            failed += 1;
+                        }
                     }
+                    debug!(
+                        "UplinkService, batch upload: {}, failed: {}",
+                        items.len(),
+                        failed
+                    );
+                    failed
                 }
+                Err(e) => {
+                    metrics::inc_failed("uplink");
+                    error!(
+                        "error, UplinkService, batch upload: {} fail, err:{:?}",
+                        items.len(),
+                        e
+                    );
+                    items.len()
+                }
+            };
+
+            if failed > 0 {
+                let wait = self.increate_wait();
+                error!(
+                    "error, UplinkService, batch upload, failed: {}, pending: {}, retry after {}s",
+                    failed,
+                    self.spool.len(),
+                    wait
+                );
+                self.retry_at = Some(Instant::now() + Duration::from_secs(wait));
+                return;
+            }
+            self.reset_wait();
+
+            // 上传期间新到的track，也先落盘
+            while let Some(item) = self.queue.try_pop() {
+                self.save_item(item).await;
             }
         }
     }
@@ -63,13 +320,25 @@ impl Service for UplinkService {
 
         tokio::spawn(async move {
             loop {
+                let retry_at = svc.retry_at.unwrap_or_else(Instant::now);
+                let flush_at = svc.flush_at.unwrap_or_else(Instant::now);
                 tokio::select! {
                     _ = exit_rx.changed() => {
                         info!("UplinkService recv exit");
                         break;
                     }
                     items = svc.queue.pop() => {
-                        svc.process_item(items).await;
+                        svc.save_item(items).await;
+                        if svc.retry_at.is_none() {
+                            svc.flush_or_wait().await;
+                        }
+                    }
+                    _ = tokio::time::sleep_until(retry_at), if svc.retry_at.is_some() => {
+                        svc.retry_at = None;
+                        svc.upload_spool().await;
+                    }
+                    _ = tokio::time::sleep_until(flush_at), if svc.flush_at.is_some() => {
+                        svc.upload_spool().await;
                     }
                 }
             }
@@ -77,3 +346,35 @@ impl Service for UplinkService {
         })
     }
 }
+
+//-----------------------
+// 转码一张图片，返回转码后的宽高，没有配置或失败时保留原图
+async fn transcode_image(
+    uuid: &str,
+    buf: &mut Bytes,
+    profile: Option<&ImageProfile>,
+) -> Option<(u32, u32)> {
+    let profile = profile?.clone();
+    if buf.is_empty() {
+        return None;
+    }
+
+    let content = buf.clone();
+    let rst = tokio::task::spawn_blocking(move || image::transcode(content, &profile)).await;
+    match rst {
+        Ok(Ok(v)) => {
+            *buf = v.buf;
+            Some((v.width, v.height))
+        }
+        Ok(Err(e)) => {
+            metrics::inc_failed("transcode");
+            error!("error, UplinkService, transcode: {}, err:{:?}", uuid, e);
+            None
+        }
+        Err(e) => {
+            metrics::inc_failed("transcode");
+            error!("error, UplinkService, transcode: {}, err:{:?}", uuid, e);
+            None
+        }
+    }
+}
@@ -8,6 +8,7 @@ CREATE TABLE base_box(
     has_db SMALLINT NOT NULL   COMMENT '是否保存db;0: 不需同步db 1:需要同步db' ,
     has_camera SMALLINT NOT NULL   COMMENT '是否有摄像头;0: 不需要同步摄像头 1:需要同步摄像头' ,
     latest_online DATETIME(3)    COMMENT '最新上线时间' ,
+    secret VARCHAR(64)    COMMENT '请求签名密钥;为空时盒子请求不能通过校验' ,
     create_time DATETIME(3) NOT NULL   COMMENT '录入时间' ,
     modify_time DATETIME(3) NOT NULL   COMMENT '修改时间' ,
     PRIMARY KEY (id)
@@ -172,3 +173,36 @@ CREATE TABLE base_fea_map(
 
 CREATE INDEX idx_fea_map_uuid ON base_fea_map(uuid);
 
+DROP TABLE IF EXISTS base_plate;
+CREATE TABLE base_plate(
+    id BIGINT NOT NULL AUTO_INCREMENT  COMMENT 'id' ,
+    uuid VARCHAR(50) NOT NULL   COMMENT 'uuid' ,
+    plate VARCHAR(20) NOT NULL   COMMENT '车牌号;支持通配符 * ?' ,
+    tag VARCHAR(50)    COMMENT '布控类别;如 被盗,通缉' ,
+    memo VARCHAR(255)    COMMENT '备注' ,
+    valid_from DATETIME(3)    COMMENT '生效时间;为空表示不限' ,
+    valid_to DATETIME(3)    COMMENT '失效时间;为空表示不限' ,
+    create_time DATETIME(3) NOT NULL   COMMENT '创建时间' ,
+    modify_time DATETIME(3) NOT NULL   COMMENT '更新时间' ,
+    PRIMARY KEY (id)
+)  COMMENT = '车牌布控';
+
+
+CREATE UNIQUE INDEX idx_plate_uuid ON base_plate(uuid);
+CREATE INDEX idx_plate_modify ON base_plate(modify_time);
+
+DROP TABLE IF EXISTS base_plate_del;
+CREATE TABLE base_plate_del(
+    id BIGINT NOT NULL AUTO_INCREMENT  COMMENT 'id' ,
+    origin_id INT NOT NULL   COMMENT '原来表中的id' ,
+    uuid VARCHAR(50) NOT NULL   COMMENT 'uuid' ,
+    plate VARCHAR(20) NOT NULL   COMMENT '车牌号' ,
+    create_time DATETIME(3) NOT NULL   COMMENT '创建时间' ,
+    modify_time DATETIME(3) NOT NULL   COMMENT '更新时间;删除时间' ,
+    PRIMARY KEY (id)
+)  COMMENT = '车牌布控删除表';
+
+
+CREATE INDEX idx_plate_del_uuid ON base_plate_del(uuid);
+CREATE INDEX idx_plate_del_modify ON base_plate_del(modify_time);
+
@@ -40,4 +40,17 @@ BEGIN
   DELETE FROM base_fea_map WHERE uuid =  OLD.uuid;
 
 
-END;
\ No newline at end of file
+END;
+
+-- trigger for base_plate
+DROP TRIGGER IF EXISTS trg_base_plate_del;
+CREATE TRIGGER trg_base_plate_del
+AFTER DELETE
+   ON base_plate FOR EACH ROW
+
+BEGIN
+
+  INSERT INTO base_plate_del(origin_id,uuid,plate,create_time,modify_time)
+  VALUES(OLD.id, OLD.uuid,OLD.plate,OLD.create_time,now(3));
+
+END;
@@ -0,0 +1,2 @@
+-- 已有数据库升级: base_box 增加请求签名密钥
+ALTER TABLE base_box ADD COLUMN secret VARCHAR(64)    COMMENT '请求签名密钥;为空时盒子请求不能通过校验' AFTER latest_online;
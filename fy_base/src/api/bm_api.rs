@@ -1,7 +1,7 @@
 use std::{
     fmt::Debug,
     sync::atomic::{AtomicU64, Ordering},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use bytes::{buf::Buf, Bytes};
@@ -13,6 +13,9 @@ use reqwest::{Client, Error as Reqwest_Error};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::{Error as Serde_Error, Value};
 
+use crate::util::metrics;
+use crate::util::tls::ClientTls;
+
 #[derive(Debug)]
 pub enum ApiError {
     NetErr(String),
@@ -334,7 +337,7 @@ pub struct CreateSourceReq {
     pub config: CreateSourceReqConfig,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct CreateSourceRes {
     pub code: i64,
     pub msg: String,
@@ -348,7 +351,7 @@ pub struct UpdateSourceReq {
     pub config: CreateSourceReqConfig,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct UpdateSourceRes {
     pub code: i64,
     pub msg: String,
@@ -359,7 +362,7 @@ pub struct DeleteSourceReq {
     pub id: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct DeleteSourceRes {
     pub code: i64,
     pub msg: String,
@@ -373,7 +376,7 @@ pub struct GetSourcesResSource {
     pub id: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct GetSourcesRes {
     pub code: i64,
     pub msg: String,
@@ -385,7 +388,7 @@ pub struct GetSourceInfoReq {
     pub id: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct GetSourceInfoRes {
     pub code: i64,
     pub msg: String,
@@ -431,7 +434,7 @@ pub struct DetectReq {
     pub retattr: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct DetectRes {
     pub code: i64,
     pub msg: String,
@@ -445,7 +448,7 @@ pub struct GetFeaturesReq {
     pub retattr: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct GetFeaturesRes {
     pub code: i64,
     pub msg: String,
@@ -460,7 +463,7 @@ pub struct CreateDbReq {
     pub volume: i64,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct CreateDbRes {
     pub code: i64,
     pub msg: String,
@@ -473,7 +476,7 @@ pub struct GetDbInfoReq {
     pub id: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct GetDbInfoRes {
     pub code: i64,
     pub msg: String,
@@ -487,7 +490,7 @@ pub struct DeleteDbReq {
     pub id: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct DeleteDbRes {
     pub code: i64,
     pub msg: String,
@@ -498,7 +501,7 @@ pub struct FlushDbReq {
     pub id: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct FlushDbRes {
     pub code: i64,
     pub msg: String,
@@ -507,7 +510,7 @@ pub struct FlushDbRes {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct GetDbsReq {}
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct GetDbsRes {
     pub code: i64,
     pub msg: String,
@@ -533,7 +536,7 @@ pub struct CreatePersonsResPerson {
     pub faces: Vec<i64>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct CreatePersonsRes {
     pub code: i64,
     pub msg: String,
@@ -546,7 +549,7 @@ pub struct DeletePersonReq {
     pub id: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct DeletePersonRes {
     pub code: i64,
     pub msg: String,
@@ -559,7 +562,7 @@ pub struct GetDbPersonsReq {
     pub count: i64,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct GetDbPersonsRes {
     pub code: i64,
     pub msg: String,
@@ -573,7 +576,7 @@ pub struct MovePersonsReq {
     pub ids: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct MovePersonsRes {
     pub code: i64,
     pub msg: String,
@@ -592,7 +595,7 @@ pub struct GetPersonInfoResFace {
     pub quality: f64,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct GetPersonInfoRes {
     pub code: i64,
     pub msg: String,
@@ -608,7 +611,7 @@ pub struct AddFeaturesToPersonReq {
     pub features: Vec<ApiFeatureQuality>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct AddFeaturesToPersonRes {
     pub code: i64,
     pub msg: String,
@@ -623,7 +626,7 @@ pub struct AddAggregateFeatureToPersonReq {
     pub feature: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct AddAggregateFeatureToPersonRes {
     pub code: i64,
     pub msg: String,
@@ -636,7 +639,7 @@ pub struct DeletePersonFeatureReq {
     pub id: i64,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct DeletePersonFeatureRes {
     pub code: i64,
     pub msg: String,
@@ -657,7 +660,7 @@ pub struct SearchResPerson {
     pub db: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct SearchRes {
     pub code: i64,
     pub msg: String,
@@ -670,7 +673,7 @@ pub struct CompareReq {
     pub b: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct CompareRes {
     pub code: i64,
     pub msg: String,
@@ -683,7 +686,7 @@ pub struct CompareNReq {
     pub b: Vec<Vec<ApiFeatureQuality>>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct CompareNRes {
     pub code: i64,
     pub msg: String,
@@ -696,6 +699,18 @@ async fn do_post<R: Serialize, T: DeserializeOwned>(
     url: &str,
     method: &str,
     req: &R,
+) -> ApiResult<T> {
+    let begin_ts = Instant::now();
+    let res = do_post_call(client, url, method, req).await;
+    metrics::observe_bm_api(method, res.is_ok(), begin_ts);
+    res
+}
+
+async fn do_post_call<R: Serialize, T: DeserializeOwned>(
+    client: &Client,
+    url: &str,
+    method: &str,
+    req: &R,
 ) -> ApiResult<T> {
     let req = serde_json::to_value(req)?;
 
@@ -749,7 +764,7 @@ pub struct AnalysisApi {
 }
 
 impl AnalysisApi {
-    pub fn new(url: &str) -> Self {
+    pub fn new(url: &str, tls: &ClientTls) -> Self {
         let mut headers = header::HeaderMap::new();
         headers.insert(
             header::USER_AGENT,
@@ -765,11 +780,11 @@ impl AnalysisApi {
             header::HeaderValue::from_static("keep-alive"),
         );
 
-        let client = Client::builder()
+        let client = tls
+            .builder()
             .connect_timeout(Duration::from_secs(3))
             .pool_max_idle_per_host(0)
             .default_headers(headers)
-            .danger_accept_invalid_certs(true)
             .build()
             .unwrap();
 
@@ -837,7 +852,7 @@ pub struct RecognitionApi {
 }
 
 impl RecognitionApi {
-    pub fn new(url: &str) -> Self {
+    pub fn new(url: &str, tls: &ClientTls) -> Self {
         let mut headers = header::HeaderMap::new();
         headers.insert(
             header::USER_AGENT,
@@ -853,11 +868,11 @@ impl RecognitionApi {
             header::HeaderValue::from_static("keep-alive"),
         );
 
-        let client = Client::builder()
+        let client = tls
+            .builder()
             .connect_timeout(Duration::from_secs(3))
             .pool_max_idle_per_host(0)
             .default_headers(headers)
-            .danger_accept_invalid_certs(true)
             .build()
             .unwrap();
 
@@ -1044,6 +1059,22 @@ impl RecognitionApi {
 }
 
 //------------------------ impl notify ------------------------
+impl NotifyBackground {
+    /// 背景图缩放后，更新宽高并等比缩放目标框
+    pub fn resized(&mut self, width: i64, height: i64) {
+        if self.width > 0 && self.height > 0 && (width, height) != (self.width, self.height) {
+            self.rect = ApiRect {
+                x: self.rect.x * width / self.width,
+                y: self.rect.y * height / self.height,
+                w: self.rect.w * width / self.width,
+                h: self.rect.h * height / self.height,
+            };
+        }
+        self.width = width;
+        self.height = height;
+    }
+}
+
 impl FaceNotifyParams {
     pub fn has_trip_info(&self) -> bool {
         if let Some(ref v) = self.trip {
@@ -1161,7 +1192,7 @@ impl CarNotifyParams {
         false
     }
 
-    /// 车牌号码置信度
+    /// 车牌号码置信度，每一位首选字符conf的平均值(多帧投票后为平均得票)
     pub fn get_plate_confidence(&self) -> Option<f64> {
         if let Some(ref v) = self.plate_info {
             if let Some(ref bits) = v.bits {
@@ -1,3 +1,4 @@
 pub mod bm_api;
 pub mod sync_api;
 pub mod upload_api;
+pub mod upload_codec;
@@ -4,16 +4,19 @@ use axum::Json;
 use std::time::Duration;
 
 use bytes::Buf;
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Datelike, Local, NaiveDate, TimeZone, Timelike};
 use reqwest::header;
 use reqwest::{Client, Error as Reqwest_Error};
 
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::Error as Serde_Error;
+use sha2::{Digest, Sha256};
 use tracing::{debug, error, info};
 
-use crate::util::time_format::long_ts_format;
+use crate::util::sign::Signer;
+use crate::util::time_format::{long_ts_format, opt_long_ts_format};
+use crate::util::tls::ClientTls;
 use crate::util::utils;
 
 pub const SYNC_OP_MODIFY: i8 = 1;
@@ -23,6 +26,7 @@ pub const RES_STATUS_OK: i32 = 0;
 pub const RES_STATUS_ERROR: i32 = 500;
 pub const RES_STATUS_INVALID_PARA: i32 = 1;
 pub const RES_STATUS_BIZ_ERR: i32 = 2;
+pub const RES_STATUS_AUTH_ERR: i32 = 3;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct PersonInfoFace {
@@ -31,6 +35,34 @@ pub struct PersonInfoFace {
     pub quality: f32,
 
     pub id: String, // face num
+
+    // 特征值的 sha256，盒子按 hash 比对差异，旧版本的服务端没有
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+impl PersonInfoFace {
+    pub fn new(id: String, fea: String, quality: f32) -> Self {
+        let hash = Some(feature_hash(&fea));
+        PersonInfoFace {
+            fea,
+            quality,
+            id,
+            hash,
+        }
+    }
+
+    pub fn get_hash(&self) -> String {
+        match self.hash {
+            Some(ref v) => v.clone(),
+            None => feature_hash(&self.fea),
+        }
+    }
+}
+
+/// 特征值(base64)的 sha256
+pub fn feature_hash(fea: &str) -> String {
+    hex::encode(Sha256::digest(fea.trim().as_bytes()))
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -47,6 +79,9 @@ pub struct PersonInfo {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Person {
     pub id: String,
+    // 游标中的 id，删除记录为 _del 表的 id(id 为原记录的 id)。旧版本的服务端没有
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<i64>,
     pub uuid: String,
     pub db_id: String,
 
@@ -83,6 +118,9 @@ impl Person {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Camera {
     pub id: String,
+    // 同 Person.seq
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<i64>,
     pub uuid: String,
 
     // 1：增加或修改 2：删除
@@ -109,9 +147,189 @@ pub struct CameraInfo {
     pub config: String,
 }
 
+impl CameraInfo {
+    /// 摄像头配置中的 filter 字段，没有配置返回 None
+    pub fn filter(&self) -> Result<Option<CameraFilter>, Serde_Error> {
+        #[derive(Deserialize)]
+        struct ConfigFilter {
+            filter: Option<CameraFilter>,
+        }
+
+        let v: ConfigFilter = serde_json::from_str(&self.config)?;
+        Ok(v.filter.map(|mut x| {
+            x.uuid = self.uuid.clone();
+            x
+        }))
+    }
+
+    /// 摄像头配置中的 schedule 字段，没有配置返回 None
+    pub fn schedule(&self) -> Result<Option<CameraSchedule>, Serde_Error> {
+        #[derive(Deserialize)]
+        struct ConfigSchedule {
+            schedule: Option<CameraSchedule>,
+        }
+
+        let v: ConfigSchedule = serde_json::from_str(&self.config)?;
+        Ok(v.schedule.map(|mut x| {
+            x.uuid = self.uuid.clone();
+            x
+        }))
+    }
+}
+
+/// 人脸/车辆的过滤规则，不满足的图片在盒子上直接丢弃，不再上传和比对
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TrackFilter {
+    // 感兴趣区域，多边形顶点为视频原始分辨率下的像素坐标 [x, y]，
+    // 目标框中心落在任意一个多边形内才保留，为空表示不限
+    #[serde(default)]
+    pub rois: Vec<Vec<[i64; 2]>>,
+
+    // 目标框宽、高的最小像素，0 表示不限
+    #[serde(default)]
+    pub min_size: i64,
+
+    // 人脸角度上限(度)，车辆不使用
+    pub max_yaw: Option<f64>,
+    pub max_pitch: Option<f64>,
+
+    // 允许的运动方向(move_direction)，为空表示不限
+    #[serde(default)]
+    pub directions: Vec<i64>,
+}
+
+/// 摄像头的过滤规则，放在摄像头配置的 filter 字段中，
+/// 由 sync_client 同步到本地文件，box_agent 加载
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CameraFilter {
+    #[serde(default)]
+    pub uuid: String,
+    pub face: Option<TrackFilter>,
+    pub vehicle: Option<TrackFilter>,
+}
+
+/// 一天内的时间段，"HH:MM"，end 可以为 "24:00"，跨天的分成两段
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScheduleRange {
+    pub start: String,
+    pub end: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScheduleWeekly {
+    // 1-7，周一为1
+    pub days: Vec<u32>,
+    pub ranges: Vec<ScheduleRange>,
+}
+
+/// 日期例外，优先于 weekly
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScheduleException {
+    pub date: NaiveDate,
+    // 为空表示全天不采集(节假日)，否则当天按这些时间段采集(调休)
+    #[serde(default)]
+    pub ranges: Vec<ScheduleRange>,
+}
+
+/// 采集时间窗口外的处理
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduleOutside {
+    #[default]
+    Drop, // 丢弃，并停用摄像头
+    LowPriority, // 照常采集上传，标记为低优先级，不做比对
+}
+
+/// 摄像头的采集时间表，放在摄像头配置的 schedule 字段中，
+/// 由 sync_client 同步到本地文件，box_agent 加载
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CameraSchedule {
+    #[serde(default)]
+    pub uuid: String,
+
+    // 采集类型，sync_client 重新启用摄像头时使用
+    #[serde(default)]
+    pub c_type: i64,
+
+    // 为空表示每天全天
+    #[serde(default)]
+    pub weekly: Vec<ScheduleWeekly>,
+    #[serde(default)]
+    pub exceptions: Vec<ScheduleException>,
+    #[serde(default)]
+    pub outside: ScheduleOutside,
+}
+
+impl CameraSchedule {
+    pub fn validate(&self) -> Result<(), String> {
+        let ranges = self
+            .weekly
+            .iter()
+            .flat_map(|x| x.ranges.iter())
+            .chain(self.exceptions.iter().flat_map(|x| x.ranges.iter()));
+        for range in ranges {
+            match (parse_hm(&range.start), parse_hm(&range.end)) {
+                (Some(start), Some(end)) if start < end => {}
+                _ => {
+                    return Err(format!("invalid range: {}-{}", range.start, range.end));
+                }
+            }
+        }
+
+        for weekly in self.weekly.iter() {
+            if weekly.days.iter().any(|x| !(1..=7).contains(x)) {
+                return Err(format!("invalid days: {:?}", weekly.days));
+            }
+        }
+        Ok(())
+    }
+
+    /// now 是否在采集时间窗口内
+    pub fn is_active(&self, now: DateTime<Local>) -> bool {
+        let minute = now.hour() * 60 + now.minute();
+
+        let today = now.naive_local().date();
+        if let Some(v) = self.exceptions.iter().find(|x| x.date == today) {
+            return in_ranges(&v.ranges, minute);
+        }
+
+        if self.weekly.is_empty() {
+            return true;
+        }
+        let day = now.weekday().number_from_monday();
+        self.weekly
+            .iter()
+            .filter(|x| x.days.contains(&day))
+            .any(|x| in_ranges(&x.ranges, minute))
+    }
+}
+
+fn in_ranges(ranges: &[ScheduleRange], minute: u32) -> bool {
+    ranges
+        .iter()
+        .any(|x| match (parse_hm(&x.start), parse_hm(&x.end)) {
+            (Some(start), Some(end)) => minute >= start && minute < end,
+            _ => false,
+        })
+}
+
+// "HH:MM" 转为分钟数，0 ~ 1440
+fn parse_hm(s: &str) -> Option<u32> {
+    let (h, m) = s.trim().split_once(':')?;
+    let h: u32 = h.parse().ok()?;
+    let m: u32 = m.parse().ok()?;
+    if m >= 60 || h * 60 + m > 24 * 60 {
+        return None;
+    }
+    Some(h * 60 + m)
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Db {
     pub id: String,
+    // 同 Person.seq
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<i64>,
     pub uuid: String,
 
     // 1：增加或修改 2：删除
@@ -125,6 +343,113 @@ pub struct Db {
 
 //----------------------------------
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PlateInfo {
+    pub uuid: String,
+
+    // 车牌号，支持通配符: * 任意个字符，? 单个字符
+    pub plate: String,
+
+    // 布控类别，如 被盗、通缉
+    pub tag: Option<String>,
+
+    pub memo: Option<String>,
+
+    // 有效期，为空表示不限
+    #[serde(default, with = "opt_long_ts_format")]
+    pub valid_from: Option<DateTime<Local>>,
+    #[serde(default, with = "opt_long_ts_format")]
+    pub valid_to: Option<DateTime<Local>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Plate {
+    pub id: String,
+    // 同 Person.seq
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<i64>,
+    pub uuid: String,
+
+    // 1：增加或修改 2：删除
+    pub op: i8,
+
+    #[serde(with = "long_ts_format")]
+    pub last_update: DateTime<Local>,
+
+    pub detail: Option<PlateInfo>,
+}
+
+//----------------------------------
+
+/// 同步游标 (modify_time, op, id)。主表和 _del 表的记录合并后按这个顺序分页，
+/// 同一个 modify_time 的记录超过一页时也不会丢失。
+/// 编码后作为 ResponseData.next 返回，客户端下次请求时原样带回(cursor 参数)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SyncCursor {
+    pub ts: DateTime<Local>,
+    pub op: i8,
+    pub id: i64,
+}
+
+impl SyncCursor {
+    pub fn new(ts: DateTime<Local>, op: i8, id: i64) -> Self {
+        SyncCursor { ts, op, id }
+    }
+
+    /// 旧版本的盒子只传 last_update，取 last_update 之后的记录
+    pub fn from_ts(ts: DateTime<Local>) -> Self {
+        SyncCursor {
+            ts,
+            op: SYNC_OP_DEL,
+            id: i64::MAX,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        format!("{}_{}_{}", self.ts.timestamp_millis(), self.op, self.id)
+    }
+
+    pub fn decode(s: &str) -> Option<Self> {
+        let mut it = s.splitn(3, '_');
+        let ts = it.next()?.parse::<i64>().ok()?;
+        let op = it.next()?.parse::<i8>().ok()?;
+        let id = it.next()?.parse::<i64>().ok()?;
+        let ts = Local.timestamp_millis_opt(ts).single()?;
+        Some(SyncCursor { ts, op, id })
+    }
+
+    /// 表(op)中 modify_time 等于游标时间的记录，id 需要大于这个值
+    pub fn after_id(&self, op: i8) -> i64 {
+        match self.op.cmp(&op) {
+            std::cmp::Ordering::Less => i64::MIN,
+            std::cmp::Ordering::Equal => self.id,
+            std::cmp::Ordering::Greater => i64::MAX,
+        }
+    }
+}
+
+/// 同步记录在游标中的位置
+pub trait SyncItem {
+    fn cursor(&self) -> SyncCursor;
+}
+
+macro_rules! impl_sync_item {
+    ($($t:ty),*) => {
+        $(
+            impl SyncItem for $t {
+                fn cursor(&self) -> SyncCursor {
+                    let id = self.seq.unwrap_or_else(|| self.id.parse().unwrap_or(0));
+                    SyncCursor::new(self.last_update, self.op, id)
+                }
+            }
+        )*
+    };
+}
+
+impl_sync_item!(Db, Person, Camera, Plate);
+
+//----------------------------------
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ResponseData<T> {
     pub status: i32,
@@ -136,6 +461,10 @@ pub struct ResponseData<T> {
     #[serde(bound(serialize = "T: Serialize", deserialize = "T: Deserialize<'de>"))]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub data: Option<Vec<T>>,
+
+    // 下一页的游标，旧版本的服务端没有
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next: Option<String>,
 }
 
 impl<T> ResponseData<T> {
@@ -161,6 +490,7 @@ where
 pub enum ApiError {
     NetErr(String),
     JsonErr(String),
+    AuthErr(String), // 签名校验失败
 }
 
 pub type ApiResult<T> = std::result::Result<T, ApiError>;
@@ -190,10 +520,18 @@ impl From<Reqwest_Error> for ApiError {
 
 pub struct Api {
     pub client: Client,
+    // 配置了 secret 时，请求带上签名
+    pub signer: Option<Signer>,
 }
 
 impl Default for Api {
     fn default() -> Self {
+        Self::new(None, &ClientTls::default())
+    }
+}
+
+impl Api {
+    pub fn new(signer: Option<Signer>, tls: &ClientTls) -> Self {
         let mut headers = header::HeaderMap::new();
         headers.insert(
             header::USER_AGENT,
@@ -209,58 +547,108 @@ impl Default for Api {
             header::HeaderValue::from_static("keep-alive"),
         );
 
-        let client = Client::builder()
+        let client = tls
+            .builder()
             .connect_timeout(Duration::from_secs(10))
             .pool_max_idle_per_host(0)
             .default_headers(headers)
-            .danger_accept_invalid_certs(true)
             .build()
             .unwrap();
 
-        Self { client }
+        Self { client, signer }
     }
-}
 
-impl Api {
     pub async fn fetch_db_updated(
         &self,
         url: &str,
         last_update_ts: &str,
+        cursor: Option<&str>,
         hw_id: &str,
     ) -> ApiResult<ResponseData<Db>> {
-        let dst_url = utils::add_url_query(url, "last_update", last_update_ts);
-        let dst_url = utils::add_url_query(&dst_url, "hw_id", hw_id);
-        do_get(&self.client, &dst_url).await
+        let dst_url = build_sync_url(url, last_update_ts, cursor, hw_id);
+        do_get(&self.client, &self.signer, &dst_url).await
     }
 
     pub async fn fetch_person_updated(
         &self,
         url: &str,
         last_update_ts: &str,
+        cursor: Option<&str>,
         hw_id: &str,
     ) -> ApiResult<ResponseData<Person>> {
-        let dst_url = utils::add_url_query(url, "last_update", last_update_ts);
-        let dst_url = utils::add_url_query(&dst_url, "hw_id", hw_id);
-        do_get(&self.client, &dst_url).await
+        let dst_url = build_sync_url(url, last_update_ts, cursor, hw_id);
+        do_get(&self.client, &self.signer, &dst_url).await
     }
 
     pub async fn fetch_camera_updated(
         &self,
         url: &str,
         last_update_ts: &str,
+        cursor: Option<&str>,
         hw_id: &str,
     ) -> ApiResult<ResponseData<Camera>> {
-        let dst_url = utils::add_url_query(url, "last_update", last_update_ts);
-        let dst_url = utils::add_url_query(&dst_url, "hw_id", hw_id);
-        do_get(&self.client, &dst_url).await
+        let dst_url = build_sync_url(url, last_update_ts, cursor, hw_id);
+        do_get(&self.client, &self.signer, &dst_url).await
+    }
+
+    pub async fn fetch_plate_updated(
+        &self,
+        url: &str,
+        last_update_ts: &str,
+        cursor: Option<&str>,
+        hw_id: &str,
+    ) -> ApiResult<ResponseData<Plate>> {
+        let dst_url = build_sync_url(url, last_update_ts, cursor, hw_id);
+        do_get(&self.client, &self.signer, &dst_url).await
+    }
+}
+
+impl Api {
+    /// 对账接口，取服务端的全部记录(或id)
+    pub async fn fetch_list<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        hw_id: &str,
+        paras: &[(&str, &str)],
+    ) -> ApiResult<ResponseData<T>> {
+        let mut dst_url = utils::add_url_query(url, "hw_id", hw_id);
+        for (name, value) in paras.iter() {
+            dst_url = utils::add_url_query(&dst_url, name, value);
+        }
+        do_get(&self.client, &self.signer, &dst_url).await
     }
 }
 
-async fn do_get<T: DeserializeOwned>(client: &Client, url: &str) -> ApiResult<T> {
+// last_update 保留给旧版本的服务端，新版本优先使用 cursor
+fn build_sync_url(url: &str, last_update_ts: &str, cursor: Option<&str>, hw_id: &str) -> String {
+    let dst_url = utils::add_url_query(url, "last_update", last_update_ts);
+    let dst_url = match cursor {
+        Some(v) => utils::add_url_query(&dst_url, "cursor", v),
+        None => dst_url,
+    };
+    utils::add_url_query(&dst_url, "hw_id", hw_id)
+}
+
+async fn do_get<T: DeserializeOwned>(
+    client: &Client,
+    signer: &Option<Signer>,
+    url: &str,
+) -> ApiResult<T> {
     info!("url: {:?}", url);
 
-    let response = client.get(url).send().await?;
+    let mut req = client.get(url);
+    if let Some(signer) = signer {
+        req = req.headers(signer.headers("GET", url, b""));
+    }
+    let response = req.send().await?;
 
+    if response.status() == StatusCode::UNAUTHORIZED {
+        return Err(ApiError::AuthErr(format!(
+            "http status:{}, {}",
+            response.status(),
+            response.text().await.unwrap_or_default()
+        )));
+    }
     if response.status() != StatusCode::OK {
         return Err(ApiError::net_err(&format!(
             "http status:{}",
@@ -2,6 +2,7 @@ use crate::api::bm_api::{CarNotifyParams, FaceNotifyParams};
 use crate::util::time_format::long_ts_format;
 use axum::response::{IntoResponse, Response};
 use axum::Json;
+use bytes::Bytes;
 use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
 
@@ -21,9 +22,25 @@ pub struct NotifyFaceQueueItem {
     pub ts: DateTime<Local>,
 
     pub matches: Option<Vec<MatchPerson>>,
+
+    // 采集时间窗口外的track，不做比对
+    #[serde(default)]
+    pub low_priority: bool,
 }
 
 // ------------------- queue structs (car) -------------------
+/// 车牌布控命中
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PlateHit {
+    // 布控记录的uuid
+    pub uuid: String,
+    // 布控的车牌(可能带通配符)
+    pub plate: String,
+    pub tag: Option<String>,
+    // 是否是按易混淆字符(0/D, 8/B 等)放宽后才命中的
+    pub fuzzy: bool,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct NotifyCarQueueItem {
     pub uuid: String,
@@ -31,6 +48,33 @@ pub struct NotifyCarQueueItem {
 
     #[serde(with = "long_ts_format")]
     pub ts: DateTime<Local>,
+
+    // 盒子本地车牌布控比对结果
+    pub plate_hits: Option<Vec<PlateHit>>,
+
+    // 采集时间窗口外的track
+    #[serde(default)]
+    pub low_priority: bool,
+}
+
+// ------------------- queue structs (alarm) -------------------
+/// 布控告警，比对分数超过告警阈值时，单独通道优先上报
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NotifyAlarmItem {
+    pub uuid: String,
+    pub track_uuid: String,
+    pub source: String,
+    pub matched: MatchPerson,
+
+    pub face_file: String,
+    // 人脸图base64，track_warehouse转发时填充
+    pub face_image: Option<String>,
+
+    #[serde(with = "long_ts_format")]
+    pub ts: DateTime<Local>,
+
+    #[serde(skip)]
+    pub face_buf: Bytes,
 }
 
 // --------------------------------------
@@ -50,6 +94,11 @@ impl QI {
 }
 
 //---------------------------------------------
+// 签名校验失败，同时返回 http 401
+pub const RES_STATUS_AUTH_ERR: i32 = 401;
+// track 内容错误(格式、缺少字段、图片无法转换)，重传也不会成功，盒子不再重传
+pub const RES_STATUS_REJECTED: i32 = 422;
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ResponseData {
     pub status: i32,
@@ -61,3 +110,25 @@ impl IntoResponse for ResponseData {
         Json(self).into_response()
     }
 }
+
+//---------------------------------------------
+/// /upload_batch 中单个track的结果，失败的由盒子重传
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BatchItemResult {
+    pub uuid: String,
+    pub status: i32,
+    pub message: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BatchResponseData {
+    pub status: i32,
+    pub message: Option<String>,
+    pub results: Vec<BatchItemResult>,
+}
+
+impl IntoResponse for BatchResponseData {
+    fn into_response(self) -> Response {
+        Json(self).into_response()
+    }
+}
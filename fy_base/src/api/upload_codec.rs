@@ -0,0 +1,183 @@
+use std::io::{Read, Write};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use crate::api::upload_api::{NotifyCarQueueItem, NotifyFaceQueueItem, QI};
+
+// track 的二进制格式: json长度 + json + 按固定顺序排列的各个图片/特征buf
+// box_agent 的本地 spool 和 /upload_batch 共用
+
+pub type CodecResult<T> = std::result::Result<T, String>;
+
+const KIND_FACE: u8 = 1;
+const KIND_CAR: u8 = 2;
+
+pub fn encode_face(item: &NotifyFaceQueueItem) -> CodecResult<Bytes> {
+    let json = serde_json::to_vec(item).map_err(|e| format!("{:?}", e))?;
+    let mut buf = BytesMut::new();
+    put_buf(&mut buf, &json);
+
+    put_buf(&mut buf, &item.notify.background.image_buf);
+    for face in item.notify.faces.iter() {
+        put_buf(&mut buf, &face.aligned_buf);
+        put_buf(&mut buf, &face.display_buf);
+        put_opt_buf(&mut buf, &face.feature_buf);
+    }
+
+    Ok(buf.freeze())
+}
+
+pub fn decode_face(mut buf: Bytes) -> CodecResult<NotifyFaceQueueItem> {
+    let json = get_buf(&mut buf)?;
+    let mut item: NotifyFaceQueueItem =
+        serde_json::from_slice(&json).map_err(|e| format!("{:?}", e))?;
+
+    item.notify.background.image_buf = get_buf(&mut buf)?;
+    for face in item.notify.faces.iter_mut() {
+        face.aligned_buf = get_buf(&mut buf)?;
+        face.display_buf = get_buf(&mut buf)?;
+        face.feature_buf = get_opt_buf(&mut buf)?;
+    }
+
+    Ok(item)
+}
+
+pub fn encode_car(item: &NotifyCarQueueItem) -> CodecResult<Bytes> {
+    let json = serde_json::to_vec(item).map_err(|e| format!("{:?}", e))?;
+    let mut buf = BytesMut::new();
+    put_buf(&mut buf, &json);
+
+    put_buf(&mut buf, &item.notify.background.image_buf);
+    for car in item.notify.vehicles.iter() {
+        put_buf(&mut buf, &car.img_buf);
+    }
+    if let Some(ref plate) = item.notify.plate_info {
+        put_buf(&mut buf, &plate.img_buf);
+        put_buf(&mut buf, &plate.binary_buf);
+    }
+
+    Ok(buf.freeze())
+}
+
+pub fn decode_car(mut buf: Bytes) -> CodecResult<NotifyCarQueueItem> {
+    let json = get_buf(&mut buf)?;
+    let mut item: NotifyCarQueueItem =
+        serde_json::from_slice(&json).map_err(|e| format!("{:?}", e))?;
+
+    item.notify.background.image_buf = get_buf(&mut buf)?;
+    for car in item.notify.vehicles.iter_mut() {
+        car.img_buf = get_buf(&mut buf)?;
+    }
+    if let Some(ref mut plate) = item.notify.plate_info {
+        plate.img_buf = get_buf(&mut buf)?;
+        plate.binary_buf = get_buf(&mut buf)?;
+    }
+
+    Ok(item)
+}
+
+//---------------------------------------------
+/// 批量格式: 个数 + (类型 + uuid + track)...
+/// 单个track解析失败不影响其他track，按uuid返回结果
+pub fn encode_batch(items: &[QI]) -> CodecResult<Bytes> {
+    let mut buf = BytesMut::new();
+    buf.put_u32(items.len() as u32);
+    for item in items {
+        let (kind, body) = match item {
+            QI::FT(v) => (KIND_FACE, encode_face(v)?),
+            QI::CT(v) => (KIND_CAR, encode_car(v)?),
+        };
+        buf.put_u8(kind);
+        put_buf(&mut buf, item.get_id().as_bytes());
+        put_buf(&mut buf, &body);
+    }
+    Ok(buf.freeze())
+}
+
+/// 返回 (uuid, track)，格式错误时整个批次失败
+pub fn decode_batch(mut buf: Bytes) -> CodecResult<Vec<(String, CodecResult<QI>)>> {
+    if buf.remaining() < 4 {
+        return Err("batch truncated".into());
+    }
+    let count = buf.get_u32();
+
+    let mut items = vec![];
+    for _ in 0..count {
+        if !buf.has_remaining() {
+            return Err("batch truncated".into());
+        }
+        let kind = buf.get_u8();
+        let uuid = String::from_utf8_lossy(&get_buf(&mut buf)?).to_string();
+        let body = get_buf(&mut buf)?;
+
+        let item = match kind {
+            KIND_FACE => decode_face(body).map(|x| QI::FT(Box::new(x))),
+            KIND_CAR => decode_car(body).map(|x| QI::CT(Box::new(x))),
+            _ => Err(format!("unknown track kind: {}", kind)),
+        };
+        items.push((uuid, item));
+    }
+    Ok(items)
+}
+
+pub fn gzip(content: &[u8]) -> CodecResult<Bytes> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+    encoder.write_all(content).map_err(|e| format!("{:?}", e))?;
+    let buf = encoder.finish().map_err(|e| format!("{:?}", e))?;
+    Ok(Bytes::from(buf))
+}
+
+/// 解压后超过 max 字节的直接拒绝，避免压缩炸弹耗尽内存
+pub fn gunzip(content: &[u8], max: u64) -> CodecResult<Bytes> {
+    let mut buf = Vec::new();
+    GzDecoder::new(content)
+        .take(max + 1)
+        .read_to_end(&mut buf)
+        .map_err(|e| format!("{:?}", e))?;
+    if buf.len() as u64 > max {
+        return Err(format!("decompressed size exceeds {}", max));
+    }
+    Ok(Bytes::from(buf))
+}
+
+//---------------------------------------------
+pub fn put_buf(buf: &mut BytesMut, data: &[u8]) {
+    buf.put_u32(data.len() as u32);
+    buf.put_slice(data);
+}
+
+fn put_opt_buf(buf: &mut BytesMut, data: &Option<Bytes>) {
+    match data {
+        Some(v) => {
+            buf.put_u8(1);
+            put_buf(buf, v);
+        }
+        None => {
+            buf.put_u8(0);
+        }
+    }
+}
+
+pub fn get_buf(buf: &mut Bytes) -> CodecResult<Bytes> {
+    if buf.remaining() < 4 {
+        return Err("buf truncated".into());
+    }
+    let len = buf.get_u32() as usize;
+    if buf.remaining() < len {
+        return Err("buf truncated".into());
+    }
+    Ok(buf.split_to(len))
+}
+
+fn get_opt_buf(buf: &mut Bytes) -> CodecResult<Option<Bytes>> {
+    if !buf.has_remaining() {
+        return Err("buf truncated".into());
+    }
+    match buf.get_u8() {
+        0 => Ok(None),
+        _ => Ok(Some(get_buf(buf)?)),
+    }
+}
@@ -18,6 +18,7 @@ pub struct BoxLogMessage {
 
 pub const BOXLOGMESSAGE_TYPE_STATUS: &str = "status";
 pub const BOXLOGMESSAGE_TYPE_LOG: &str = "log";
+pub const BOXLOGMESSAGE_TYPE_RECONCILE: &str = "reconcile";
 
 // log级别 ： debug(0), info(1), warn(2), error(3)
 pub const BOXLOGMESSAGE_LEVEL_DEBUG: i16 = 0;
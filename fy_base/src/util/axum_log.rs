@@ -11,6 +11,9 @@ use chrono::Local;
 
 use hyper::HeaderMap;
 
+use crate::util::metrics;
+use crate::util::tls::PeerInfo;
+
 fn get_header_value(headers: &HeaderMap<HeaderValue>, name: HeaderName) -> String {
     match headers.get(name) {
         None => "-".to_string(),
@@ -30,8 +33,11 @@ pub async fn access_log<B>(
 
     let now = Local::now().format("%d/%b/%Y:%T %z").to_string();
     let ip = match req.extensions().get::<ConnectInfo<SocketAddr>>() {
-        None => "-".to_string(),
         Some(conn) => conn.0.ip().to_string(),
+        None => match req.extensions().get::<ConnectInfo<PeerInfo>>() {
+            Some(conn) => conn.0.addr.ip().to_string(),
+            None => "-".to_string(),
+        },
     };
     let method = req.method().clone();
     let uri = req.uri().clone();
@@ -56,7 +62,9 @@ pub async fn access_log<B>(
 
 pub async fn time_use<B>(req: Request<B>, next: Next<B>) -> impl IntoResponse {
     let ts = Instant::now();
+    let path = req.uri().path().to_string();
     let mut res = next.run(req).await;
+    metrics::observe_http(&path, res.status().as_u16(), ts);
     let used = ts.elapsed().as_millis().to_string();
     res.headers_mut()
         .insert("x-fy-use", HeaderValue::from_str(used.as_str()).unwrap());
@@ -0,0 +1,107 @@
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::{DateTime, Local};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use tracing::warn;
+
+use crate::util::time_format::opt_long_ts_format;
+
+// 各依赖的状态，/readyz 输出
+// 1) 主动检查: 调用 /readyz 时执行(bm api, mysql, minio)
+// 2) 被动检查: 由服务自己上报(rabbitmq 重连循环)
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct CheckStatus {
+    pub ok: bool,
+    #[serde(with = "opt_long_ts_format")]
+    pub last_ok: Option<DateTime<Local>>,
+    pub last_error: Option<String>,
+    #[serde(with = "opt_long_ts_format")]
+    pub last_error_time: Option<DateTime<Local>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ReadyRes {
+    pub ready: bool,
+    pub checks: BTreeMap<String, CheckStatus>,
+}
+
+static CHECKS: Lazy<Mutex<BTreeMap<String, CheckStatus>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+//-----------------------------------------
+/// 注册一个检查项，第一次上报之前为 not ready
+pub fn register(name: &str) {
+    let mut checks = CHECKS.lock().unwrap();
+    checks.entry(name.to_string()).or_default();
+}
+
+pub fn set_ok(name: &str) {
+    let mut checks = CHECKS.lock().unwrap();
+    let status = checks.entry(name.to_string()).or_default();
+    status.ok = true;
+    status.last_ok = Some(Local::now());
+}
+
+pub fn set_err<E: Debug>(name: &str, err: E) {
+    let mut checks = CHECKS.lock().unwrap();
+    let status = checks.entry(name.to_string()).or_default();
+    status.ok = false;
+    status.last_error = Some(format!("{:?}", err));
+    status.last_error_time = Some(Local::now());
+}
+
+/// 执行一次主动检查，超时也算失败
+pub async fn probe<T, E, F>(name: &str, timeout: Duration, f: F) -> bool
+where
+    E: Debug,
+    F: Future<Output = Result<T, E>>,
+{
+    match tokio::time::timeout(timeout, f).await {
+        Ok(Ok(_)) => {
+            set_ok(name);
+            true
+        }
+        Ok(Err(e)) => {
+            warn!("warn, health, {}, err: {:?}", name, e);
+            set_err(name, e);
+            false
+        }
+        Err(_) => {
+            warn!("warn, health, {}, timeout", name);
+            set_err(name, "timeout");
+            false
+        }
+    }
+}
+
+pub fn report() -> ReadyRes {
+    let checks = CHECKS.lock().unwrap().clone();
+    let ready = checks.values().all(|x| x.ok);
+    ReadyRes { ready, checks }
+}
+
+//-----------------------------------------
+/// axum handler: GET /healthz, 进程能响应即可
+pub async fn healthz_handler() -> Response {
+    (StatusCode::OK, "ok").into_response()
+}
+
+/// /readyz 的输出，有检查项失败返回 503
+pub fn readyz_response() -> Response {
+    let res = report();
+    let code = if res.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (code, Json(res)).into_response()
+}
@@ -1,8 +1,15 @@
 use bytes::Bytes;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::{self, FilterType};
 use image::io::Reader as ImageReader;
+use image::{DynamicImage, ImageOutputFormat, Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
 
 use std::io::Cursor;
 
+/// 没有配置转码时的 jpeg 质量
+pub const DEFAULT_QUALITY: u8 = 85;
+
 pub fn check_bmp_magic(buf: &[u8]) -> bool {
     buf.len() >= 2 && buf[0] == 0x42 && buf[1] == 0x4d
 }
@@ -20,7 +27,7 @@ pub fn escape_bmp_to_jpg(content: &[u8]) -> image::ImageResult<Bytes> {
     let mut buf = Vec::new();
     img.write_to(
         &mut Cursor::new(&mut buf),
-        image::ImageOutputFormat::Jpeg(85),
+        ImageOutputFormat::Jpeg(DEFAULT_QUALITY),
     )?;
     Ok(Bytes::from(buf))
 }
@@ -46,7 +53,7 @@ pub fn escape_bmp(content: Bytes) -> std::result::Result<Bytes, String> {
     let mut buf = Vec::new();
     match img.write_to(
         &mut Cursor::new(&mut buf),
-        image::ImageOutputFormat::Jpeg(85),
+        ImageOutputFormat::Jpeg(DEFAULT_QUALITY),
     ) {
         Ok(_) => Ok(Bytes::from(buf)),
         Err(e) => {
@@ -54,3 +61,298 @@ pub fn escape_bmp(content: Bytes) -> std::result::Result<Bytes, String> {
         }
     }
 }
+
+/// 根据文件头猜测 content type，猜不出来的按 jpeg
+pub fn guess_content_type(content: &[u8]) -> &'static str {
+    match image::guess_format(content) {
+        Ok(image::ImageFormat::Png) => "image/png",
+        Ok(image::ImageFormat::Bmp) => "image/bmp",
+        _ => "image/jpeg",
+    }
+}
+
+//----------------------------------------------
+// 转码: 输出格式、质量、最大尺寸、缩略图
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageFormat {
+    Jpeg,
+    Png,
+}
+
+impl ImageFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Png => "image/png",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Png => "png",
+        }
+    }
+
+    fn is_same(&self, format: Option<image::ImageFormat>) -> bool {
+        matches!(
+            (self, format),
+            (ImageFormat::Jpeg, Some(image::ImageFormat::Jpeg))
+                | (ImageFormat::Png, Some(image::ImageFormat::Png))
+        )
+    }
+}
+
+/// 转码配置，图片格式相同、尺寸不超过限制、不需要去掉元数据时原样输出
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ImageProfile {
+    pub format: ImageFormat,
+    pub quality: u8,      // jpeg 质量, 1~100
+    pub max_width: u32,   // 0 不限制
+    pub max_height: u32,  // 0 不限制
+    pub thumbnail: u32,   // 缩略图的最长边, 0 不生成
+    pub strip_exif: bool, // 总是重新编码，去掉 exif 等元数据
+}
+
+impl Default for ImageProfile {
+    fn default() -> Self {
+        Self {
+            format: ImageFormat::Jpeg,
+            quality: DEFAULT_QUALITY,
+            max_width: 0,
+            max_height: 0,
+            thumbnail: 0,
+            strip_exif: false,
+        }
+    }
+}
+
+impl ImageProfile {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.quality == 0 || self.quality > 100 {
+            return Err(format!("quality must be in 1..=100, {}", self.quality));
+        }
+        Ok(())
+    }
+
+    // 等比缩小到 max_width x max_height 以内
+    fn fit(&self, width: u32, height: u32) -> (u32, u32) {
+        let mut scale = 1.0_f64;
+        if self.max_width > 0 && width > self.max_width {
+            scale = scale.min(self.max_width as f64 / width as f64);
+        }
+        if self.max_height > 0 && height > self.max_height {
+            scale = scale.min(self.max_height as f64 / height as f64);
+        }
+        if scale >= 1.0 {
+            return (width, height);
+        }
+        (
+            ((width as f64 * scale) as u32).max(1),
+            ((height as f64 * scale) as u32).max(1),
+        )
+    }
+
+    fn encode(&self, img: &DynamicImage) -> image::ImageResult<Bytes> {
+        match self.format {
+            ImageFormat::Jpeg => encode_jpeg(&img.to_rgb8(), self.quality),
+            ImageFormat::Png => {
+                let mut buf = Vec::new();
+                img.write_to(&mut Cursor::new(&mut buf), ImageOutputFormat::Png)?;
+                Ok(Bytes::from(buf))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TranscodedImage {
+    pub buf: Bytes,
+    pub width: u32,
+    pub height: u32,
+    pub thumbnail: Option<Bytes>,
+}
+
+/// 按 profile 转码，只在需要时才解码
+pub fn transcode(content: Bytes, profile: &ImageProfile) -> image::ImageResult<TranscodedImage> {
+    let reader = ImageReader::new(Cursor::new(&content[..])).with_guessed_format()?;
+    let same_format = profile.format.is_same(reader.format());
+    let (width, height) = reader.into_dimensions()?;
+    let (to_width, to_height) = profile.fit(width, height);
+    let keep = same_format && (to_width, to_height) == (width, height) && !profile.strip_exif;
+
+    if keep && profile.thumbnail == 0 {
+        return Ok(TranscodedImage {
+            buf: content,
+            width,
+            height,
+            thumbnail: None,
+        });
+    }
+
+    let mut img = ImageReader::new(Cursor::new(&content[..]))
+        .with_guessed_format()?
+        .decode()?;
+    if (to_width, to_height) != (width, height) {
+        img = img.resize_exact(to_width, to_height, FilterType::Triangle);
+    }
+
+    let thumbnail = match profile.thumbnail {
+        0 => None,
+        v => Some(profile.encode(&img.thumbnail(v, v))?),
+    };
+    let buf = if keep { content } else { profile.encode(&img)? };
+
+    Ok(TranscodedImage {
+        buf,
+        width: to_width,
+        height: to_height,
+        thumbnail,
+    })
+}
+
+//----------------------------------------------
+// 隐私脱敏: 背景图中除目标外的区域做模糊/马赛克
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RedactMethod {
+    Blur,     // 模糊，strength 为缩小的倍数
+    Pixelate, // 马赛克，strength 为色块的像素
+}
+
+/// 图片上的区域，像素坐标
+#[derive(Debug, Clone, Copy)]
+pub struct ImageRect {
+    pub x: i64,
+    pub y: i64,
+    pub w: i64,
+    pub h: i64,
+}
+
+impl ImageRect {
+    /// 四周各扩大 w/h 的 ratio 倍
+    pub fn expand(&self, ratio: f64) -> Self {
+        let dw = (self.w as f64 * ratio) as i64;
+        let dh = (self.h as f64 * ratio) as i64;
+        Self {
+            x: self.x - dw,
+            y: self.y - dh,
+            w: self.w + dw * 2,
+            h: self.h + dh * 2,
+        }
+    }
+
+    // 裁剪到图片范围内，返回 (x, y, w, h)，没有交集返回 None
+    fn clip(&self, width: u32, height: u32) -> Option<(u32, u32, u32, u32)> {
+        let x0 = self.x.clamp(0, width as i64);
+        let y0 = self.y.clamp(0, height as i64);
+        let x1 = (self.x + self.w).clamp(0, width as i64);
+        let y1 = (self.y + self.h).clamp(0, height as i64);
+        if x1 <= x0 || y1 <= y0 {
+            return None;
+        }
+        Some((x0 as u32, y0 as u32, (x1 - x0) as u32, (y1 - y0) as u32))
+    }
+}
+
+pub fn decode_rgb(content: &[u8]) -> image::ImageResult<RgbImage> {
+    let img = ImageReader::new(Cursor::new(content))
+        .with_guessed_format()?
+        .decode()?;
+    Ok(img.to_rgb8())
+}
+
+pub fn encode_jpeg(img: &RgbImage, quality: u8) -> image::ImageResult<Bytes> {
+    let mut buf = Vec::new();
+    JpegEncoder::new_with_quality(&mut buf, quality).encode_image(img)?;
+    Ok(Bytes::from(buf))
+}
+
+/// 宽度超过 max_width 时等比缩小
+pub fn downscale(img: RgbImage, max_width: u32) -> RgbImage {
+    if max_width == 0 || img.width() <= max_width {
+        return img;
+    }
+    let height = (img.height() as u64 * max_width as u64 / img.width() as u64).max(1) as u32;
+    imageops::resize(&img, max_width, height, FilterType::Triangle)
+}
+
+/// 对 regions 内的区域脱敏
+pub fn redact_regions(
+    img: &mut RgbImage,
+    regions: &[ImageRect],
+    method: RedactMethod,
+    strength: u32,
+) {
+    for region in regions {
+        if let Some((x, y, w, h)) = region.clip(img.width(), img.height()) {
+            let part = imageops::crop_imm(img, x, y, w, h).to_image();
+            let part = redact_image(&part, method, strength);
+            imageops::replace(img, &part, x as i64, y as i64);
+        }
+    }
+}
+
+/// 除 keeps 外的区域全部脱敏
+pub fn redact_outside(
+    img: &mut RgbImage,
+    keeps: &[ImageRect],
+    method: RedactMethod,
+    strength: u32,
+) {
+    let mut out = redact_image(img, method, strength);
+    for keep in keeps {
+        if let Some((x, y, w, h)) = keep.clip(img.width(), img.height()) {
+            let part = imageops::crop_imm(img, x, y, w, h).to_image();
+            imageops::replace(&mut out, &part, x as i64, y as i64);
+        }
+    }
+    *img = out;
+}
+
+fn redact_image(img: &RgbImage, method: RedactMethod, strength: u32) -> RgbImage {
+    let strength = strength.max(2);
+    let (width, height) = img.dimensions();
+    match method {
+        RedactMethod::Blur => {
+            // 缩小再放大，比高斯模糊快很多
+            let small = imageops::resize(
+                img,
+                (width / strength).max(1),
+                (height / strength).max(1),
+                FilterType::Triangle,
+            );
+            imageops::resize(&small, width, height, FilterType::Triangle)
+        }
+        RedactMethod::Pixelate => {
+            let mut out = img.clone();
+            for by in (0..height).step_by(strength as usize) {
+                for bx in (0..width).step_by(strength as usize) {
+                    let bw = strength.min(width - bx);
+                    let bh = strength.min(height - by);
+
+                    let mut sum = [0_u64; 3];
+                    for y in by..by + bh {
+                        for x in bx..bx + bw {
+                            let p = img.get_pixel(x, y);
+                            sum.iter_mut()
+                                .zip(p.0.iter())
+                                .for_each(|(s, v)| *s += *v as u64);
+                        }
+                    }
+                    let n = (bw * bh) as u64;
+                    let avg = Rgb([(sum[0] / n) as u8, (sum[1] / n) as u8, (sum[2] / n) as u8]);
+                    for y in by..by + bh {
+                        for x in bx..bx + bw {
+                            out.put_pixel(x, y, avg);
+                        }
+                    }
+                }
+            }
+            out
+        }
+    }
+}
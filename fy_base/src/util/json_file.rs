@@ -0,0 +1,99 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use tracing::{error, info};
+
+pub type JsonParser<T> = fn(&[u8]) -> serde_json::Result<T>;
+
+//----------------------------------------------
+struct JsonFileState<T> {
+    data: Arc<T>,
+    mtime: Option<SystemTime>,
+    checked_at: Instant,
+}
+
+/// 定时检查的本地 JSON 文件(sync_client 同步下来的规则/名单等)，
+/// 修改时间变化了才重新加载，文件不存在时为 T::default()。
+/// 文件读取和解析都在锁外进行，锁内只做判断和替换
+pub struct JsonFile<T> {
+    name: &'static str,
+    file: PathBuf,
+    reload: Duration,
+    parse: JsonParser<T>,
+    state: Mutex<JsonFileState<T>>,
+}
+
+impl<T: Default> JsonFile<T> {
+    pub fn new(name: &'static str, file: &str, reload: Duration, parse: JsonParser<T>) -> Self {
+        let json_file = Self {
+            name,
+            file: PathBuf::from(file),
+            reload,
+            parse,
+            state: Mutex::new(JsonFileState {
+                data: Arc::new(T::default()),
+                mtime: None,
+                checked_at: Instant::now(),
+            }),
+        };
+
+        json_file.reload_file(None);
+        json_file
+    }
+
+    /// 返回当前数据，到了检查时间则先重新加载
+    pub fn get(&self) -> Arc<T> {
+        let mtime = {
+            let mut state = self.state.lock().unwrap();
+            if state.checked_at.elapsed() < self.reload {
+                return state.data.clone();
+            }
+
+            // 先更新检查时间，其它调用方不会重复加载
+            state.checked_at = Instant::now();
+            state.mtime
+        };
+
+        self.reload_file(mtime);
+        self.state.lock().unwrap().data.clone()
+    }
+
+    // 文件修改时间变化了才重新加载
+    fn reload_file(&self, old_mtime: Option<SystemTime>) {
+        let mtime = match fs::metadata(&self.file).and_then(|x| x.modified()) {
+            Ok(v) => v,
+            Err(_) => {
+                if old_mtime.is_some() {
+                    info!("{}, {:?} not exist, clear", self.name, self.file);
+                }
+                self.replace(T::default(), None);
+                return;
+            }
+        };
+        if Some(mtime) == old_mtime {
+            return;
+        }
+
+        let data = fs::read(&self.file)
+            .map_err(|e| format!("{:?}", e))
+            .and_then(|x| (self.parse)(&x).map_err(|e| format!("{:?}", e)));
+        match data {
+            Ok(v) => {
+                info!("{}, load {:?}", self.name, self.file);
+                self.replace(v, Some(mtime));
+            }
+            Err(e) => {
+                // 加载失败，继续使用旧的数据
+                error!("error, {}, load {:?}, err: {}", self.name, self.file, e);
+            }
+        }
+    }
+
+    fn replace(&self, data: T, mtime: Option<SystemTime>) {
+        let mut state = self.state.lock().unwrap();
+        state.data = Arc::new(data);
+        state.mtime = mtime;
+    }
+}
@@ -0,0 +1,234 @@
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use axum::http::header::CONTENT_TYPE;
+use axum::http::{HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use deadqueue::unlimited::Queue;
+use once_cell::sync::Lazy;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramTimer, HistogramVec, IntCounterVec, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+use tracing::error;
+
+// 各程序共用的指标，通过 /metrics 以 prometheus 文本格式输出
+// 程序之间用 prometheus 的 job/instance 区分，指标名不区分
+
+const NAMESPACE: &str = "fy";
+
+// 秒
+const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+
+type QueueLenFn = Box<dyn Fn() -> usize + Send + Sync>;
+
+struct Metrics {
+    registry: Registry,
+
+    queue_depth: IntGaugeVec,
+    queue_dropped: IntCounterVec,
+
+    processed: IntCounterVec,
+    failed: IntCounterVec,
+    filtered: IntCounterVec,
+    dedup: IntCounterVec,
+
+    bm_api: HistogramVec,
+    op: HistogramVec,
+    reconnect: IntCounterVec,
+    http: HistogramVec,
+
+    // gather时读取队列长度
+    queues: Mutex<Vec<(String, QueueLenFn)>>,
+}
+
+static METRICS: Lazy<Metrics> = Lazy::new(Metrics::new);
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+
+        let queue_depth = IntGaugeVec::new(
+            Opts::new("queue_depth", "items waiting in queue").namespace(NAMESPACE),
+            &["queue"],
+        )
+        .unwrap();
+        let queue_dropped = IntCounterVec::new(
+            Opts::new("queue_dropped_total", "items dropped by full queue").namespace(NAMESPACE),
+            &["queue"],
+        )
+        .unwrap();
+        let processed = IntCounterVec::new(
+            Opts::new("processed_total", "items processed by service").namespace(NAMESPACE),
+            &["service"],
+        )
+        .unwrap();
+        let failed = IntCounterVec::new(
+            Opts::new("failed_total", "items failed in service").namespace(NAMESPACE),
+            &["service"],
+        )
+        .unwrap();
+        let filtered = IntCounterVec::new(
+            Opts::new("filtered_total", "targets dropped by camera filter").namespace(NAMESPACE),
+            &["kind", "reason"],
+        )
+        .unwrap();
+        let dedup = IntCounterVec::new(
+            Opts::new("dedup_total", "duplicate tracks suppressed").namespace(NAMESPACE),
+            &["action"],
+        )
+        .unwrap();
+        let bm_api = HistogramVec::new(
+            HistogramOpts::new("bm_api_seconds", "bm_api call latency")
+                .namespace(NAMESPACE)
+                .buckets(LATENCY_BUCKETS.to_vec()),
+            &["method", "result"],
+        )
+        .unwrap();
+        let op = HistogramVec::new(
+            HistogramOpts::new("op_seconds", "minio/mysql/rabbitmq operation latency")
+                .namespace(NAMESPACE)
+                .buckets(LATENCY_BUCKETS.to_vec()),
+            &["backend", "op"],
+        )
+        .unwrap();
+        let reconnect = IntCounterVec::new(
+            Opts::new("reconnect_total", "reconnect count").namespace(NAMESPACE),
+            &["backend"],
+        )
+        .unwrap();
+        let http = HistogramVec::new(
+            HistogramOpts::new("http_request_seconds", "http request latency")
+                .namespace(NAMESPACE)
+                .buckets(LATENCY_BUCKETS.to_vec()),
+            &["path", "status"],
+        )
+        .unwrap();
+
+        registry.register(Box::new(queue_depth.clone())).unwrap();
+        registry.register(Box::new(queue_dropped.clone())).unwrap();
+        registry.register(Box::new(processed.clone())).unwrap();
+        registry.register(Box::new(failed.clone())).unwrap();
+        registry.register(Box::new(filtered.clone())).unwrap();
+        registry.register(Box::new(dedup.clone())).unwrap();
+        registry.register(Box::new(bm_api.clone())).unwrap();
+        registry.register(Box::new(op.clone())).unwrap();
+        registry.register(Box::new(reconnect.clone())).unwrap();
+        registry.register(Box::new(http.clone())).unwrap();
+
+        Self {
+            registry,
+            queue_depth,
+            queue_dropped,
+            processed,
+            failed,
+            filtered,
+            dedup,
+            bm_api,
+            op,
+            reconnect,
+            http,
+            queues: Mutex::new(vec![]),
+        }
+    }
+}
+
+//-----------------------------------------
+/// 注册一个队列，输出指标时读取其长度
+pub fn register_queue<F>(name: &str, f: F)
+where
+    F: Fn() -> usize + Send + Sync + 'static,
+{
+    let mut queues = METRICS.queues.lock().unwrap();
+    queues.push((name.to_string(), Box::new(f)));
+}
+
+pub fn register_deadqueue<T: Send + 'static>(name: &str, queue: &Arc<Queue<T>>) {
+    let queue = queue.clone();
+    register_queue(name, move || queue.len());
+}
+
+/// 不方便注册的队列(如被service独占的)，直接设置长度
+pub fn set_queue_depth(name: &str, depth: usize) {
+    METRICS
+        .queue_depth
+        .with_label_values(&[name])
+        .set(depth as i64);
+}
+
+pub fn inc_queue_dropped(name: &str) {
+    METRICS.queue_dropped.with_label_values(&[name]).inc();
+}
+
+pub fn inc_processed(service: &str) {
+    METRICS.processed.with_label_values(&[service]).inc();
+}
+
+pub fn inc_failed(service: &str) {
+    METRICS.failed.with_label_values(&[service]).inc();
+}
+
+/// kind: face/vehicle, reason: 被哪条规则过滤
+pub fn inc_filtered(kind: &str, reason: &str) {
+    METRICS.filtered.with_label_values(&[kind, reason]).inc();
+}
+
+/// action: suppress(重复的 track 被丢弃)
+pub fn inc_dedup(action: &str) {
+    METRICS.dedup.with_label_values(&[action]).inc();
+}
+
+pub fn observe_bm_api(method: &str, ok: bool, begin: Instant) {
+    let result = if ok { "ok" } else { "err" };
+    METRICS
+        .bm_api
+        .with_label_values(&[method, result])
+        .observe(begin.elapsed().as_secs_f64());
+}
+
+/// minio/mysql/rabbitmq 的操作耗时，返回的timer在drop时记录
+pub fn op_timer(backend: &str, op: &str) -> HistogramTimer {
+    METRICS.op.with_label_values(&[backend, op]).start_timer()
+}
+
+pub fn inc_reconnect(backend: &str) {
+    METRICS.reconnect.with_label_values(&[backend]).inc();
+}
+
+pub fn observe_http(path: &str, status: u16, begin: Instant) {
+    // 404 的path不可控，归为一类
+    let path = if status == 404 { "other" } else { path };
+    METRICS
+        .http
+        .with_label_values(&[path, status.to_string().as_str()])
+        .observe(begin.elapsed().as_secs_f64());
+}
+
+//-----------------------------------------
+pub fn gather() -> String {
+    {
+        let queues = METRICS.queues.lock().unwrap();
+        for (name, f) in queues.iter() {
+            set_queue_depth(name, f());
+        }
+    }
+
+    let encoder = TextEncoder::new();
+    let mut buf = vec![];
+    if let Err(e) = encoder.encode(&METRICS.registry.gather(), &mut buf) {
+        error!("error, metrics, encode, err: {:?}", e);
+    }
+    String::from_utf8(buf).unwrap_or_default()
+}
+
+/// axum handler: GET /metrics
+pub async fn metrics_handler() -> Response {
+    let mut res = (StatusCode::OK, gather()).into_response();
+    res.headers_mut().insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("text/plain; version=0.0.4"),
+    );
+    res
+}
@@ -119,3 +119,28 @@ pub fn get_cartrack_relate_binary_path(uuid: &str, ts: DateTime<Local>) -> Strin
     let ts_prefix = get_ts_prefix(ts);
     format!("{}/{}/{}_binary.jpg", ts_prefix, uuid, uuid)
 }
+
+//-------------------------------------
+/*
+转码后的图片
+acdef_bg.jpg -> acdef_bg.png
+缩略图
+acdef_bg.jpg -> acdef_bg_thumb.jpg
+ */
+
+/// 按图片格式替换扩展名
+pub fn replace_extension(path: &str, ext: &str) -> String {
+    match path.rfind('.') {
+        Some(pos) if !path[pos..].contains('/') => format!("{}.{}", &path[..pos], ext),
+        _ => format!("{}.{}", path, ext),
+    }
+}
+
+pub fn get_thumbnail_path(path: &str) -> String {
+    match path.rfind('.') {
+        Some(pos) if !path[pos..].contains('/') => {
+            format!("{}_thumb{}", &path[..pos], &path[pos..])
+        }
+        _ => format!("{}_thumb", path),
+    }
+}
@@ -1,12 +1,16 @@
 pub mod axum_log;
 pub mod delay_queue;
+pub mod track_aggregator;
 pub mod ip;
+pub mod json_file;
 pub mod mysql_util;
 pub mod service;
 pub mod time_format;
 pub mod utils;
 
+pub mod health;
 pub mod logger;
+pub mod metrics;
 pub mod rabbitmq;
 pub mod se5;
 
@@ -14,3 +18,5 @@ pub mod image;
 pub mod multipart_form;
 
 pub mod minio;
+pub mod sign;
+pub mod tls;
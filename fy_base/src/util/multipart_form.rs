@@ -1,12 +1,15 @@
 use indexmap::IndexMap;
 
-use axum::extract::Multipart;
-use bytes::Bytes;
+use axum::body::Body;
+use axum::extract::{FromRequest, Multipart, RequestParts};
+use axum::http::{header, Request};
+use bytes::{BufMut, Bytes, BytesMut};
 
 use log::warn;
 use std::fmt::Debug;
 use std::fmt::Formatter;
 use std::ops::Deref;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 #[derive(Debug)]
 pub enum MultipartFormItem {
@@ -264,3 +267,75 @@ pub async fn parse_multi_form(
 
     Ok(values)
 }
+
+/// 从完整的 body 解析，用于需要先保存原始请求的场景
+pub async fn parse_multi_form_bytes(
+    content_type: &str,
+    body: Bytes,
+) -> std::result::Result<MultipartFormValues, String> {
+    let req = Request::builder()
+        .header(header::CONTENT_TYPE, content_type)
+        .body(Body::from(body))
+        .map_err(|e| format!("parse_multi_form, {:?}", e))?;
+    let payload = Multipart::from_request(&mut RequestParts::new(req))
+        .await
+        .map_err(|e| format!("parse_multi_form, {:?}", e))?;
+    parse_multi_form(payload).await
+}
+
+//---------------------
+/// 生成 multipart/form-data 的 body，签名需要对完整的 body 计算 hash
+pub struct MultipartFormBuilder {
+    boundary: String,
+    buf: BytesMut,
+}
+
+impl Default for MultipartFormBuilder {
+    fn default() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |x| x.as_nanos());
+        Self {
+            boundary: format!("------------------------{:032x}", nanos),
+            buf: BytesMut::new(),
+        }
+    }
+}
+
+impl MultipartFormBuilder {
+    pub fn text(mut self, name: &str, value: &str) -> Self {
+        self.buf.put_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n",
+                self.boundary, name
+            )
+            .as_bytes(),
+        );
+        self.buf.put_slice(value.as_bytes());
+        self.buf.put_slice(b"\r\n");
+        self
+    }
+
+    pub fn file(mut self, name: &str, file_name: &str, mime: &str, data: &[u8]) -> Self {
+        self.buf.put_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: {}\r\n\r\n",
+                self.boundary, name, file_name, mime
+            )
+            .as_bytes(),
+        );
+        self.buf.put_slice(data);
+        self.buf.put_slice(b"\r\n");
+        self
+    }
+
+    /// 返回 (content type, body)
+    pub fn finish(mut self) -> (String, Bytes) {
+        self.buf
+            .put_slice(format!("--{}--\r\n", self.boundary).as_bytes());
+        (
+            format!("multipart/form-data; boundary={}", self.boundary),
+            self.buf.freeze(),
+        )
+    }
+}
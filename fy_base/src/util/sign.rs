@@ -0,0 +1,335 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use axum::body::{Body, HttpBody};
+use bytes::{Bytes, BytesMut};
+use chrono::Local;
+use hmac::{Hmac, Mac};
+use http::{header, HeaderMap, HeaderValue, Request};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{error, warn};
+
+use crate::util::tls;
+
+// 盒子请求签名:
+// HMAC-SHA256(secret, method + "\n" + path?query + "\n" + timestamp + "\n" + hex(sha256(body)))
+// secret 在 base_box 中配置，并预置到盒子上
+
+pub const HEADER_BOX: &str = "x-fy-box";
+pub const HEADER_TIMESTAMP: &str = "x-fy-timestamp";
+pub const HEADER_SIGNATURE: &str = "x-fy-signature";
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn string_to_sign(method: &str, path: &str, ts: i64, body: &[u8]) -> String {
+    let body_hash = hex::encode(Sha256::digest(body));
+    format!("{}\n{}\n{}\n{}", method.to_uppercase(), path, ts, body_hash)
+}
+
+fn new_mac(secret: &str, method: &str, path: &str, ts: i64, body: &[u8]) -> HmacSha256 {
+    // hmac 可以接受任意长度的 key
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(string_to_sign(method, path, ts, body).as_bytes());
+    mac
+}
+
+pub fn sign(secret: &str, method: &str, path: &str, ts: i64, body: &[u8]) -> String {
+    hex::encode(
+        new_mac(secret, method, path, ts, body)
+            .finalize()
+            .into_bytes(),
+    )
+}
+
+/// url 中参与签名的部分: path + query
+pub fn sign_path(url: &str) -> String {
+    match url::Url::parse(url) {
+        Ok(v) => match v.query() {
+            Some(q) => format!("{}?{}", v.path(), q),
+            None => v.path().to_string(),
+        },
+        Err(_) => url.to_string(),
+    }
+}
+
+//---------------------
+/// 配置中的密钥，Debug 输出时隐藏，避免打印配置时泄露
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            write!(f, "\"\"")
+        } else {
+            write!(f, "\"***\"")
+        }
+    }
+}
+
+/// 盒子端，给请求加上签名头
+#[derive(Debug, Clone)]
+pub struct Signer {
+    box_id: String,
+    secret: Secret,
+}
+
+impl Signer {
+    pub fn new(box_id: &str, secret: &Secret) -> Self {
+        Self {
+            box_id: box_id.to_string(),
+            secret: secret.clone(),
+        }
+    }
+
+    pub fn headers(&self, method: &str, url: &str, body: &[u8]) -> HeaderMap {
+        let ts = Local::now().timestamp();
+        let signature = sign(self.secret.expose(), method, &sign_path(url), ts, body);
+
+        let mut headers = HeaderMap::new();
+        if let Ok(v) = HeaderValue::from_str(&self.box_id) {
+            headers.insert(HEADER_BOX, v);
+        }
+        headers.insert(HEADER_TIMESTAMP, HeaderValue::from(ts));
+        if let Ok(v) = HeaderValue::from_str(&signature) {
+            headers.insert(HEADER_SIGNATURE, v);
+        }
+        headers
+    }
+}
+
+//---------------------
+#[derive(Debug)]
+pub enum VerifyError {
+    Missing(&'static str),
+    UnknownBox(String),
+    CertMismatch(String), // 请求中的盒子与客户端证书不一致
+    Expired(i64),
+    Replayed,
+    BadSignature,
+}
+
+pub fn get_box_id(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(HEADER_BOX)
+        .and_then(|x| x.to_str().ok())
+        .filter(|x| !x.is_empty())
+        .map(|x| x.to_string())
+}
+
+/// 服务端校验签名。时间戳必须在 window 秒内，window 内同一个签名只能用一次
+pub struct Verifier {
+    window: i64,
+    seen: Mutex<HashMap<String, i64>>,
+}
+
+impl Verifier {
+    pub fn new(window: u64) -> Self {
+        Self {
+            window: window as i64,
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn verify(
+        &self,
+        secret: &str,
+        method: &str,
+        path: &str,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<(), VerifyError> {
+        let ts = headers
+            .get(HEADER_TIMESTAMP)
+            .and_then(|x| x.to_str().ok())
+            .and_then(|x| x.parse::<i64>().ok())
+            .ok_or(VerifyError::Missing(HEADER_TIMESTAMP))?;
+        let signature = headers
+            .get(HEADER_SIGNATURE)
+            .and_then(|x| x.to_str().ok())
+            .and_then(|x| hex::decode(x).ok())
+            .ok_or(VerifyError::Missing(HEADER_SIGNATURE))?;
+
+        let now = Local::now().timestamp();
+        if (now - ts).abs() > self.window {
+            return Err(VerifyError::Expired(ts));
+        }
+
+        new_mac(secret, method, path, ts, body)
+            .verify_slice(&signature)
+            .map_err(|_| VerifyError::BadSignature)?;
+
+        // 签名正确后再记录，避免伪造的请求占满缓存
+        let mut seen = self.seen.lock().unwrap();
+        seen.retain(|_, v| now - *v <= self.window);
+        if seen.insert(hex::encode(&signature), ts).is_some() {
+            return Err(VerifyError::Replayed);
+        }
+        Ok(())
+    }
+}
+
+//---------------------
+/// 按 box id 缓存 secret，None 表示盒子不存在，空字符串表示没有配置 secret
+pub struct SecretCache {
+    ttl: Duration,
+    items: Mutex<HashMap<String, (Option<String>, Instant)>>,
+}
+
+impl SecretCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            items: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(&self, box_id: &str) -> Option<Option<String>> {
+        let items = self.items.lock().unwrap();
+        match items.get(box_id) {
+            Some((v, ts)) if ts.elapsed() < self.ttl => Some(v.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn put(&self, box_id: &str, secret: Option<String>) {
+        let mut items = self.items.lock().unwrap();
+        items.retain(|_, (_, ts)| ts.elapsed() < self.ttl);
+        items.insert(box_id.to_string(), (secret, Instant::now()));
+    }
+}
+
+//---------------------
+/// 校验通过的盒子 hw_id，verify_box 放到请求的 extensions 中
+#[derive(Debug, Clone)]
+pub struct AuthBox(pub String);
+
+#[derive(Debug)]
+pub enum AuthError<E> {
+    Verify(VerifyError),
+    TooLarge,
+    ReadBody(String),
+    Lookup(E), // 查询盒子 secret 失败
+}
+
+/// 服务端校验盒子身份: mTLS 时客户端证书的 CN 为盒子的 hw_id，开启签名校验时校验请求签名。
+/// 都没有开启时直接放行，否则把 AuthBox 放到返回的请求中。
+/// find_secret 在 secrets 中没有缓存时查询盒子的 secret，签名校验时 body 最长 max_body
+pub async fn verify_box<F, Fut, E>(
+    mut req: Request<Body>,
+    verifier: Option<&Verifier>,
+    secrets: &SecretCache,
+    max_body: u64,
+    find_secret: F,
+) -> Result<Request<Body>, AuthError<E>>
+where
+    F: FnOnce(String) -> Fut,
+    Fut: Future<Output = Result<Option<String>, E>>,
+    E: fmt::Debug,
+{
+    let cert_id = tls::peer_hw_id(&req);
+    if cert_id.is_none() && verifier.is_none() {
+        return Ok(req);
+    }
+
+    let box_id = get_box_id(req.headers())
+        .or_else(|| cert_id.clone())
+        .ok_or(AuthError::Verify(VerifyError::Missing(HEADER_BOX)))?;
+    if cert_id.as_ref().is_some_and(|x| *x != box_id) {
+        warn!("warn, verify_box, box: {}, cert: {:?}", box_id, cert_id);
+        return Err(AuthError::Verify(VerifyError::CertMismatch(box_id)));
+    }
+
+    let secret = match secrets.get(&box_id) {
+        Some(v) => v,
+        None => {
+            let secret = match find_secret(box_id.clone()).await {
+                Ok(v) => v,
+                Err(e) => {
+                    error!("error, verify_box, find box: {}, err: {:?}", box_id, e);
+                    return Err(AuthError::Lookup(e));
+                }
+            };
+            secrets.put(&box_id, secret.clone());
+            secret
+        }
+    };
+    let secret = match secret {
+        Some(v) => v,
+        None => {
+            warn!("warn, verify_box, unknown box: {}", box_id);
+            return Err(AuthError::Verify(VerifyError::UnknownBox(box_id)));
+        }
+    };
+
+    let verifier = match verifier {
+        Some(v) => v,
+        None => {
+            req.extensions_mut().insert(AuthBox(box_id));
+            return Ok(req);
+        }
+    };
+    if secret.is_empty() {
+        warn!("warn, verify_box, box: {} has no secret", box_id);
+        return Err(AuthError::Verify(VerifyError::UnknownBox(box_id)));
+    }
+
+    let (mut parts, body) = req.into_parts();
+    let body = read_body(body, max_body, &parts.headers).await?;
+
+    let path = parts
+        .uri
+        .path_and_query()
+        .map(|x| x.as_str())
+        .unwrap_or_else(|| parts.uri.path());
+    if let Err(e) = verifier.verify(&secret, parts.method.as_str(), path, &parts.headers, &body) {
+        warn!("warn, verify_box, box: {}, err: {:?}", box_id, e);
+        return Err(AuthError::Verify(e));
+    }
+
+    parts.extensions.insert(AuthBox(box_id));
+    Ok(Request::from_parts(parts, Body::from(body)))
+}
+
+// 签名校验时 body 要缓存一次，超长的直接拒绝，没有 Content-Length 时边读边检查
+async fn read_body<E>(
+    mut body: Body,
+    max: u64,
+    headers: &HeaderMap,
+) -> Result<Bytes, AuthError<E>> {
+    let length = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| x.parse::<u64>().ok());
+    if length.is_some_and(|x| x > max) {
+        return Err(AuthError::TooLarge);
+    }
+
+    let mut buf = BytesMut::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|e| {
+            error!("error, verify_box, read body, err: {:?}", e);
+            AuthError::ReadBody(format!("{:?}", e))
+        })?;
+        if (buf.len() + chunk.len()) as u64 > max {
+            return Err(AuthError::TooLarge);
+        }
+        buf.extend_from_slice(&chunk);
+    }
+    Ok(buf.freeze())
+}
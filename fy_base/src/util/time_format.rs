@@ -21,6 +21,39 @@ pub mod long_ts_format {
     }
 }
 
+pub mod opt_long_ts_format {
+    use chrono::prelude::*;
+    use serde::{self, Deserialize, Deserializer, Serializer};
+
+    use crate::util::utils;
+
+    pub fn serialize<S>(date: &Option<DateTime<Local>>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match date {
+            Some(v) => {
+                let s = format!("{}", v.format(utils::DATETIME_FMT_LONG));
+                serializer.serialize_some(&s)
+            }
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<DateTime<Local>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = Option::<String>::deserialize(deserializer)?;
+        match s {
+            Some(v) => utils::parse_localtime_str(&v, utils::DATETIME_FMT_LONG)
+                .map(Some)
+                .map_err(serde::de::Error::custom),
+            None => Ok(None),
+        }
+    }
+}
+
 pub mod short_ts_format {
     use chrono::prelude::*;
     use serde::{self, Deserialize, Deserializer, Serializer};
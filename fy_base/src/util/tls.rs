@@ -0,0 +1,371 @@
+use std::fs;
+use std::io::{self, BufReader};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+
+use axum::extract::connect_info::Connected;
+use axum::extract::ConnectInfo;
+use axum::http::Request;
+use hyper::server::accept::{self, Accept};
+use reqwest::ClientBuilder;
+use rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+use rustls::server::{
+    AllowAnyAnonymousOrAuthenticatedClient, AllowAnyAuthenticatedClient, NoClientAuth,
+};
+use rustls::{Certificate, ClientConfig, PrivateKey, RootCertStore, ServerConfig, ServerName};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{debug, warn};
+
+pub type TlsResult<T> = Result<T, String>;
+
+// 握手超时，避免半开的连接占用资源
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+//---------------------
+/// http 客户端的 tls 配置，全部为空时使用系统根证书校验服务端
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TlsClientCfg {
+    pub ca_file: Option<String>,    // PEM 格式的 CA 证书，配置后只信任这些 CA
+    pub pin_sha256: Option<String>, // 服务端证书 DER 的 sha256(hex)，没有 ca_file 时只校验指纹
+    pub cert_file: Option<String>,  // mTLS 客户端证书，CN 为盒子 hw_id
+    pub key_file: Option<String>,
+}
+
+impl TlsClientCfg {
+    pub fn validate(&self) -> TlsResult<()> {
+        if self.cert_file.is_some() != self.key_file.is_some() {
+            return Err("cert_file and key_file must be set together".to_string());
+        }
+        if let Some(ref v) = self.pin_sha256 {
+            parse_pin(v)?;
+        }
+        Ok(())
+    }
+}
+
+/// 加载好的客户端 tls 配置，所有 reqwest client 都从这里创建
+#[derive(Clone, Default)]
+pub struct ClientTls {
+    config: Option<ClientConfig>,
+}
+
+impl ClientTls {
+    pub fn load(cfg: &TlsClientCfg) -> TlsResult<Self> {
+        cfg.validate()?;
+        if cfg.ca_file.is_none() && cfg.pin_sha256.is_none() && cfg.cert_file.is_none() {
+            return Ok(Self::default());
+        }
+
+        let webpki = if cfg.ca_file.is_some() || cfg.pin_sha256.is_none() {
+            let roots = match cfg.ca_file {
+                Some(ref v) => load_roots(v)?,
+                None => load_native_roots()?,
+            };
+            Some(WebPkiVerifier::new(roots, None))
+        } else {
+            None
+        };
+        let pin = match cfg.pin_sha256 {
+            Some(ref v) => Some(parse_pin(v)?),
+            None => None,
+        };
+
+        let builder = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(Arc::new(PinnedVerifier { webpki, pin }));
+        let config = match (&cfg.cert_file, &cfg.key_file) {
+            (Some(cert), Some(key)) => builder
+                .with_client_auth_cert(load_certs(cert)?, load_key(key)?)
+                .map_err(|e| format!("client cert: {:?}", e))?,
+            _ => builder.with_no_client_auth(),
+        };
+
+        Ok(Self {
+            config: Some(config),
+        })
+    }
+
+    /// 没有配置时使用 reqwest 默认的 tls 实现
+    pub fn builder(&self) -> ClientBuilder {
+        let builder = reqwest::Client::builder();
+        match self.config {
+            Some(ref v) => builder.use_preconfigured_tls(v.clone()),
+            None => builder,
+        }
+    }
+}
+
+// 先按 CA 校验证书链，再校验证书指纹
+struct PinnedVerifier {
+    webpki: Option<WebPkiVerifier>,
+    pin: Option<Vec<u8>>,
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if let Some(ref v) = self.webpki {
+            v.verify_server_cert(
+                end_entity,
+                intermediates,
+                server_name,
+                scts,
+                ocsp_response,
+                now,
+            )?;
+        }
+        if let Some(ref pin) = self.pin {
+            if Sha256::digest(&end_entity.0).as_slice() != pin.as_slice() {
+                return Err(rustls::Error::General(
+                    "server certificate fingerprint mismatch".to_string(),
+                ));
+            }
+        }
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+fn parse_pin(pin: &str) -> TlsResult<Vec<u8>> {
+    // 兼容 openssl 输出的 AA:BB:... 格式
+    let v = hex::decode(pin.replace(':', "")).map_err(|e| format!("pin_sha256: {:?}", e))?;
+    if v.len() != 32 {
+        return Err(format!("pin_sha256 length: {}", v.len()));
+    }
+    Ok(v)
+}
+
+//---------------------
+/// http 服务端的 tls 配置
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TlsServerCfg {
+    pub enable: bool,
+    pub cert_file: String,
+    pub key_file: String,
+    pub client_ca_file: Option<String>, // 校验客户端证书的 CA，不配置时不要求客户端证书
+    pub require_client_cert: bool,      // true 时没有客户端证书的连接直接拒绝
+}
+
+impl TlsServerCfg {
+    pub fn validate(&self) -> TlsResult<()> {
+        if !self.enable {
+            return Ok(());
+        }
+        if self.cert_file.is_empty() || self.key_file.is_empty() {
+            return Err("cert_file and key_file must be set".to_string());
+        }
+        if self.require_client_cert && self.client_ca_file.is_none() {
+            return Err("require_client_cert needs client_ca_file".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn load(&self) -> TlsResult<Arc<ServerConfig>> {
+        self.validate()?;
+
+        let verifier = match self.client_ca_file {
+            Some(ref v) if self.require_client_cert => {
+                AllowAnyAuthenticatedClient::new(load_roots(v)?).boxed()
+            }
+            Some(ref v) => AllowAnyAnonymousOrAuthenticatedClient::new(load_roots(v)?).boxed(),
+            None => NoClientAuth::boxed(),
+        };
+        let mut config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_client_cert_verifier(verifier)
+            .with_single_cert(load_certs(&self.cert_file)?, load_key(&self.key_file)?)
+            .map_err(|e| format!("server cert: {:?}", e))?;
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+        Ok(Arc::new(config))
+    }
+}
+
+/// 连接信息，客户端证书的 CN 为盒子的 hw_id
+#[derive(Debug, Clone)]
+pub struct PeerInfo {
+    pub addr: SocketAddr,
+    pub hw_id: Option<String>,
+}
+
+impl Connected<&TlsConn> for PeerInfo {
+    fn connect_info(target: &TlsConn) -> Self {
+        target.peer.clone()
+    }
+}
+
+impl Connected<&hyper::server::conn::AddrStream> for PeerInfo {
+    fn connect_info(target: &hyper::server::conn::AddrStream) -> Self {
+        Self {
+            addr: target.remote_addr(),
+            hw_id: None,
+        }
+    }
+}
+
+/// 客户端证书中的 hw_id，不是 tls 连接或者没有客户端证书时为 None
+pub fn peer_hw_id<B>(req: &Request<B>) -> Option<String> {
+    req.extensions()
+        .get::<ConnectInfo<PeerInfo>>()
+        .and_then(|x| x.0.hw_id.clone())
+}
+
+/// 握手完成的 tls 连接
+pub struct TlsConn {
+    inner: TlsStream<TcpStream>,
+    peer: PeerInfo,
+}
+
+impl AsyncRead for TlsConn {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for TlsConn {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// 接受 tcp 连接并完成 tls 握手，握手失败的连接只记录日志。
+/// 返回值交给 hyper::Server::builder
+pub fn incoming(
+    listener: TcpListener,
+    config: Arc<ServerConfig>,
+) -> impl Accept<Conn = TlsConn, Error = io::Error> {
+    let acceptor = TlsAcceptor::from(config);
+    let (tx, rx) = mpsc::channel::<io::Result<TlsConn>>(64);
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, addr) = tokio::select! {
+                _ = tx.closed() => {
+                    break;
+                }
+                res = listener.accept() => match res {
+                    Ok(v) => v,
+                    Err(e) => {
+                        warn!("warn, tls incoming, accept, err: {:?}", e);
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                }
+            };
+
+            let acceptor = acceptor.clone();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let inner =
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(v)) => v,
+                        Ok(Err(e)) => {
+                            debug!("tls incoming, {}, handshake err: {:?}", addr, e);
+                            return;
+                        }
+                        Err(_) => {
+                            debug!("tls incoming, {}, handshake timeout", addr);
+                            return;
+                        }
+                    };
+
+                let hw_id = inner
+                    .get_ref()
+                    .1
+                    .peer_certificates()
+                    .and_then(|x| x.first())
+                    .and_then(|x| get_common_name(&x.0));
+                let peer = PeerInfo { addr, hw_id };
+                let _ = tx.send(Ok(TlsConn { inner, peer })).await;
+            });
+        }
+    });
+
+    accept::from_stream(ReceiverStream::new(rx))
+}
+
+//---------------------
+fn get_common_name(der: &[u8]) -> Option<String> {
+    let (_, cert) = x509_parser::parse_x509_certificate(der).ok()?;
+    let cn = cert.subject().iter_common_name().next()?;
+    cn.as_str().ok().map(|x| x.to_string())
+}
+
+fn load_certs(path: &str) -> TlsResult<Vec<Certificate>> {
+    let file = fs::File::open(path).map_err(|e| format!("open {}: {:?}", path, e))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .map_err(|e| format!("read {}: {:?}", path, e))?;
+    if certs.is_empty() {
+        return Err(format!("no certificate in {}", path));
+    }
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+fn load_key(path: &str) -> TlsResult<PrivateKey> {
+    let file = fs::File::open(path).map_err(|e| format!("open {}: {:?}", path, e))?;
+    let mut reader = BufReader::new(file);
+    loop {
+        match rustls_pemfile::read_one(&mut reader)
+            .map_err(|e| format!("read {}: {:?}", path, e))?
+        {
+            Some(rustls_pemfile::Item::RSAKey(v))
+            | Some(rustls_pemfile::Item::PKCS8Key(v))
+            | Some(rustls_pemfile::Item::ECKey(v)) => return Ok(PrivateKey(v)),
+            Some(_) => {}
+            None => return Err(format!("no private key in {}", path)),
+        }
+    }
+}
+
+fn load_roots(path: &str) -> TlsResult<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(path)? {
+        roots
+            .add(&cert)
+            .map_err(|e| format!("add ca {}: {:?}", path, e))?;
+    }
+    Ok(roots)
+}
+
+fn load_native_roots() -> TlsResult<RootCertStore> {
+    let certs =
+        rustls_native_certs::load_native_certs().map_err(|e| format!("native certs: {:?}", e))?;
+    let mut roots = RootCertStore::empty();
+    for cert in certs {
+        // 系统中个别无法解析的证书忽略
+        let _ = roots.add(&Certificate(cert.0));
+    }
+    Ok(roots)
+}
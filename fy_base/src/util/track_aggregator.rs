@@ -0,0 +1,225 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use tokio_util::time::delay_queue::DelayQueue;
+use tracing::{debug, warn};
+
+use crate::util::delay_queue::{DReceiver, DSender, DelayQueueChan};
+
+// ------------------- trait -------------------
+/// 一种track(人脸/车辆等)的聚合规则
+pub trait TrackHandler {
+    type Item;
+    type Output;
+
+    /// 日志中使用的名称
+    fn name(&self) -> &str;
+
+    fn track_id(&self, item: &Self::Item) -> String;
+
+    /// 新track的第一批数据是否已满足要求，满足则马上输出，不用等待 ready_delay
+    fn is_ready(&self, item: &Self::Item) -> bool;
+
+    /// 将同一track后续的数据合并进来
+    fn merge(&self, track: &mut Self::Item, item: Self::Item);
+
+    /// 聚合完成，生成输出
+    fn finalize(&self, track: Self::Item) -> Self::Output;
+}
+
+// ------------------- structs -------------------
+/// 按track_id聚合同一track分多次上报的数据
+/// 1) 第一批数据 is_ready，直接输出
+/// 2) 否则等待，后续数据到达(合并后)或 ready_delay 超时，输出
+/// 3) 输出后，clean_delay 内同一track_id的数据忽略
+pub struct TrackAggregator<H: TrackHandler> {
+    handler: H,
+    ready_delay: Duration,
+    clean_delay: Duration,
+
+    // 还未输出的track
+    tracks: HashMap<String, H::Item>,
+    // clean_delay内出现过的track_id
+    track_ids: HashSet<String>,
+
+    ready_tx: DSender<String>,
+    ready_rx: DReceiver<String>,
+    clean_tx: DSender<String>,
+    clean_rx: DReceiver<String>,
+}
+
+// ------------------- impls -------------------
+impl<H: TrackHandler> TrackAggregator<H> {
+    pub fn new(handler: H, ready_delay: Duration, clean_delay: Duration) -> Self {
+        let (ready_tx, ready_rx) = DelayQueue::new().channel();
+        let (clean_tx, clean_rx) = DelayQueue::new().channel();
+
+        Self {
+            handler,
+            ready_delay,
+            clean_delay,
+            tracks: HashMap::new(),
+            track_ids: HashSet::new(),
+            ready_tx,
+            ready_rx,
+            clean_tx,
+            clean_rx,
+        }
+    }
+
+    /// 未输出的track数量
+    pub fn pending(&self) -> usize {
+        self.tracks.len()
+    }
+
+    /// 接收一批数据，如果track聚合完成，返回输出
+    pub async fn push(&mut self, item: H::Item) -> Option<H::Output> {
+        let id = self.handler.track_id(&item);
+        let name = self.handler.name();
+
+        if let Some(mut track) = self.tracks.remove(&id) {
+            // 已有的track，合并后输出
+            debug!("{}, {} is appended", name, id);
+            self.handler.merge(&mut track, item);
+            return Some(self.handler.finalize(track));
+        }
+
+        // 如果在track_ids中有，但是在 tracks中没有，说明该track的数据已经输出，这个track的后续数据忽略
+        if self.track_ids.contains(&id) {
+            warn!("warn, {}, {}, has put to next, skip it", name, id);
+            return None;
+        }
+
+        // 新的track
+        debug!("{}, {} is newed", name, id);
+        self.track_ids.insert(id.clone());
+        let _ = self.clean_tx.send((id.clone(), self.clean_delay)).await;
+
+        if self.handler.is_ready(&item) {
+            return Some(self.handler.finalize(item));
+        }
+
+        self.tracks.insert(id.clone(), item);
+        let _ = self.ready_tx.send((id, self.ready_delay)).await;
+        None
+    }
+
+    /// 等待超时事件，ready超时的track返回输出
+    pub async fn next_timeout(&mut self) -> Option<H::Output> {
+        tokio::select! {
+            Some(Ok(expired)) = self.ready_rx.recv() => {
+                self.process_ready_timeout(expired.into_inner())
+            }
+            Some(Ok(expired)) = self.clean_rx.recv() => {
+                self.process_clean_timeout(expired.into_inner());
+                None
+            }
+            else => None,
+        }
+    }
+
+    fn process_ready_timeout(&mut self, id: String) -> Option<H::Output> {
+        debug!("{}, process ready_timeout: {}", self.handler.name(), id);
+        self.tracks
+            .remove(&id)
+            .map(|track| self.handler.finalize(track))
+    }
+
+    fn process_clean_timeout(&mut self, id: String) {
+        debug!("{}, process clean_timeout: {}", self.handler.name(), id);
+        self.track_ids.remove(&id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // (track_id, 数据, 第一批是否已满足要求)
+    type Item = (String, Vec<u32>, bool);
+
+    struct TestHandler;
+
+    impl TrackHandler for TestHandler {
+        type Item = Item;
+        type Output = (String, Vec<u32>);
+
+        fn name(&self) -> &str {
+            "test"
+        }
+
+        fn track_id(&self, item: &Item) -> String {
+            item.0.clone()
+        }
+
+        fn is_ready(&self, item: &Item) -> bool {
+            item.2
+        }
+
+        fn merge(&self, track: &mut Item, item: Item) {
+            track.1.extend(item.1);
+        }
+
+        fn finalize(&self, track: Item) -> Self::Output {
+            (track.0, track.1)
+        }
+    }
+
+    const READY_DELAY: Duration = Duration::from_millis(50);
+    const CLEAN_DELAY: Duration = Duration::from_millis(100);
+
+    fn item(id: &str, v: u32, ready: bool) -> Item {
+        (id.to_string(), vec![v], ready)
+    }
+
+    fn new_aggregator() -> TrackAggregator<TestHandler> {
+        TrackAggregator::new(TestHandler, READY_DELAY, CLEAN_DELAY)
+    }
+
+    async fn next_timeout(agg: &mut TrackAggregator<TestHandler>) -> Option<(String, Vec<u32>)> {
+        tokio::time::timeout(Duration::from_secs(2), agg.next_timeout())
+            .await
+            .expect("no timeout event")
+    }
+
+    #[tokio::test]
+    async fn flush_after_ready_delay() {
+        let mut agg = new_aggregator();
+
+        assert_eq!(agg.push(item("t1", 1, false)).await, None);
+        assert_eq!(agg.pending(), 1);
+
+        let out = next_timeout(&mut agg).await;
+        assert_eq!(out, Some(("t1".to_string(), vec![1])));
+        assert_eq!(agg.pending(), 0);
+    }
+
+    #[tokio::test]
+    async fn merge_on_append() {
+        let mut agg = new_aggregator();
+
+        assert_eq!(agg.push(item("t1", 1, false)).await, None);
+        let out = agg.push(item("t1", 2, false)).await;
+        assert_eq!(out, Some(("t1".to_string(), vec![1, 2])));
+        assert_eq!(agg.pending(), 0);
+
+        // 已经输出，ready 超时不再输出
+        assert_eq!(next_timeout(&mut agg).await, None);
+    }
+
+    #[tokio::test]
+    async fn skip_resent_track_until_clean_delay() {
+        let mut agg = new_aggregator();
+
+        let out = agg.push(item("t1", 1, true)).await;
+        assert_eq!(out, Some(("t1".to_string(), vec![1])));
+
+        // clean_delay 内重发的忽略
+        assert_eq!(agg.push(item("t1", 2, true)).await, None);
+
+        // clean 超时后作为新的track
+        assert_eq!(next_timeout(&mut agg).await, None);
+        let out = agg.push(item("t1", 3, true)).await;
+        assert_eq!(out, Some(("t1".to_string(), vec![3])));
+    }
+}
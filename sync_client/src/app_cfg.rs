@@ -1,9 +1,13 @@
-use crate::error::AppResult;
+use crate::error::{AppError, AppResult};
 use chrono::{DateTime, Local, TimeZone};
-use fy_base::util::time_format::long_ts_format;
+use fy_base::util::sign::Secret;
+use fy_base::util::time_format::{long_ts_format, opt_long_ts_format};
+use fy_base::util::tls::TlsClientCfg;
 use serde::{Deserialize, Serialize};
 use std::fs;
+use std::io::Write;
 use std::path::Path;
+use tracing::warn;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct AppCfgVersion {
@@ -23,6 +27,12 @@ pub struct AppCfgLog {
 pub struct AppCfgApi {
     pub grab_url: String,
     pub recg_url: String,
+    pub tls: TlsClientCfg, // 算法服务为 https 时使用
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AppCfgHttp {
+    pub addr: String, // 只用于 /metrics
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -30,16 +40,49 @@ pub struct AppCfgSyncServer {
     pub db_sync: String,
     pub person_sync: String,
     pub camera_sync: String,
+    pub plate_sync: String,
+    // 对账接口
+    pub db_list: String,
+    pub camera_list: String,
+    pub person_ids: String,
+    pub person_list: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AppCfgSyncPerson {
+    pub concurrency: usize, // 同时处理的批次数
+    pub batch: usize,       // 每个批次的人员数，新增的人员一次 create_persons
+    pub max_loop: u32,      // 每次同步最多取多少页，0 表示不限制
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AppCfgReconcile {
+    // 是否定时对账，不开启时也可以通过 rabbitmq 命令触发
+    pub enable: bool,
+    pub interval: u64, // 分钟
+    pub batch: usize,  // 每次从服务端取多少个人员
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct AppCfgSync {
     pub sync_log: String,
+    // 车牌布控名单，供 box_agent 读取
+    pub plate_file: String,
+    // 摄像头过滤规则，供 box_agent 读取
+    pub camera_filter_file: String,
+    // 摄像头采集时间表，供 box_agent 读取，sync_client 按时间表启用/停用摄像头
+    pub camera_schedule_file: String,
     pub camera_upload: Option<String>,
     pub server: AppCfgSyncServer,
+    // 请求签名，和 base_box.secret 一致，不配置时不签名
+    pub secret: Option<Secret>,
+    pub tls: TlsClientCfg,
     pub heartbeat: u64,
     // 心跳间隔
-    pub sync_ttl: u64, // 多久触发同步
+    pub sync_ttl: u64,       // 多久触发同步
+    pub schedule_check: u64, // 秒，检查摄像头时间表的间隔
+    pub person: AppCfgSyncPerson,
+    pub reconcile: AppCfgReconcile,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -70,6 +113,7 @@ pub struct AppCfg {
     pub log: AppCfgLog,
     pub api: AppCfgApi,
     pub sync: AppCfgSync,
+    pub http: AppCfgHttp,
     pub rabbitmq: AppCfgRabbitMq,
     pub hw_id: Option<String>,
 }
@@ -82,6 +126,26 @@ impl AppCfg {
     }
 
     pub fn validate(&self) -> AppResult<()> {
+        if self.sync.schedule_check == 0 {
+            return Err(AppError::new("sync.schedule_check must be > 0"));
+        }
+        if self.sync.person.concurrency == 0 || self.sync.person.batch == 0 {
+            return Err(AppError::new(
+                "sync.person.concurrency and sync.person.batch must be > 0",
+            ));
+        }
+        if self.sync.reconcile.enable && self.sync.reconcile.interval == 0 {
+            return Err(AppError::new("sync.reconcile.interval must be > 0"));
+        }
+        if self.sync.reconcile.batch == 0 {
+            return Err(AppError::new("sync.reconcile.batch must be > 0"));
+        }
+        if let Err(e) = self.api.tls.validate() {
+            return Err(AppError::new(&format!("api.tls: {}", e)));
+        }
+        if let Err(e) = self.sync.tls.validate() {
+            return Err(AppError::new(&format!("sync.tls: {}", e)));
+        }
         Ok(())
     }
 }
@@ -93,6 +157,9 @@ pub struct AppSyncLogDb {
     #[serde(with = "long_ts_format")]
     pub last_ts: DateTime<Local>,
     pub last_id: String,
+    // 服务端返回的游标，下次请求原样带回
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -100,6 +167,9 @@ pub struct AppSyncLogPerson {
     #[serde(with = "long_ts_format")]
     pub last_ts: DateTime<Local>,
     pub last_id: String,
+    // 服务端返回的游标，下次请求原样带回
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -107,6 +177,19 @@ pub struct AppSyncLogCamera {
     #[serde(with = "long_ts_format")]
     pub last_ts: DateTime<Local>,
     pub last_id: String,
+    // 服务端返回的游标，下次请求原样带回
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AppSyncLogPlate {
+    #[serde(with = "long_ts_format")]
+    pub last_ts: DateTime<Local>,
+    pub last_id: String,
+    // 服务端返回的游标，下次请求原样带回
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
 }
 //----------------------
 
@@ -115,6 +198,7 @@ impl Default for AppSyncLogDb {
         Self {
             last_ts: Local.timestamp(0, 0),
             last_id: "".into(),
+            cursor: None,
         }
     }
 }
@@ -124,6 +208,7 @@ impl Default for AppSyncLogPerson {
         Self {
             last_ts: Local.timestamp(0, 0),
             last_id: "".into(),
+            cursor: None,
         }
     }
 }
@@ -133,6 +218,17 @@ impl Default for AppSyncLogCamera {
         Self {
             last_ts: Local.timestamp(0, 0),
             last_id: "".into(),
+            cursor: None,
+        }
+    }
+}
+
+impl Default for AppSyncLogPlate {
+    fn default() -> Self {
+        Self {
+            last_ts: Local.timestamp(0, 0),
+            last_id: "".into(),
+            cursor: None,
         }
     }
 }
@@ -142,7 +238,13 @@ pub struct AppSyncLog {
     pub db: AppSyncLogDb,
     pub person: AppSyncLogPerson,
     pub camera: AppSyncLogCamera,
+    // 老版本的 sync_log 中没有
+    #[serde(default)]
+    pub plate: AppSyncLogPlate,
     pub hw_id: String,
+    // 各类数据的同步历史，随心跳上报
+    #[serde(default)]
+    pub history: AppSyncHistory,
 }
 
 impl Default for AppSyncLog {
@@ -151,21 +253,126 @@ impl Default for AppSyncLog {
             db: Default::default(),
             person: Default::default(),
             camera: Default::default(),
+            plate: Default::default(),
             hw_id: "".to_string(),
+            history: Default::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SyncEntity {
+    Db,
+    Person,
+    Camera,
+    Plate,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AppSyncHistoryItem {
+    // 最近一次同步完成的时间
+    #[serde(default, with = "opt_long_ts_format")]
+    pub last_success: Option<DateTime<Local>>,
+
+    // 最近一次同步失败的时间和原因
+    #[serde(default, with = "opt_long_ts_format")]
+    pub last_error_ts: Option<DateTime<Local>>,
+    #[serde(default)]
+    pub last_error: Option<String>,
+
+    // 最近一次同步应用的记录数，以及累计的记录数
+    #[serde(default)]
+    pub last_applied: u64,
+    #[serde(default)]
+    pub total_applied: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AppSyncHistory {
+    #[serde(default)]
+    pub db: AppSyncHistoryItem,
+    #[serde(default)]
+    pub person: AppSyncHistoryItem,
+    #[serde(default)]
+    pub camera: AppSyncHistoryItem,
+    #[serde(default)]
+    pub plate: AppSyncHistoryItem,
+}
+
+impl AppSyncHistory {
+    pub fn get_mut(&mut self, entity: SyncEntity) -> &mut AppSyncHistoryItem {
+        match entity {
+            SyncEntity::Db => &mut self.db,
+            SyncEntity::Person => &mut self.person,
+            SyncEntity::Camera => &mut self.camera,
+            SyncEntity::Plate => &mut self.plate,
         }
     }
 }
 
 impl AppSyncLog {
-    pub fn load<P: AsRef<Path>>(path: P) -> AppResult<Self> {
+    // 上一代的 sync_log，主文件损坏时使用
+    pub fn backup_path(path: &str) -> String {
+        format!("{}.bak", path)
+    }
+
+    pub fn exists(path: &str) -> bool {
+        Path::new(path).exists() || Path::new(&Self::backup_path(path)).exists()
+    }
+
+    fn load_file<P: AsRef<Path>>(path: P) -> AppResult<Self> {
         let file = fs::File::open(path)?;
         let cfg = serde_json::from_reader(file)?;
         Ok(cfg)
     }
 
-    pub fn save<P: AsRef<Path>>(&self, path: P) -> AppResult<()> {
+    /// 读取 sync_log，主文件不存在或损坏(如写入时掉电)时读取上一代
+    pub fn load(path: &str) -> AppResult<Self> {
+        let err = match Self::load_file(path) {
+            Ok(v) => {
+                return Ok(v);
+            }
+            Err(e) => e,
+        };
+
+        let backup = Self::backup_path(path);
+        match Self::load_file(&backup) {
+            Ok(v) => {
+                warn!(
+                    "warn, load sync_log: {}, err: {}, use backup: {}",
+                    path, err, backup
+                );
+                Ok(v)
+            }
+            Err(e) => Err(AppError::new(&format!(
+                "load sync_log: {}, err: {}, backup: {}, err: {}",
+                path, err, backup, e
+            ))),
+        }
+    }
+
+    /// 写临时文件并 fsync，当前文件改名为 .bak 保留上一代，再把临时文件改名为正式文件。
+    /// 任何时刻掉电，正式文件或 .bak 至少有一个是完整的
+    pub fn save(&self, path: &str) -> AppResult<()> {
         let content = serde_json::to_string_pretty(self)?;
-        fs::write(path, content)?;
+
+        let tmp_path = format!("{}.tmp", path);
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+        drop(file);
+
+        if Path::new(path).exists() {
+            fs::rename(path, Self::backup_path(path))?;
+        }
+        fs::rename(&tmp_path, path)?;
+
+        // rename 需要 fsync 目录才能落盘
+        let dir = match Path::new(path).parent() {
+            Some(v) if !v.as_os_str().is_empty() => v,
+            _ => Path::new("."),
+        };
+        fs::File::open(dir)?.sync_all()?;
         Ok(())
     }
 }
@@ -3,8 +3,11 @@ use std::sync::{Arc, Mutex};
 use tokio::sync::watch::Receiver;
 use tracing::{debug, error};
 
-use crate::app_cfg::{AppCfg, AppSyncLog};
+use crate::app_cfg::{AppCfg, AppSyncHistory, AppSyncLog, SyncEntity};
+use crate::error::AppError;
 use fy_base::api::sync_api::Api;
+use fy_base::util::sign::Signer;
+use fy_base::util::tls::ClientTls;
 use fy_base::{
     api::bm_api::{AnalysisApi, RecognitionApi},
     util::service::SignalProduce,
@@ -26,16 +29,24 @@ pub struct AppCtx {
 }
 
 impl AppCtx {
-    pub fn new(cfg: AppCfg, exit_rx: Receiver<i64>, sync_log: AppSyncLog, hw_id: String) -> Self {
-        let ana_api = AnalysisApi::new(&cfg.api.grab_url);
-        let recg_api = RecognitionApi::new(&cfg.api.recg_url);
+    pub fn new(
+        cfg: AppCfg,
+        exit_rx: Receiver<i64>,
+        sync_log: AppSyncLog,
+        hw_id: String,
+        tls: &ClientTls,
+        api_tls: &ClientTls,
+    ) -> Self {
+        let ana_api = AnalysisApi::new(&cfg.api.grab_url, api_tls);
+        let recg_api = RecognitionApi::new(&cfg.api.recg_url, api_tls);
+        let signer = cfg.sync.secret.as_ref().map(|x| Signer::new(&hw_id, x));
 
         Self {
             cfg,
             exit_rx,
             ana_api,
             recg_api,
-            sync_api: Api::default(),
+            sync_api: Api::new(signer, tls),
             sync_log: Arc::new(Mutex::new(sync_log)),
             hw_id,
         }
@@ -84,21 +95,72 @@ impl AppCtx {
         guard.camera.last_ts = last_ts;
     }
 
+    pub fn update_synclog_for_plate(&self, last_id: &str, last_ts: DateTime<Local>) {
+        let mut guard = self.sync_log.lock().unwrap();
+        guard.plate.last_id = last_id.to_string();
+        guard.plate.last_ts = last_ts;
+    }
+
+    pub fn update_synclog_cursor_for_db(&self, cursor: String) {
+        let mut guard = self.sync_log.lock().unwrap();
+        guard.db.cursor = Some(cursor);
+    }
+
+    pub fn update_synclog_cursor_for_person(&self, cursor: String) {
+        let mut guard = self.sync_log.lock().unwrap();
+        guard.person.cursor = Some(cursor);
+    }
+
+    pub fn update_synclog_cursor_for_camera(&self, cursor: String) {
+        let mut guard = self.sync_log.lock().unwrap();
+        guard.camera.cursor = Some(cursor);
+    }
+
+    pub fn update_synclog_cursor_for_plate(&self, cursor: String) {
+        let mut guard = self.sync_log.lock().unwrap();
+        guard.plate.cursor = Some(cursor);
+    }
+
+    // 每次同步开始时清零本次应用的记录数
+    pub fn begin_sync_history(&self, entity: SyncEntity) {
+        let mut guard = self.sync_log.lock().unwrap();
+        guard.history.get_mut(entity).last_applied = 0;
+    }
+
+    pub fn add_sync_applied(&self, entity: SyncEntity, count: usize) {
+        let mut guard = self.sync_log.lock().unwrap();
+        let item = guard.history.get_mut(entity);
+        item.last_applied += count as u64;
+        item.total_applied += count as u64;
+    }
+
+    // 收到退出信号中断的不记录
+    pub fn end_sync_history(&self, entity: SyncEntity, rst: &Result<bool, AppError>) {
+        let mut guard = self.sync_log.lock().unwrap();
+        let item = guard.history.get_mut(entity);
+        match rst {
+            Ok(false) => {
+                item.last_success = Some(Local::now());
+            }
+            Ok(true) => {}
+            Err(e) => {
+                item.last_error = Some(e.to_string());
+                item.last_error_ts = Some(Local::now());
+            }
+        }
+    }
+
+    pub fn get_sync_history(&self) -> AppSyncHistory {
+        self.sync_log.lock().unwrap().history.clone()
+    }
+
     pub fn save_sync_log(&self) {
         let sync_log = self.get_sync_log();
         let dst_fn = &self.cfg.sync.sync_log;
 
-        let content = serde_json::to_string_pretty(&sync_log);
-        match content {
-            Ok(v) => {
-                match std::fs::write(dst_fn, v) {
-                    Ok(_) => {
-                        debug!("save_sync_log, ok, {}", dst_fn);
-                    }
-                    Err(e) => {
-                        error!("error, save_sync_log, {}, err: {:?}", dst_fn, e);
-                    }
-                };
+        match sync_log.save(dst_fn) {
+            Ok(_) => {
+                debug!("save_sync_log, ok, {}", dst_fn);
             }
             Err(e) => {
                 error!("error, save_sync_log, {}, err: {:?}", dst_fn, e);
@@ -6,11 +6,12 @@ use sync_client::{app_cfg::AppCfg, app_ctx::AppCtx, service::signal_service::Sig
 use tokio::sync::watch;
 use tracing::{error, info};
 
-use fy_base::util::{logger, se5, service::ServiceRepo, utils};
+use fy_base::util::{logger, metrics, se5, service::ServiceRepo, tls::ClientTls};
 use sync_client::app_cfg::AppSyncLog;
 use sync_client::model::queue_item::{RabbitmqItem, TaskItem};
 use sync_client::service::rabbitmq::rabbitmq_service::RabbitmqService;
 use sync_client::service::timer_service::TimerService;
+use sync_client::service::web_service::WebService;
 use sync_client::service::wroker::worker_service::WorkerService;
 
 const APP_NAME: &str = "sync_client";
@@ -85,9 +86,9 @@ async fn main() {
         Some(ref v) => v.clone(),
     };
 
-    // 读取同步状态文件, 不存在则生成默认值
+    // 读取同步状态文件(主文件损坏时读取上一代), 不存在则生成默认值
     let persistence_file = app_config.sync.sync_log.as_str();
-    let app_sync_log = if utils::file_exists(persistence_file) {
+    let app_sync_log = if AppSyncLog::exists(persistence_file) {
         info!("persistence file: {}, read it.", persistence_file);
         let sync_log = AppSyncLog::load(persistence_file);
         match sync_log {
@@ -118,9 +119,32 @@ async fn main() {
         sync_log
     };
 
+    let tls = match ClientTls::load(&app_config.sync.tls) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("error, load sync tls, err:{}", e);
+            return;
+        }
+    };
+
+    let api_tls = match ClientTls::load(&app_config.api.tls) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("error, load api tls, err:{}", e);
+            return;
+        }
+    };
+
     // 初始化 context
     let (exit_tx, exit_rx) = watch::channel(0);
-    let app_context = Arc::new(AppCtx::new(app_config, exit_rx, app_sync_log, hw_id));
+    let app_context = Arc::new(AppCtx::new(
+        app_config,
+        exit_rx,
+        app_sync_log,
+        hw_id,
+        &tls,
+        &api_tls,
+    ));
 
     // 创建服务集
     let mut service_repo = ServiceRepo::new(app_context.clone());
@@ -128,10 +152,15 @@ async fn main() {
     // 创建队列
     let task_queue: Arc<Queue<TaskItem>> = Arc::new(Queue::new());
     let rabbitmq_queue: Arc<Queue<RabbitmqItem>> = Arc::new(Queue::new());
+    metrics::register_deadqueue("task", &task_queue);
+    metrics::register_deadqueue("rabbitmq", &rabbitmq_queue);
 
     // 初始退出信号服务
     let exit_service = SignalService::new(exit_tx);
 
+    // 初始 /metrics 服务
+    let web_service = WebService::new(app_context.clone());
+
     // 初始化timer服务
     let timer_service = TimerService::new(app_context.clone(), task_queue.clone());
 
@@ -151,6 +180,7 @@ async fn main() {
 
     // 启动服务
     service_repo.start_service(exit_service);
+    service_repo.start_service(web_service);
     service_repo.start_service(timer_service);
     service_repo.start_service(worker_service);
     service_repo.start_service(rabbitmq_service);
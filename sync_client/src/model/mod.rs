@@ -2,6 +2,7 @@ pub mod queue_item;
 
 use chrono::{DateTime, Local};
 
+use crate::app_cfg::AppSyncHistory;
 use crate::model::queue_item::{TaskItem, TaskItemType};
 use fy_base::util::time_format::long_ts_format;
 use serde::{Deserialize, Serialize};
@@ -23,7 +24,7 @@ pub struct RabbitmqInMessage {
     pub ts: DateTime<Local>, // 时间戳
 }
 
-// RabbitmqInMessage 转成 sync_notify, reset{db,camera}, reboot,
+// RabbitmqInMessage 转成 sync_notify, reset{db,camera}, reboot, reconcile
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ResetPayload {
@@ -76,6 +77,33 @@ pub struct StatusPayload {
     // 流水号
     pub cameras: Vec<StatusCamera>,
     pub dbs: Vec<StatusDb>,
+    // 各类数据的同步历史
+    #[serde(default)]
+    pub sync: AppSyncHistory,
+}
+
+//---------------------------------
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct ReconcileCount {
+    // 服务端和盒子上的数量
+    pub server: usize,
+    pub local: usize,
+
+    // 修复: 补同步的和删除的
+    pub added: usize,
+    pub deleted: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct ReconcilePayload {
+    //  对应某个任务(task)的id
+    pub ref_id: u64,
+    pub cameras: ReconcileCount,
+    pub dbs: ReconcileCount,
+    pub persons: ReconcileCount,
+
+    // 修复失败的记录
+    pub errors: Vec<String>,
 }
 
 //-----------------------------------------------------
@@ -88,6 +116,7 @@ impl TryFrom<RabbitmqInMessage> for TaskItem {
             "sync" => 0,
             "reset" => 1,
             "reboot" => 2,
+            "reconcile" => 3,
             _ => {
                 return Err(format!("unknown message type: {}", value.m_type));
             }
@@ -22,6 +22,8 @@ pub enum TaskItemType {
     SyncTimer, // 定时器触发，去同步
     HeartBeat, // 心跳信息
     ServerCmd, // 服务端的命令
+    Schedule,  // 定时检查摄像头时间表
+    Reconcile, // 定时对账，和服务端比对修复差异
 }
 
 pub type RabbitmqItem = BoxLogMessage;
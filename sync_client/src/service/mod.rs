@@ -1,4 +1,5 @@
 pub mod rabbitmq;
 pub mod signal_service;
 pub mod timer_service;
+pub mod web_service;
 pub mod wroker;
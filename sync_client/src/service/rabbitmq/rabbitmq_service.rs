@@ -4,6 +4,7 @@ use std::time::Duration;
 
 use crate::app_ctx::AppCtx;
 use crate::model::queue_item::{RabbitmqItem, TaskItem};
+use fy_base::util::metrics;
 use fy_base::util::rabbitmq::init_conn_props;
 use fy_base::util::service::Service;
 use tokio::sync::watch::Receiver;
@@ -231,6 +232,7 @@ impl RabbitmqService {
             }
             Err(e) => {
                 error!("error, RabbitmqService, {}, err: {:?}", msg, e);
+                metrics::inc_reconnect("rabbitmq");
 
                 let wait = self.increate_wait();
                 if Self::wait_a_moment(Duration::from_secs(wait), exit_rx).await {
@@ -427,6 +429,7 @@ impl RabbitmqService {
 
         let expire = self.ctx.cfg.rabbitmq.log.expire * 60 * 1000; // 分钟 * 60* 1000
 
+        let timer = metrics::op_timer("rabbitmq", "publish_log");
         let publish_confirm = channel
             .basic_publish(
                 exchange,
@@ -437,6 +440,8 @@ impl RabbitmqService {
             )
             .await?
             .await?;
+        timer.observe_duration();
+        metrics::inc_processed("rabbitmq");
         debug!("RabbitmqService, publish_confirm, {:?}", publish_confirm);
 
         Ok(())
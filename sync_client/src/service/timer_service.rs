@@ -7,7 +7,8 @@ use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::watch::Receiver;
 use tokio::task::JoinHandle;
-use tracing::info;
+use tokio::time::Instant;
+use tracing::{debug, info};
 
 pub struct TimerService {
     pub ctx: Arc<AppCtx>,
@@ -54,11 +55,52 @@ impl TimerService {
         info!("TimerService, push_sync_task");
     }
 
+    fn push_schedule_task(&self) {
+        if self.queue.len() > 10 {
+            info!("TimerService, queue full, skip push_schedule_task");
+            return;
+        }
+
+        let now = Local::now();
+        self.queue.push(TaskItem {
+            id: now.timestamp_millis() as u64,
+            t_type: TaskItemType::Schedule,
+            ts: now,
+            sub_type: 0,
+            payload: "".to_string(),
+        });
+        debug!("TimerService, push_schedule_task");
+    }
+
+    fn push_reconcile_task(&self) {
+        if self.queue.len() > 10 {
+            info!("TimerService, queue full, skip push_reconcile_task");
+            return;
+        }
+
+        let now = Local::now();
+        self.queue.push(TaskItem {
+            id: now.timestamp_millis() as u64,
+            t_type: TaskItemType::Reconcile,
+            ts: now,
+            sub_type: 0,
+            payload: "".to_string(),
+        });
+        info!("TimerService, push_reconcile_task");
+    }
+
     pub async fn do_run(self, mut exit_rx: Receiver<i64>) {
         let hb_interval = self.ctx.cfg.sync.heartbeat; // 分钟
         let sync_interval = self.ctx.cfg.sync.sync_ttl; // 分钟
         let mut hb_timer = tokio::time::interval(Duration::from_secs(hb_interval * 60));
         let mut sync_timer = tokio::time::interval(Duration::from_secs(sync_interval * 60));
+        let schedule_interval = self.ctx.cfg.sync.schedule_check; // 秒
+        let mut schedule_timer = tokio::time::interval(Duration::from_secs(schedule_interval));
+        // 对账比较耗时，启动时不立即执行
+        let reconcile = &self.ctx.cfg.sync.reconcile;
+        let reconcile_interval = Duration::from_secs(reconcile.interval.max(1) * 60); // 分钟
+        let mut reconcile_timer =
+            tokio::time::interval_at(Instant::now() + reconcile_interval, reconcile_interval);
 
         loop {
             tokio::select! {
@@ -71,6 +113,13 @@ impl TimerService {
                     info!("TimerService, sync timer, tick ...");
                     self.push_sync_task();
                 }
+                _ = schedule_timer.tick() => {
+                    self.push_schedule_task();
+                }
+                _ = reconcile_timer.tick(), if reconcile.enable => {
+                    info!("TimerService, reconcile timer, tick ...");
+                    self.push_reconcile_task();
+                }
                 _ = exit_rx.changed() => {
                     info!("TimerService, recv signal, will exit");
                     break;
@@ -0,0 +1,69 @@
+use axum::routing::get;
+use axum::{middleware, Router, Server};
+use hyper::server::conn::AddrIncoming;
+use hyper::server::Builder;
+use std::net::{AddrParseError, SocketAddr};
+use std::sync::Arc;
+
+use crate::app_ctx::AppCtx;
+use fy_base::util::{axum_log::time_use, metrics::metrics_handler, service::Service};
+use tokio::sync::watch::Receiver;
+use tokio::task::JoinHandle;
+
+use tracing::{error, info};
+
+/// sync_client 本身不对外提供接口，只输出 /metrics
+pub struct WebService {
+    pub ctx: Arc<AppCtx>,
+}
+
+impl WebService {
+    pub fn new(ctx: Arc<AppCtx>) -> Self {
+        Self { ctx }
+    }
+
+    pub fn init_router(&self) -> Router {
+        Router::new()
+            .route("/metrics", get(metrics_handler))
+            .layer(middleware::from_fn(time_use))
+    }
+
+    pub fn init_socket_addr(&self) -> Result<SocketAddr, AddrParseError> {
+        let addr = self.ctx.cfg.http.addr.as_str();
+        addr.parse::<SocketAddr>()
+    }
+
+    async fn run(self, server: Builder<AddrIncoming>, exit_rx: Receiver<i64>) {
+        let mut exit_rx = exit_rx;
+
+        let app = self.init_router();
+        let graceful = server
+            .serve(app.into_make_service())
+            .with_graceful_shutdown(async move {
+                let _ = exit_rx.changed().await;
+                info!("WebService, recv signal, will exit");
+            });
+
+        if let Err(e) = graceful.await {
+            error!("error, WebService, err: {:?}", e);
+        }
+        info!("WebService exit.");
+    }
+}
+
+impl Service for WebService {
+    fn run(self, exit_rx: Receiver<i64>) -> JoinHandle<()> {
+        // 绑定http端口
+        let addr = self
+            .init_socket_addr()
+            .unwrap_or_else(|_| panic!("cant parse http addr: {}", self.ctx.cfg.http.addr));
+        info!("http bind: {:?}", addr);
+
+        let server = Server::try_bind(&addr).unwrap_or_else(|e| {
+            error!("error, http can't bind: {:?}, err: {:?}", addr, e);
+            panic!("error, http can't bind: {:?}, err: {:?}", addr, e)
+        });
+
+        tokio::spawn(self.run(server, exit_rx))
+    }
+}
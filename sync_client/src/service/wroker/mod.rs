@@ -1,2 +1,3 @@
+pub mod reconcile;
 pub mod work;
 pub mod worker_service;
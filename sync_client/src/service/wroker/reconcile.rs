@@ -0,0 +1,336 @@
+use chrono::Local;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use fy_base::api::sync_api::{Camera, Db, Person, ResponseData, SYNC_OP_DEL};
+use fy_base::sync::rabbitmq_type::{
+    BOXLOGMESSAGE_LEVEL_ERROR, BOXLOGMESSAGE_LEVEL_INFO, BOXLOGMESSAGE_LEVEL_WARN,
+    BOXLOGMESSAGE_TYPE_RECONCILE,
+};
+use tracing::{debug, error, info};
+
+use crate::app_ctx::AppCtx;
+use crate::error::AppError;
+use crate::model::queue_item::RabbitmqItem;
+use crate::model::{ReconcileCount, ReconcilePayload};
+use crate::service::wroker::work::{do_sync_camera_batch, do_sync_db_batch, do_sync_person_batch};
+
+// 每次从算法服务取多少个人员
+const LOCAL_PAGE: i64 = 1000;
+
+// data 为 None 表示盒子不需要同步这类数据，不对账
+fn get_list<T>(name: &str, res: ResponseData<T>) -> Result<Option<Vec<T>>, AppError> {
+    if res.status != 0 {
+        return Err(AppError::new(&format!(
+            "{}, return status: {}, msg: {:?}",
+            name, res.status, res.message
+        )));
+    }
+    Ok(res.data)
+}
+
+/// 对账: 和服务端的全部记录比对，补同步盒子上缺少的，删除多余的。
+/// 只比对 id，内容的差异由增量同步处理
+pub async fn do_reconcile(ctx: Arc<AppCtx>, ref_id: u64) -> ReconcilePayload {
+    let mut payload = ReconcilePayload {
+        ref_id,
+        ..Default::default()
+    };
+
+    if let Err(e) = reconcile_cameras(&ctx, &mut payload.cameras).await {
+        error!("error, reconcile_cameras, err: {}", e);
+        payload.errors.push(format!("camera: {}", e));
+    }
+    if ctx.is_exit() {
+        return payload;
+    }
+
+    let dbs = match reconcile_dbs(&ctx, &mut payload.dbs).await {
+        Ok(v) => v,
+        Err(e) => {
+            error!("error, reconcile_dbs, err: {}", e);
+            payload.errors.push(format!("db: {}", e));
+            None
+        }
+    };
+    if ctx.is_exit() {
+        return payload;
+    }
+
+    if let Some(dbs) = dbs {
+        for db in dbs.iter() {
+            if let Err(e) = reconcile_persons(&ctx, db, &mut payload.persons).await {
+                error!("error, reconcile_persons({}), err: {}", db, e);
+                payload.errors.push(format!("person({}): {}", db, e));
+            }
+            if ctx.is_exit() {
+                return payload;
+            }
+        }
+    }
+
+    info!("reconcile done, {:?}", payload);
+    payload
+}
+
+async fn reconcile_cameras(ctx: &Arc<AppCtx>, count: &mut ReconcileCount) -> Result<(), AppError> {
+    let url = ctx.cfg.sync.server.camera_list.as_str();
+    let res = ctx.sync_api.fetch_list(url, &ctx.hw_id, &[]).await?;
+    let list: Vec<Camera> = match get_list("fetch camera_list", res)? {
+        Some(v) => v,
+        None => {
+            debug!("reconcile_cameras, box not sync camera, skip");
+            return Ok(());
+        }
+    };
+
+    let res = ctx.ana_api.get_sources().await?;
+    if res.code != 0 {
+        return Err(AppError::new(&format!("get_sources, code:{}", res.code)));
+    }
+    let local: HashSet<String> = res
+        .sources
+        .unwrap_or_default()
+        .into_iter()
+        .map(|x| x.id)
+        .collect();
+    let server: HashSet<String> = list.iter().map(|x| x.uuid.clone()).collect();
+    count.server = server.len();
+    count.local = local.len();
+
+    let missing: Vec<Camera> = list
+        .into_iter()
+        .filter(|x| !local.contains(&x.uuid))
+        .collect();
+    let extra: Vec<Camera> = local
+        .iter()
+        .filter(|x| !server.contains(*x))
+        .map(|x| Camera {
+            id: "0".to_string(),
+            seq: None,
+            uuid: x.clone(),
+            op: SYNC_OP_DEL,
+            last_update: Local::now(),
+            c_type: 0,
+            detail: None,
+        })
+        .collect();
+    info!(
+        "reconcile_cameras, server: {}, local: {}, missing: {}, extra: {}",
+        count.server,
+        count.local,
+        missing.len(),
+        extra.len()
+    );
+
+    let added = missing.len();
+    if do_sync_camera_batch(ctx.clone(), missing, false).await? {
+        return Ok(());
+    }
+    count.added = added;
+
+    let deleted = extra.len();
+    if do_sync_camera_batch(ctx.clone(), extra, false).await? {
+        return Ok(());
+    }
+    count.deleted = deleted;
+    Ok(())
+}
+
+// 返回服务端的 db 列表，用于人员对账
+async fn reconcile_dbs(
+    ctx: &Arc<AppCtx>,
+    count: &mut ReconcileCount,
+) -> Result<Option<Vec<String>>, AppError> {
+    let url = ctx.cfg.sync.server.db_list.as_str();
+    let res = ctx.sync_api.fetch_list(url, &ctx.hw_id, &[]).await?;
+    let list: Vec<Db> = match get_list("fetch db_list", res)? {
+        Some(v) => v,
+        None => {
+            debug!("reconcile_dbs, box not sync db, skip");
+            return Ok(None);
+        }
+    };
+
+    let res = ctx.recg_api.get_dbs().await?;
+    if res.code != 0 {
+        return Err(AppError::new(&format!("get_dbs, code:{}", res.code)));
+    }
+    let local: HashSet<String> = res.dbs.unwrap_or_default().into_iter().collect();
+    let dbs: Vec<String> = list.iter().map(|x| x.uuid.clone()).collect();
+    let server: HashSet<String> = dbs.iter().cloned().collect();
+    count.server = server.len();
+    count.local = local.len();
+
+    let missing: Vec<Db> = list
+        .into_iter()
+        .filter(|x| !local.contains(&x.uuid))
+        .collect();
+    let extra: Vec<Db> = local
+        .iter()
+        .filter(|x| !server.contains(*x))
+        .map(|x| Db {
+            id: "0".to_string(),
+            seq: None,
+            uuid: x.clone(),
+            op: SYNC_OP_DEL,
+            last_update: Local::now(),
+            capacity: 0,
+        })
+        .collect();
+    info!(
+        "reconcile_dbs, server: {}, local: {}, missing: {}, extra: {}",
+        count.server,
+        count.local,
+        missing.len(),
+        extra.len()
+    );
+
+    let added = missing.len();
+    if do_sync_db_batch(ctx.clone(), missing, false).await? {
+        return Ok(None);
+    }
+    count.added = added;
+
+    let deleted = extra.len();
+    if do_sync_db_batch(ctx.clone(), extra, false).await? {
+        return Ok(None);
+    }
+    count.deleted = deleted;
+    Ok(Some(dbs))
+}
+
+async fn get_local_persons(ctx: &AppCtx, db: &str) -> Result<HashSet<String>, AppError> {
+    let mut ids = HashSet::new();
+    let mut offset = 0;
+    loop {
+        let res = ctx
+            .recg_api
+            .get_db_persons(db.to_string(), offset, LOCAL_PAGE)
+            .await?;
+        if res.code != 0 {
+            return Err(AppError::new(&format!(
+                "get_db_persons({}), code:{}",
+                db, res.code
+            )));
+        }
+
+        let persons = res.persons.unwrap_or_default();
+        let len = persons.len() as i64;
+        ids.extend(persons);
+        if len < LOCAL_PAGE {
+            break;
+        }
+        offset += len;
+    }
+    Ok(ids)
+}
+
+async fn reconcile_persons(
+    ctx: &Arc<AppCtx>,
+    db: &str,
+    count: &mut ReconcileCount,
+) -> Result<(), AppError> {
+    let url = ctx.cfg.sync.server.person_ids.as_str();
+    let res = ctx
+        .sync_api
+        .fetch_list(url, &ctx.hw_id, &[("db_id", db)])
+        .await?;
+    let server: HashSet<String> = match get_list("fetch person_ids", res)? {
+        Some(v) => v.into_iter().collect(),
+        None => {
+            return Ok(());
+        }
+    };
+
+    let local = get_local_persons(ctx, db).await?;
+    count.server += server.len();
+    count.local += local.len();
+
+    let missing: Vec<String> = server
+        .iter()
+        .filter(|x| !local.contains(*x))
+        .cloned()
+        .collect();
+    let extra: Vec<Person> = local
+        .iter()
+        .filter(|x| !server.contains(*x))
+        .map(|x| Person {
+            id: "0".to_string(),
+            seq: None,
+            uuid: x.clone(),
+            db_id: db.to_string(),
+            op: SYNC_OP_DEL,
+            last_update: Local::now(),
+            detail: None,
+        })
+        .collect();
+    info!(
+        "reconcile_persons, db: {}, server: {}, local: {}, missing: {}, extra: {}",
+        db,
+        server.len(),
+        local.len(),
+        missing.len(),
+        extra.len()
+    );
+
+    // 缺少的分批从服务端取详情
+    let url = ctx.cfg.sync.server.person_list.as_str();
+    for uuids in missing.chunks(ctx.cfg.sync.reconcile.batch) {
+        let uuids = uuids.join(",");
+        let res = ctx
+            .sync_api
+            .fetch_list(url, &ctx.hw_id, &[("uuids", &uuids)])
+            .await?;
+        let list: Vec<Person> = get_list("fetch person_list", res)?.unwrap_or_default();
+
+        let added = list.len();
+        if do_sync_person_batch(ctx.clone(), list, false).await? {
+            return Ok(());
+        }
+        count.added += added;
+    }
+
+    let deleted = extra.len();
+    if do_sync_person_batch(ctx.clone(), extra, false).await? {
+        return Ok(());
+    }
+    count.deleted += deleted;
+    Ok(())
+}
+
+//-----------------------------
+// 有修复失败的为 error，有差异的为 warn
+pub fn build_rabbitmqitem_from_reconcile(
+    hw_id: &str,
+    ips: &str,
+    payload: &ReconcilePayload,
+) -> RabbitmqItem {
+    let drifted = [&payload.cameras, &payload.dbs, &payload.persons]
+        .iter()
+        .any(|x| x.added > 0 || x.deleted > 0);
+    let level = if !payload.errors.is_empty() {
+        BOXLOGMESSAGE_LEVEL_ERROR
+    } else if drifted {
+        BOXLOGMESSAGE_LEVEL_WARN
+    } else {
+        BOXLOGMESSAGE_LEVEL_INFO
+    };
+
+    let payload = match serde_json::to_string(payload) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("error, build_rabbitmqitem_from_reconcile, err: {:?}", e);
+            "".to_string()
+        }
+    };
+
+    RabbitmqItem {
+        hwid: hw_id.to_string(),
+        ips: ips.to_string(),
+        c_type: BOXLOGMESSAGE_TYPE_RECONCILE.to_string(),
+        level,
+        payload,
+        ts: Local::now(),
+    }
+}
@@ -1,17 +1,28 @@
 use crate::error::AppError;
 use crate::model::queue_item::RabbitmqItem;
 use crate::model::{StatusCamera, StatusDb, StatusPayload};
-use chrono::Local;
+use chrono::{DateTime, Local};
+use futures::stream::{self, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
 
-use fy_base::api::bm_api::{AnalysisApi, ApiFeatureQuality, CreateSourceReqConfig, RecognitionApi};
+use fy_base::api::bm_api::{
+    AnalysisApi, ApiFeatureQuality, CreateSourceReqConfig, GetPersonInfoRes, RecognitionApi,
+};
 use fy_base::sync::rabbitmq_type::{BOXLOGMESSAGE_LEVEL_INFO, BOXLOGMESSAGE_TYPE_STATUS};
 use log::debug;
 
-use fy_base::api::sync_api::{Camera, Db, Person, SYNC_OP_DEL};
+use fy_base::api::sync_api::{
+    feature_hash, Camera, CameraFilter, CameraSchedule, Db, Person, PersonInfo, Plate, PlateInfo,
+    ScheduleOutside, SyncItem, SYNC_OP_DEL,
+};
 use tracing::{error, info};
 
+use crate::app_cfg::SyncEntity;
 use crate::app_ctx::AppCtx;
 use fy_base::util::utils;
 
@@ -171,6 +182,7 @@ pub async fn get_status_payload(
         ref_id,
         cameras: camera_list,
         dbs: db_list,
+        sync: Default::default(),
     })
 }
 
@@ -211,8 +223,11 @@ pub async fn do_sync_camera(ctx: Arc<AppCtx>) -> Result<bool, AppError> {
         let sync_log = ctx.get_sync_log();
         let last_ts = sync_log.camera.last_ts;
         let last_ts = last_ts.format(utils::DATETIME_FMT_LONG).to_string();
+        let cursor = sync_log.camera.cursor.as_deref();
 
-        let res = api.fetch_camera_updated(url, &last_ts, hw_id).await?;
+        let res = api
+            .fetch_camera_updated(url, &last_ts, cursor, hw_id)
+            .await?;
         if res.status != 0 {
             return Err(AppError::new(&format!(
                 "fetch_camera_updated, return status: {}",
@@ -230,11 +245,16 @@ pub async fn do_sync_camera(ctx: Arc<AppCtx>) -> Result<bool, AppError> {
             return Ok(false);
         }
 
+        let next = res.next;
         let res_data = res.data.unwrap();
-        let exited = do_sync_camera_batch(ctx.clone(), res_data).await?;
+        let exited = do_sync_camera_batch(ctx.clone(), res_data, true).await?;
         if exited {
             return Ok(true);
         }
+        // 整批处理完才更新游标，中途退出的下次从上一个游标重新同步
+        if let Some(next) = next {
+            ctx.update_synclog_cursor_for_camera(next);
+        }
 
         loop_count += 1;
         // 循环次数过多，退出
@@ -249,7 +269,19 @@ pub async fn do_sync_camera(ctx: Arc<AppCtx>) -> Result<bool, AppError> {
     Ok(false)
 }
 
-pub async fn do_sync_camera_batch(ctx: Arc<AppCtx>, list: Vec<Camera>) -> Result<bool, AppError> {
+// update_log: 对账时补同步的记录不更新 sync_log
+pub async fn do_sync_camera_batch(
+    ctx: Arc<AppCtx>,
+    list: Vec<Camera>,
+    update_log: bool,
+) -> Result<bool, AppError> {
+    let filter_file = ctx.cfg.sync.camera_filter_file.as_str();
+    let mut filters: BTreeMap<String, CameraFilter> =
+        load_list_file(filter_file, |x: &CameraFilter| &x.uuid)?;
+    let schedule_file = ctx.cfg.sync.camera_schedule_file.as_str();
+    let mut schedules: BTreeMap<String, CameraSchedule> =
+        load_list_file(schedule_file, |x: &CameraSchedule| &x.uuid)?;
+
     for camera in list.iter() {
         debug!("process sync_camera, {}, {}", camera.uuid, camera.op);
 
@@ -260,21 +292,38 @@ pub async fn do_sync_camera_batch(ctx: Arc<AppCtx>, list: Vec<Camera>) -> Result
                 "WorkerService, delete camera:{}, return: {:?}",
                 camera.uuid, deled
             );
+
+            update_list_file(filter_file, &mut filters, &camera.uuid, None)?;
+            update_list_file(schedule_file, &mut schedules, &camera.uuid, None)?;
         } else {
             //新增或修改
-            let (grab_url, config) = match camera.detail {
+            let (grab_url, config, camera_filter, camera_schedule) = match camera.detail {
                 None => {
                     return Err(AppError::new(&format!(
                         "camera:{} detail is none",
                         camera.uuid
                     )));
                 }
-                Some(ref v) => (v.url.clone(), v.config.clone()),
+                Some(ref v) => (v.url.clone(), v.config.clone(), v.filter()?, v.schedule()?),
             };
 
             let mut camera_config =
                 serde_json::from_reader::<_, CreateSourceReqConfig>(config.as_bytes())?;
 
+            let camera_schedule = match camera_schedule {
+                Some(mut v) => {
+                    if let Err(e) = v.validate() {
+                        return Err(AppError::new(&format!(
+                            "camera:{} schedule, {}",
+                            camera.uuid, e
+                        )));
+                    }
+                    v.c_type = camera.c_type;
+                    Some(v)
+                }
+                None => None,
+            };
+
             // 先删除
             let deled = ctx.ana_api.delete_source(camera.uuid.clone()).await?;
             debug!(
@@ -289,26 +338,9 @@ pub async fn do_sync_camera_batch(ctx: Arc<AppCtx>, list: Vec<Camera>) -> Result
                 Some(ref v) => v.clone(),
             };
 
-            match camera.c_type {
-                1 => {
-                    camera_config.enable_face = true;
-                    camera_config.enable_vehicle = false;
-                }
-                2 => {
-                    camera_config.enable_face = false;
-                    camera_config.enable_vehicle = true;
-                }
-                3 => {
-                    camera_config.enable_face = true;
-                    camera_config.enable_vehicle = true;
-                }
-                _ => {
-                    return Err(AppError::new(&format!(
-                        "unkown camera type:{}",
-                        camera.c_type
-                    )));
-                }
-            }
+            let (enable_face, enable_vehicle) = camera_type_flags(camera.c_type)?;
+            camera_config.enable_face = enable_face;
+            camera_config.enable_vehicle = enable_vehicle;
 
             let res = ctx
                 .ana_api
@@ -326,10 +358,17 @@ pub async fn do_sync_camera_batch(ctx: Arc<AppCtx>, list: Vec<Camera>) -> Result
                     camera.uuid, res.code, res.msg
                 )));
             }
+
+            // 过滤规则、时间表不下发到算法服务，写入本地文件由 box_agent 使用
+            update_list_file(filter_file, &mut filters, &camera.uuid, camera_filter)?;
+            update_list_file(schedule_file, &mut schedules, &camera.uuid, camera_schedule)?;
         }
 
         // 更新 last_update_ts
-        ctx.update_synclog_for_camera(&camera.id, camera.last_update);
+        if update_log {
+            ctx.update_synclog_for_camera(&camera.id, camera.last_update);
+            ctx.add_sync_applied(SyncEntity::Camera, 1);
+        }
 
         // 检查退出
         if ctx.is_exit() {
@@ -339,6 +378,95 @@ pub async fn do_sync_camera_batch(ctx: Arc<AppCtx>, list: Vec<Camera>) -> Result
     Ok(false)
 }
 
+// 采集类型 1：人脸 2：车辆 3：人脸+车辆，返回 (enable_face, enable_vehicle)
+fn camera_type_flags(c_type: i64) -> Result<(bool, bool), AppError> {
+    match c_type {
+        1 => Ok((true, false)),
+        2 => Ok((false, true)),
+        3 => Ok((true, true)),
+        _ => Err(AppError::new(&format!("unkown camera type:{}", c_type))),
+    }
+}
+
+/// 按时间表启用/停用摄像头，窗口外停用后算法服务不再占用NPU。
+/// 窗口外标记为低优先级的摄像头不停用
+pub async fn apply_camera_schedules(ctx: Arc<AppCtx>) -> Result<(), AppError> {
+    let schedule_file = ctx.cfg.sync.camera_schedule_file.as_str();
+    let schedules: BTreeMap<String, CameraSchedule> =
+        load_list_file(schedule_file, |x: &CameraSchedule| &x.uuid)?;
+
+    let now = Local::now();
+    let mut failed = 0;
+    for schedule in schedules.values() {
+        // 单个摄像头失败不影响其它摄像头
+        if let Err(e) = apply_camera_schedule(&ctx, schedule, now).await {
+            error!(
+                "error, WorkerService, apply schedule, camera:{}, err:{:?}",
+                schedule.uuid, e
+            );
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        return Err(AppError::new(&format!(
+            "apply schedule, failed cameras:{}/{}",
+            failed,
+            schedules.len()
+        )));
+    }
+    Ok(())
+}
+
+async fn apply_camera_schedule(
+    ctx: &AppCtx,
+    schedule: &CameraSchedule,
+    now: DateTime<Local>,
+) -> Result<(), AppError> {
+    let enable = schedule.is_active(now) || schedule.outside != ScheduleOutside::Drop;
+    let (enable_face, enable_vehicle) = if enable {
+        camera_type_flags(schedule.c_type)?
+    } else {
+        (false, false)
+    };
+
+    let res = ctx.ana_api.get_source_info(schedule.uuid.clone()).await?;
+    let (url, mut config) = match (res.code, res.url, res.config) {
+        (0, Some(url), Some(config)) => (url, config),
+        _ => {
+            // 还没有同步下来，或已被删除
+            debug!(
+                "WorkerService, apply schedule, camera:{} not found, code:{}",
+                schedule.uuid, res.code
+            );
+            return Ok(());
+        }
+    };
+
+    if config.enable_face == enable_face && config.enable_vehicle == enable_vehicle {
+        return Ok(());
+    }
+
+    config.enable_face = enable_face;
+    config.enable_vehicle = enable_vehicle;
+    let upload_url = config.upload_url.clone();
+    let res = ctx
+        .ana_api
+        .update_source(schedule.uuid.clone(), url, upload_url, config)
+        .await?;
+    if res.code != 0 {
+        return Err(AppError::new(&format!(
+            "update_source:{}, return code:{}, msg:{}",
+            schedule.uuid, res.code, res.msg
+        )));
+    }
+    info!(
+        "WorkerService, apply schedule, camera:{}, enable: {}",
+        schedule.uuid, enable
+    );
+    Ok(())
+}
+
 //-----------------------------------------------------------------------------
 pub async fn do_sync_db(ctx: Arc<AppCtx>) -> Result<bool, AppError> {
     let max_loop = 100;
@@ -352,8 +480,9 @@ pub async fn do_sync_db(ctx: Arc<AppCtx>) -> Result<bool, AppError> {
         let sync_log = ctx.get_sync_log();
         let last_ts = sync_log.db.last_ts;
         let last_ts = last_ts.format(utils::DATETIME_FMT_LONG).to_string();
+        let cursor = sync_log.db.cursor.as_deref();
 
-        let res = api.fetch_db_updated(url, &last_ts, hw_id).await?;
+        let res = api.fetch_db_updated(url, &last_ts, cursor, hw_id).await?;
         if res.status != 0 {
             return Err(AppError::new(&format!(
                 "fetch_db_updated, return status: {}",
@@ -371,11 +500,16 @@ pub async fn do_sync_db(ctx: Arc<AppCtx>) -> Result<bool, AppError> {
             return Ok(false);
         }
 
+        let next = res.next;
         let res_data = res.data.unwrap();
-        let exited = do_sync_db_batch(ctx.clone(), res_data).await?;
+        let exited = do_sync_db_batch(ctx.clone(), res_data, true).await?;
         if exited {
             return Ok(true);
         }
+        // 整批处理完才更新游标
+        if let Some(next) = next {
+            ctx.update_synclog_cursor_for_db(next);
+        }
 
         loop_count += 1;
         // 循环次数过多，退出
@@ -392,7 +526,11 @@ pub async fn do_sync_db(ctx: Arc<AppCtx>) -> Result<bool, AppError> {
 
 // db的处理，只支持删除和新增，
 // 修改不支持
-pub async fn do_sync_db_batch(ctx: Arc<AppCtx>, list: Vec<Db>) -> Result<bool, AppError> {
+pub async fn do_sync_db_batch(
+    ctx: Arc<AppCtx>,
+    list: Vec<Db>,
+    update_log: bool,
+) -> Result<bool, AppError> {
     for db in list.iter() {
         debug!("process sync_db, {}, {}", db.uuid, db.op);
 
@@ -425,7 +563,10 @@ pub async fn do_sync_db_batch(ctx: Arc<AppCtx>, list: Vec<Db>) -> Result<bool, A
         }
 
         // 更新 last_update_ts
-        ctx.update_synclog_for_db(&db.id, db.last_update);
+        if update_log {
+            ctx.update_synclog_for_db(&db.id, db.last_update);
+            ctx.add_sync_applied(SyncEntity::Db, 1);
+        }
 
         // 检查退出
         if ctx.is_exit() {
@@ -437,7 +578,8 @@ pub async fn do_sync_db_batch(ctx: Arc<AppCtx>, list: Vec<Db>) -> Result<bool, A
 
 //-----------------------------------------------------------------------------
 pub async fn do_sync_person(ctx: Arc<AppCtx>) -> Result<bool, AppError> {
-    let max_loop = 100;
+    // 0 表示不限制，一次同步完
+    let max_loop = ctx.cfg.sync.person.max_loop;
     let mut loop_count = 0;
 
     let url = ctx.cfg.sync.server.person_sync.as_str();
@@ -448,8 +590,11 @@ pub async fn do_sync_person(ctx: Arc<AppCtx>) -> Result<bool, AppError> {
         let sync_log = ctx.get_sync_log();
         let last_ts = sync_log.person.last_ts;
         let last_ts = last_ts.format(utils::DATETIME_FMT_LONG).to_string();
+        let cursor = sync_log.person.cursor.as_deref();
 
-        let res = api.fetch_person_updated(url, &last_ts, hw_id).await?;
+        let res = api
+            .fetch_person_updated(url, &last_ts, cursor, hw_id)
+            .await?;
         if res.status != 0 {
             return Err(AppError::new(&format!(
                 "fetch_person_updated, return status: {}",
@@ -467,26 +612,89 @@ pub async fn do_sync_person(ctx: Arc<AppCtx>) -> Result<bool, AppError> {
             return Ok(false);
         }
 
+        let next = res.next;
         let res_data = res.data.unwrap();
-        let exited = do_sync_person_batch(ctx.clone(), res_data).await?;
+        let exited = do_sync_person_batch(ctx.clone(), res_data, true).await?;
         if exited {
             return Ok(true);
         }
+        // 整批处理完才更新游标
+        if let Some(next) = next {
+            ctx.update_synclog_cursor_for_person(next);
+        }
 
         loop_count += 1;
         // 循环次数过多，退出
-        if loop_count >= max_loop {
+        if max_loop > 0 && loop_count >= max_loop {
             break;
         }
+    }
 
-        // 休眠一会
-        tokio::time::sleep(Duration::from_millis(200)).await;
+    Ok(false)
+}
+
+/// 分成多个小批次并发处理，批次内新增的人员一次 create_persons 创建。
+/// 批次按顺序完成，每完成一个批次就保存 sync_log，中断后从最后完成的批次继续
+pub async fn do_sync_person_batch(
+    ctx: Arc<AppCtx>,
+    list: Vec<Person>,
+    update_log: bool,
+) -> Result<bool, AppError> {
+    let batch = ctx.cfg.sync.person.batch;
+    let concurrency = ctx.cfg.sync.person.concurrency;
+
+    let mut chunks: Vec<Vec<Person>> = vec![];
+    for person in dedup_persons(list) {
+        match chunks.last_mut() {
+            Some(v) if v.len() < batch => v.push(person),
+            _ => chunks.push(vec![person]),
+        }
     }
 
+    let mut chunks = stream::iter(chunks)
+        .map(|chunk| {
+            let ctx = ctx.clone();
+            async move { sync_person_chunk(&ctx, &chunk).await.map(|_| chunk) }
+        })
+        .buffered(concurrency);
+
+    while let Some(rst) = chunks.next().await {
+        let chunk = rst?;
+
+        // 更新 last_update_ts
+        if let (true, Some(person)) = (update_log, chunk.last()) {
+            ctx.update_synclog_for_person(&person.id, person.last_update);
+            ctx.update_synclog_cursor_for_person(person.cursor().encode());
+            ctx.add_sync_applied(SyncEntity::Person, chunk.len());
+            ctx.save_sync_log();
+        }
+
+        // 检查退出
+        if ctx.is_exit() {
+            return Ok(true);
+        }
+    }
     Ok(false)
 }
 
-pub async fn do_sync_person_batch(ctx: Arc<AppCtx>, list: Vec<Person>) -> Result<bool, AppError> {
+// 同一个人员只保留最后一条记录，避免并发的批次操作同一个人员
+fn dedup_persons(list: Vec<Person>) -> Vec<Person> {
+    let mut last: HashMap<(String, String), usize> = HashMap::new();
+    for (i, x) in list.iter().enumerate() {
+        last.insert((x.db_id.clone(), x.uuid.clone()), i);
+    }
+
+    list.into_iter()
+        .enumerate()
+        .filter(|(i, x)| last.get(&(x.db_id.clone(), x.uuid.clone())) == Some(i))
+        .map(|(_, x)| x)
+        .collect()
+}
+
+// 删除的和已存在的逐个处理，新增的按 db 分组一次创建
+async fn sync_person_chunk(ctx: &AppCtx, list: &[Person]) -> Result<(), AppError> {
+    let mut creates: BTreeMap<&str, Vec<(&Person, &PersonInfo)>> = BTreeMap::new();
+
     for person in list.iter() {
         debug!("process sync_person, {}, {}", person.uuid, person.op);
 
@@ -500,60 +708,335 @@ pub async fn do_sync_person_batch(ctx: Arc<AppCtx>, list: Vec<Person>) -> Result
                 "WorkerService, delete person:{}, return: {:?}",
                 person.uuid, deled
             );
-        } else {
-            //新增或修改
+            continue;
+        }
 
-            // 先删除再加
-            if person.detail.is_none() {
+        //新增或修改
+        let detail = match person.detail {
+            Some(ref v) => v,
+            None => {
                 return Err(AppError::new(&format!(
                     "person:{} detail is none",
                     person.uuid
                 )));
             }
+        };
 
-            let _deled = ctx
-                .recg_api
-                .delete_person(person.db_id.clone(), person.uuid.clone())
-                .await?;
-            // debug!(
-            //     "WorkerService, for modify, delete person:{}, return: {:?}",
-            //     person.uuid, deled
-            // );
-
-            let detail = person.detail.as_ref().unwrap();
-            let fea_list: Vec<_> = detail
-                .faces
-                .iter()
-                .map(|x| ApiFeatureQuality {
-                    feature: x.fea.clone(),
-                    quality: x.quality as f64,
-                })
-                .collect();
+        let info = ctx
+            .recg_api
+            .get_person_info(person.db_id.clone(), person.uuid.clone())
+            .await?;
+        if info.code == 0 {
+            // 已存在，只同步有变化的特征
+            sync_person_features(ctx, person, detail, info).await?;
+        } else {
+            creates
+                .entry(person.db_id.as_str())
+                .or_default()
+                .push((person, detail));
+        }
+    }
 
-            let res = ctx
-                .recg_api
-                .create_persons(
-                    person.db_id.clone(),
-                    vec![person.uuid.clone()],
-                    vec![fea_list],
-                )
-                .await?;
+    for (db, persons) in creates {
+        create_persons(ctx, db, &persons).await?;
+    }
+    Ok(())
+}
 
-            if res.code != 0 {
-                return Err(AppError::new(&format!(
-                    "create_persons:{}, return code:{}, msg:{}",
-                    person.uuid, res.code, res.msg
-                )));
-            }
+fn get_features(detail: &PersonInfo) -> Vec<ApiFeatureQuality> {
+    detail
+        .faces
+        .iter()
+        .map(|x| ApiFeatureQuality {
+            feature: x.fea.clone(),
+            quality: x.quality as f64,
+        })
+        .collect()
+}
+
+async fn create_persons(
+    ctx: &AppCtx,
+    db: &str,
+    persons: &[(&Person, &PersonInfo)],
+) -> Result<(), AppError> {
+    let ids = persons.iter().map(|x| x.0.uuid.clone()).collect();
+    let features = persons.iter().map(|x| get_features(x.1)).collect();
+
+    let res = ctx
+        .recg_api
+        .create_persons(db.to_string(), ids, features)
+        .await?;
+    if res.code != 0 {
+        return Err(AppError::new(&format!(
+            "create_persons, db:{}, count:{}, return code:{}, msg:{}",
+            db,
+            persons.len(),
+            res.code,
+            res.msg
+        )));
+    }
+    debug!("create_persons, db:{}, count:{}", db, persons.len());
+
+    for (person, detail) in persons.iter() {
+        if let Some(aggregate) = non_empty_aggregate(detail) {
+            add_aggregate_feature(ctx, person, aggregate).await?;
         }
+    }
+    Ok(())
+}
 
-        // 更新 last_update_ts
-        ctx.update_synclog_for_person(&person.id, person.last_update);
+// 按特征值的 hash 和算法服务中的比对，先加新增的再删除多余的，
+// 同步过程中人员一直可以被检索到，聚合特征也不会被重置
+async fn sync_person_features(
+    ctx: &AppCtx,
+    person: &Person,
+    detail: &PersonInfo,
+    info: GetPersonInfoRes,
+) -> Result<(), AppError> {
+    let wanted: HashSet<String> = detail.faces.iter().map(|x| x.get_hash()).collect();
+
+    // 已有的特征，hash 重复的也需要删除
+    let mut existing: HashSet<String> = HashSet::new();
+    let mut to_delete: Vec<i64> = vec![];
+    for face in info.faces.unwrap_or_default() {
+        let hash = feature_hash(&face.feature);
+        if wanted.contains(&hash) && existing.insert(hash) {
+            continue;
+        }
+        to_delete.push(face.id);
+    }
 
-        // 检查退出
+    let mut added: HashSet<String> = HashSet::new();
+    let to_add: Vec<_> = detail
+        .faces
+        .iter()
+        .filter(|x| {
+            let hash = x.get_hash();
+            !existing.contains(&hash) && added.insert(hash)
+        })
+        .map(|x| ApiFeatureQuality {
+            feature: x.fea.clone(),
+            quality: x.quality as f64,
+        })
+        .collect();
+
+    debug!(
+        "sync_person_features, {}, add: {}, delete: {}",
+        person.uuid,
+        to_add.len(),
+        to_delete.len()
+    );
+
+    if !to_add.is_empty() {
+        let res = ctx
+            .recg_api
+            .add_features_to_person(person.db_id.clone(), person.uuid.clone(), to_add)
+            .await?;
+        if res.code != 0 {
+            return Err(AppError::new(&format!(
+                "add_features_to_person:{}, return code:{}, msg:{}",
+                person.uuid, res.code, res.msg
+            )));
+        }
+    }
+
+    for face_id in to_delete {
+        let res = ctx
+            .recg_api
+            .delete_person_feature(person.db_id.clone(), person.uuid.clone(), face_id)
+            .await?;
+        if res.code != 0 {
+            return Err(AppError::new(&format!(
+                "delete_person_feature:{}, face:{}, return code:{}, msg:{}",
+                person.uuid, face_id, res.code, res.msg
+            )));
+        }
+    }
+
+    // 聚合特征有变化时才更新
+    if let Some(aggregate) = non_empty_aggregate(detail) {
+        if info.aggregate_feature.as_deref() != Some(aggregate) {
+            add_aggregate_feature(ctx, person, aggregate).await?;
+        }
+    }
+    Ok(())
+}
+
+// 旧版本的服务端没有聚合特征时下发空字符串
+fn non_empty_aggregate(detail: &PersonInfo) -> Option<&str> {
+    detail.aggregate.as_deref().filter(|x| !x.trim().is_empty())
+}
+
+async fn add_aggregate_feature(
+    ctx: &AppCtx,
+    person: &Person,
+    aggregate: &str,
+) -> Result<(), AppError> {
+    let res = ctx
+        .recg_api
+        .add_aggregate_feature_to_person(
+            person.db_id.clone(),
+            person.uuid.clone(),
+            aggregate.to_string(),
+        )
+        .await?;
+    if res.code != 0 {
+        return Err(AppError::new(&format!(
+            "add_aggregate_feature_to_person:{}, return code:{}, msg:{}",
+            person.uuid, res.code, res.msg
+        )));
+    }
+    Ok(())
+}
+
+//-----------------------------------------------------------------------------
+// 车牌布控名单不需要下发到算法服务，保存在本地文件中，由 box_agent 加载做比对
+pub async fn do_sync_plate(ctx: Arc<AppCtx>) -> Result<bool, AppError> {
+    let max_loop = 100;
+    let mut loop_count = 0;
+
+    let url = ctx.cfg.sync.server.plate_sync.as_str();
+    let hw_id = ctx.hw_id.as_str();
+    let api = &ctx.sync_api;
+
+    loop {
+        let sync_log = ctx.get_sync_log();
+        let last_ts = sync_log.plate.last_ts;
+        let last_ts = last_ts.format(utils::DATETIME_FMT_LONG).to_string();
+        let cursor = sync_log.plate.cursor.as_deref();
+
+        let res = api
+            .fetch_plate_updated(url, &last_ts, cursor, hw_id)
+            .await?;
+        if res.status != 0 {
+            return Err(AppError::new(&format!(
+                "fetch_plate_updated, return status: {}",
+                res.status
+            )));
+        }
+
+        // 检查退出信号
         if ctx.is_exit() {
             return Ok(true);
         }
+
+        if res.is_empty() {
+            // 没有更新数据，退出
+            return Ok(false);
+        }
+
+        let next = res.next;
+        let res_data = res.data.unwrap();
+        let exited = do_sync_plate_batch(ctx.clone(), res_data).await?;
+        if exited {
+            return Ok(true);
+        }
+        // 整批处理完才更新游标
+        if let Some(next) = next {
+            ctx.update_synclog_cursor_for_plate(next);
+        }
+
+        loop_count += 1;
+        // 循环次数过多，退出
+        if loop_count >= max_loop {
+            break;
+        }
+
+        // 休眠一会
+        tokio::time::sleep(Duration::from_millis(200)).await;
     }
+
     Ok(false)
 }
+
+pub async fn do_sync_plate_batch(ctx: Arc<AppCtx>, list: Vec<Plate>) -> Result<bool, AppError> {
+    let plate_file = ctx.cfg.sync.plate_file.as_str();
+    let mut plates: BTreeMap<String, PlateInfo> =
+        load_list_file(plate_file, |x: &PlateInfo| &x.uuid)?;
+
+    let count = list.len();
+    let mut last: Option<(String, DateTime<Local>)> = None;
+    for plate in list.into_iter() {
+        debug!("process sync_plate, {}, {}", plate.uuid, plate.op);
+
+        if plate.op == SYNC_OP_DEL {
+            // 删除
+            plates.remove(&plate.uuid);
+        } else {
+            //新增或修改
+            match plate.detail {
+                None => {
+                    return Err(AppError::new(&format!(
+                        "plate:{} detail is none",
+                        plate.uuid
+                    )));
+                }
+                Some(v) => {
+                    plates.insert(plate.uuid.clone(), v);
+                }
+            }
+        }
+        last = Some((plate.id, plate.last_update));
+    }
+
+    // 整批写入文件后，再更新 last_update_ts
+    save_list_file(plate_file, &plates)?;
+    if let Some((last_id, last_ts)) = last {
+        ctx.update_synclog_for_plate(&last_id, last_ts);
+        ctx.add_sync_applied(SyncEntity::Plate, count);
+    }
+    info!(
+        "WorkerService, save plate file: {}, plates: {}",
+        plate_file,
+        plates.len()
+    );
+
+    Ok(ctx.is_exit())
+}
+
+// 本地文件中保存为数组，按 uuid 索引
+fn load_list_file<T, F>(path: &str, key: F) -> Result<BTreeMap<String, T>, AppError>
+where
+    T: DeserializeOwned,
+    F: Fn(&T) -> &String,
+{
+    if !Path::new(path).exists() {
+        return Ok(BTreeMap::new());
+    }
+
+    let content = std::fs::read(path)?;
+    let list: Vec<T> = serde_json::from_slice(&content)?;
+    Ok(list.into_iter().map(|x| (key(&x).clone(), x)).collect())
+}
+
+// 有变化时才写文件
+fn update_list_file<T: Serialize>(
+    path: &str,
+    map: &mut BTreeMap<String, T>,
+    uuid: &str,
+    value: Option<T>,
+) -> Result<(), AppError> {
+    let changed = match value {
+        Some(v) => {
+            map.insert(uuid.to_string(), v);
+            true
+        }
+        None => map.remove(uuid).is_some(),
+    };
+    if changed {
+        save_list_file(path, map)?;
+        info!("WorkerService, save file: {}, len: {}", path, map.len());
+    }
+    Ok(())
+}
+
+// 先写临时文件再rename，box_agent 不会读到写了一半的文件
+fn save_list_file<T: Serialize>(path: &str, map: &BTreeMap<String, T>) -> Result<(), AppError> {
+    let list: Vec<&T> = map.values().collect();
+    let content = serde_json::to_string_pretty(&list)?;
+
+    let tmp_path = format!("{}.tmp", path);
+    std::fs::write(&tmp_path, content)?;
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
@@ -1,8 +1,10 @@
+use crate::app_cfg::SyncEntity;
 use crate::app_ctx::AppCtx;
 use crate::error::AppError;
 use crate::model::queue_item::{RabbitmqItem, TaskItem, TaskItemType};
 use deadqueue::unlimited::Queue;
 use fy_base::util::ip::get_local_ips;
+use fy_base::util::metrics;
 use fy_base::util::service::Service;
 use std::sync::Arc;
 use tokio::sync::watch::Receiver;
@@ -11,9 +13,11 @@ use tracing::{debug, error, info};
 
 use crate::model::ResetPayload;
 
+use crate::service::wroker::reconcile::{build_rabbitmqitem_from_reconcile, do_reconcile};
+
 use crate::service::wroker::work::{
-    build_rabbitmqitem_from_status, delete_all_cameras, delete_all_dbs, do_sync_camera, do_sync_db,
-    do_sync_person, get_status_payload, reboot_box,
+    apply_camera_schedules, build_rabbitmqitem_from_status, delete_all_cameras, delete_all_dbs,
+    do_sync_camera, do_sync_db, do_sync_person, do_sync_plate, get_status_payload, reboot_box,
 };
 
 pub struct WorkerService {
@@ -45,6 +49,12 @@ impl WorkerService {
             TaskItemType::HeartBeat => {
                 self.process_task_status(item).await;
             }
+            TaskItemType::Schedule => {
+                self.process_task_schedule().await;
+            }
+            TaskItemType::Reconcile => {
+                self.process_task_reconcile(item).await;
+            }
             TaskItemType::ServerCmd => {
                 if item.sub_type == 0 {
                     // sync
@@ -55,6 +65,9 @@ impl WorkerService {
                 } else if item.sub_type == 2 {
                     // reboot
                     self.process_task_reboot(item).await;
+                } else if item.sub_type == 3 {
+                    // reconcile
+                    self.process_task_reconcile(item).await;
                 } else {
                     error!("error, Worker_service, unknown sub_type: {}", item.sub_type);
                 }
@@ -63,13 +76,17 @@ impl WorkerService {
     }
 
     async fn process_task_sync(&self, _item: TaskItem, exit_rx: Receiver<i64>) {
-        // -> camera -> db -> person
+        // -> camera -> plate -> db -> person
         // 先处理camera，camera数量较少,person数据量最多，最后处理。
-        // camera处理完，无论处理成功与否，继续处理 db，最后处理person
+        // camera处理完，无论处理成功与否，继续处理 plate、db，最后处理person
 
         let exited = match self.sync_camera(&exit_rx).await {
-            Ok(v) => v,
+            Ok(v) => {
+                metrics::inc_processed("sync_camera");
+                v
+            }
             Err(e) => {
+                metrics::inc_failed("sync_camera");
                 error!("error, Worker_service, sync_camera, err: {}", e);
                 false
             }
@@ -80,10 +97,35 @@ impl WorkerService {
             return;
         }
 
+        // 新同步的摄像头，立即按时间表启用/停用
+        self.process_task_schedule().await;
+
+        // 处理 sync plate
+        let exited = match self.sync_plate(&exit_rx).await {
+            Ok(v) => {
+                metrics::inc_processed("sync_plate");
+                v
+            }
+            Err(e) => {
+                metrics::inc_failed("sync_plate");
+                error!("error, Worker_service, sync_plate, err: {}", e);
+                false
+            }
+        };
+        self.ctx.save_sync_log();
+        if exited {
+            // 保存 sync_log
+            return;
+        }
+
         // 处理 sync db
         let exited = match self.sync_db(&exit_rx).await {
-            Ok(v) => v,
+            Ok(v) => {
+                metrics::inc_processed("sync_db");
+                v
+            }
             Err(e) => {
+                metrics::inc_failed("sync_db");
                 error!("error, Worker_service, sync_db, err: {}", e);
                 false
             }
@@ -96,8 +138,12 @@ impl WorkerService {
 
         // 处理 sync person
         let _exited = match self.sync_person(&exit_rx).await {
-            Ok(v) => v,
+            Ok(v) => {
+                metrics::inc_processed("sync_person");
+                v
+            }
             Err(e) => {
+                metrics::inc_failed("sync_person");
                 error!("error, Worker_service, sync_person, err: {}", e);
                 false
             }
@@ -107,21 +153,49 @@ impl WorkerService {
 
     // bool 表示是否收到退出信号
     async fn sync_camera(&self, _exit_rx: &Receiver<i64>) -> Result<bool, AppError> {
-        do_sync_camera(self.ctx.clone()).await
+        self.ctx.begin_sync_history(SyncEntity::Camera);
+        let rst = do_sync_camera(self.ctx.clone()).await;
+        self.ctx.end_sync_history(SyncEntity::Camera, &rst);
+        rst
+    }
+
+    async fn sync_plate(&self, _exit_rx: &Receiver<i64>) -> Result<bool, AppError> {
+        self.ctx.begin_sync_history(SyncEntity::Plate);
+        let rst = do_sync_plate(self.ctx.clone()).await;
+        self.ctx.end_sync_history(SyncEntity::Plate, &rst);
+        rst
     }
 
     async fn sync_db(&self, _exit_rx: &Receiver<i64>) -> Result<bool, AppError> {
-        do_sync_db(self.ctx.clone()).await
+        self.ctx.begin_sync_history(SyncEntity::Db);
+        let rst = do_sync_db(self.ctx.clone()).await;
+        self.ctx.end_sync_history(SyncEntity::Db, &rst);
+        rst
     }
 
     async fn sync_person(&self, _exit_rx: &Receiver<i64>) -> Result<bool, AppError> {
-        do_sync_person(self.ctx.clone()).await
+        self.ctx.begin_sync_history(SyncEntity::Person);
+        let rst = do_sync_person(self.ctx.clone()).await;
+        self.ctx.end_sync_history(SyncEntity::Person, &rst);
+        rst
+    }
+
+    async fn process_task_schedule(&self) {
+        match apply_camera_schedules(self.ctx.clone()).await {
+            Ok(_) => {
+                metrics::inc_processed("camera_schedule");
+            }
+            Err(e) => {
+                metrics::inc_failed("camera_schedule");
+                error!("error, Worker_service, apply_camera_schedules, err: {}", e);
+            }
+        }
     }
 
     async fn process_task_status(&self, item: TaskItem) {
         // 获取小盒子上，摄像头和db的数量情况，然后放到rabbitmq_queue中s
 
-        let status_payload =
+        let mut status_payload =
             match get_status_payload(item.id, &self.ctx.ana_api, &self.ctx.recg_api).await {
                 Ok(v) => v,
                 Err(e) => {
@@ -129,6 +203,7 @@ impl WorkerService {
                     return;
                 }
             };
+        status_payload.sync = self.ctx.get_sync_history();
 
         let ips = get_local_ips().join(",");
 
@@ -182,6 +257,25 @@ impl WorkerService {
         self.ctx.save_sync_log();
     }
 
+    // 对账，结果发送到 rabbitmq
+    async fn process_task_reconcile(&self, item: TaskItem) {
+        let payload = do_reconcile(self.ctx.clone(), item.id).await;
+        if payload.errors.is_empty() {
+            metrics::inc_processed("reconcile");
+        } else {
+            metrics::inc_failed("reconcile");
+        }
+
+        // 补同步的摄像头，立即按时间表启用/停用
+        self.process_task_schedule().await;
+
+        let ips = get_local_ips().join(",");
+        let rabbitmq_item = build_rabbitmqitem_from_reconcile(&self.ctx.hw_id, &ips, &payload);
+        debug!("WorkerService, push to rabbitmq_queue, {:?}", rabbitmq_item);
+
+        self.rabbitmq_queue.push(rabbitmq_item);
+    }
+
     async fn process_task_reboot(&self, _item: TaskItem) {
         // 重启
         debug!("WorkerService, process_task_reboot");
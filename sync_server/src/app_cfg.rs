@@ -1,5 +1,6 @@
-use crate::error::AppResult;
+use crate::error::{AppError, AppResult};
 use fy_base::util::mysql_util;
+use fy_base::util::tls::TlsServerCfg;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::Path;
@@ -38,6 +39,14 @@ pub struct AppCfgClean {
 pub struct AppCfgHttp {
     pub addr: String,
     pub max_conn: u64,
+    pub tls: TlsServerCfg,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AppCfgAuth {
+    pub enable: bool,   // 是否校验盒子请求签名
+    pub window: u64,    // 秒，时间戳允许的偏差，也是防重放的时间窗口
+    pub cache_ttl: u64, // 秒，盒子 secret 缓存时间
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -56,6 +65,7 @@ pub struct AppCfg {
     pub clean: AppCfgClean,
     pub sync_batch: u32,
     pub http: AppCfgHttp,
+    pub auth: AppCfgAuth,
     pub rabbitmq: AppCfgRabbitMq,
 }
 
@@ -68,6 +78,12 @@ impl AppCfg {
 
     pub fn validate(&self) -> AppResult<()> {
         let _ = mysql_util::parse_timezone(self.db.tz.as_str())?;
+        if let Err(e) = self.http.tls.validate() {
+            return Err(AppError::new(&format!("http.tls: {}", e)));
+        }
+        if self.auth.enable && self.auth.window == 0 {
+            return Err(AppError::new("auth.window must be > 0"));
+        }
 
         Ok(())
     }
@@ -34,6 +34,11 @@ pub struct BaseBox {
     /* 最新上线时间 */
     pub latest_online: Option<DateTime<Local>>,
 
+    /* 请求签名密钥;为空时盒子请求不能通过校验 */
+    /* 老的数据库没有这一列时为 None，升级见 upgrade_box_secret_mysql.sql */
+    #[sqlx(default)]
+    pub secret: Option<String>,
+
     /* 录入时间 */
     pub create_time: DateTime<Local>,
 
@@ -354,3 +359,58 @@ pub struct Facetrack {
     /* 创建时间 */
     pub create_time: DateTime<Local>,
 }
+/* 车牌布控 */
+#[derive(sqlx::FromRow, MysqlEntity, Serialize, Deserialize, Debug, Clone)]
+#[table = "base_plate"]
+pub struct BasePlate {
+    /* id */
+    #[pk]
+    pub id: i64,
+
+    /* uuid */
+    pub uuid: String,
+
+    /* 车牌号;支持通配符 * ? */
+    pub plate: String,
+
+    /* 布控类别;如 被盗,通缉 */
+    pub tag: Option<String>,
+
+    /* 备注 */
+    pub memo: Option<String>,
+
+    /* 生效时间;为空表示不限 */
+    pub valid_from: Option<DateTime<Local>>,
+
+    /* 失效时间;为空表示不限 */
+    pub valid_to: Option<DateTime<Local>>,
+
+    /* 创建时间 */
+    pub create_time: DateTime<Local>,
+
+    /* 更新时间 */
+    pub modify_time: DateTime<Local>,
+}
+/* 车牌布控删除表 */
+#[derive(sqlx::FromRow, MysqlEntity, Serialize, Deserialize, Debug, Clone)]
+#[table = "base_plate_del"]
+pub struct BasePlateDel {
+    /* id */
+    #[pk]
+    pub id: i64,
+
+    /* 原来表中的id */
+    pub origin_id: i32,
+
+    /* uuid */
+    pub uuid: String,
+
+    /* 车牌号 */
+    pub plate: String,
+
+    /* 创建时间 */
+    pub create_time: DateTime<Local>,
+
+    /* 更新时间;删除时间 */
+    pub modify_time: DateTime<Local>,
+}
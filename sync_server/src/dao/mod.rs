@@ -1,11 +1,14 @@
 use chrono::{DateTime, FixedOffset, Local};
+use fy_base::api::sync_api::{SyncCursor, SYNC_OP_DEL, SYNC_OP_MODIFY};
 use std::ops::Deref;
 use std::sync::Arc;
 
-use crate::dao::base_model::{BaseBox, BaseCamera, BaseCameraDel, BaseDb, BaseDbDel, BaseFeaDel};
+use crate::dao::base_model::{
+    BaseBox, BaseCamera, BaseCameraDel, BaseDb, BaseDbDel, BaseFeaDel, BasePlate, BasePlateDel,
+};
 use crate::error::AppError;
 use crate::service::web::model::BaseFeaMapRow;
-use fy_base::util::mysql_util;
+use fy_base::util::{metrics, mysql_util};
 use sqlx::{MySql, Pool};
 
 pub mod base_model;
@@ -17,10 +20,18 @@ pub struct Dao {
 }
 
 //--------------------------------
-// 从 A 表取 100条，从A_del表取100条，然后按照modify_time排序，取前100条
+// 从 A 表取 100条，从A_del表取100条，然后按照(modify_time, op, id)排序，取前100条
+// 按 (modify_time, id) 分页，同一个 modify_time 的记录超过一页时不会丢失
 
 impl Dao {
+    /// 检查连接池是否可用
+    pub async fn ping(&self) -> Result<(), AppError> {
+        sqlx::query("select 1").execute(self.pool.deref()).await?;
+        Ok(())
+    }
+
     pub async fn find_box(&self, hw_id: String) -> Result<Option<BaseBox>, AppError> {
+        let _timer = metrics::op_timer("mysql", "find_box");
         let sql = "select * from base_box where hw_id = ?";
         let mut obj = sqlx::query_as::<_, BaseBox>(sql)
             .bind(hw_id)
@@ -40,14 +51,17 @@ impl Dao {
 
     pub async fn get_db_list(
         &self,
-        last_update: DateTime<Local>,
+        cursor: &SyncCursor,
         limit: u32,
     ) -> Result<Vec<BaseDb>, AppError> {
-        let sql = "select * from base_db where modify_time > ? order by modify_time asc limit ?";
-        let last_update = mysql_util::fix_write_dt(&last_update, &self.tz);
+        let _timer = metrics::op_timer("mysql", "get_db_list");
+        let sql = "select * from base_db where modify_time >= ? and (modify_time > ? or id > ?) order by modify_time asc, id asc limit ?";
+        let last_update = mysql_util::fix_write_dt(&cursor.ts, &self.tz);
 
         let mut list = sqlx::query_as::<_, BaseDb>(sql)
             .bind(last_update)
+            .bind(last_update)
+            .bind(cursor.after_id(SYNC_OP_MODIFY))
             .bind(limit)
             .fetch_all(self.pool.deref())
             .await?;
@@ -62,15 +76,18 @@ impl Dao {
 
     pub async fn get_dbdel_list(
         &self,
-        last_update: DateTime<Local>,
+        cursor: &SyncCursor,
         limit: u32,
     ) -> Result<Vec<BaseDbDel>, AppError> {
+        let _timer = metrics::op_timer("mysql", "get_dbdel_list");
         let sql =
-            "select * from base_db_del where modify_time > ? order by modify_time asc limit ?";
-        let last_update = mysql_util::fix_write_dt(&last_update, &self.tz);
+            "select * from base_db_del where modify_time >= ? and (modify_time > ? or id > ?) order by modify_time asc, id asc limit ?";
+        let last_update = mysql_util::fix_write_dt(&cursor.ts, &self.tz);
 
         let mut list = sqlx::query_as::<_, BaseDbDel>(sql)
             .bind(last_update)
+            .bind(last_update)
+            .bind(cursor.after_id(SYNC_OP_DEL))
             .bind(limit)
             .fetch_all(self.pool.deref())
             .await?;
@@ -86,16 +103,19 @@ impl Dao {
     //------------------------------------------------
     pub async fn get_camera_list(
         &self,
-        last_update: DateTime<Local>,
+        cursor: &SyncCursor,
         box_deviceid: &str,
         limit: u32,
     ) -> Result<Vec<BaseCamera>, AppError> {
-        let sql = "select * from base_camera where box_deviceid = ? and modify_time > ? order by modify_time asc limit ?";
-        let last_update = mysql_util::fix_write_dt(&last_update, &self.tz);
+        let _timer = metrics::op_timer("mysql", "get_camera_list");
+        let sql = "select * from base_camera where box_deviceid = ? and modify_time >= ? and (modify_time > ? or id > ?) order by modify_time asc, id asc limit ?";
+        let last_update = mysql_util::fix_write_dt(&cursor.ts, &self.tz);
 
         let mut list = sqlx::query_as::<_, BaseCamera>(sql)
             .bind(box_deviceid)
             .bind(last_update)
+            .bind(last_update)
+            .bind(cursor.after_id(SYNC_OP_MODIFY))
             .bind(limit)
             .fetch_all(self.pool.deref())
             .await?;
@@ -110,16 +130,19 @@ impl Dao {
 
     pub async fn get_cameradel_list(
         &self,
-        last_update: DateTime<Local>,
+        cursor: &SyncCursor,
         box_deviceid: &str,
         limit: u32,
     ) -> Result<Vec<BaseCameraDel>, AppError> {
-        let sql = "select * from base_camera_del where box_deviceid = ? and modify_time > ? order by modify_time asc limit ?";
-        let last_update = mysql_util::fix_write_dt(&last_update, &self.tz);
+        let _timer = metrics::op_timer("mysql", "get_cameradel_list");
+        let sql = "select * from base_camera_del where box_deviceid = ? and modify_time >= ? and (modify_time > ? or id > ?) order by modify_time asc, id asc limit ?";
+        let last_update = mysql_util::fix_write_dt(&cursor.ts, &self.tz);
 
         let mut list = sqlx::query_as::<_, BaseCameraDel>(sql)
             .bind(box_deviceid)
             .bind(last_update)
+            .bind(last_update)
+            .bind(cursor.after_id(SYNC_OP_DEL))
             .bind(limit)
             .fetch_all(self.pool.deref())
             .await?;
@@ -153,15 +176,18 @@ impl Dao {
 
     pub async fn get_feadel_list(
         &self,
-        last_update: DateTime<Local>,
+        cursor: &SyncCursor,
         limit: u32,
     ) -> Result<Vec<BaseFeaDel>, AppError> {
+        let _timer = metrics::op_timer("mysql", "get_feadel_list");
         let sql =
-            "select * from base_fea_del where modify_time > ? order by modify_time asc limit ?";
-        let last_update = mysql_util::fix_write_dt(&last_update, &self.tz);
+            "select * from base_fea_del where modify_time >= ? and (modify_time > ? or id > ?) order by modify_time asc, id asc limit ?";
+        let last_update = mysql_util::fix_write_dt(&cursor.ts, &self.tz);
 
         let mut list = sqlx::query_as::<_, BaseFeaDel>(sql)
             .bind(last_update)
+            .bind(last_update)
+            .bind(cursor.after_id(SYNC_OP_DEL))
             .bind(limit)
             .fetch_all(self.pool.deref())
             .await?;
@@ -176,9 +202,10 @@ impl Dao {
 
     pub async fn get_feamap_row_list(
         &self,
-        last_update: DateTime<Local>,
+        cursor: &SyncCursor,
         limit: u32,
     ) -> Result<Vec<BaseFeaMapRow>, AppError> {
+        let _timer = metrics::op_timer("mysql", "get_feamap_row_list");
         /*
                 select a.id, a.uuid,a.modify_time, b.face_id,b.feature from (
             select * from base_fea where modify_time > '2022-06-11 16:53:02.000'
@@ -187,21 +214,157 @@ impl Dao {
         ORDER BY a.modify_time, a.uuid
                  */
 
-        let sql = r#"select a.id,a.db_uuid,a.uuid,b.face_id,b.feature,b.quality,a.modify_time from (
-	select * from base_fea where modify_time > ?
-	ORDER BY modify_time asc limit ?
+        let sql = r#"select a.id,a.db_uuid,a.uuid,b.face_id,b.feature,b.quality,a.modify_time,a.feature as aggregate from (
+	select * from base_fea where modify_time >= ? and (modify_time > ? or id > ?)
+	ORDER BY modify_time asc, id asc limit ?
     ) a  LEFT JOIN base_fea_map b on a.uuid = b.uuid where b.id is NOT NULL
-    ORDER BY a.modify_time, a.uuid
+    ORDER BY a.modify_time, a.id
         "#;
 
-        let last_update = mysql_util::fix_write_dt(&last_update, &self.tz);
+        let last_update = mysql_util::fix_write_dt(&cursor.ts, &self.tz);
 
         let mut list = sqlx::query_as::<_, BaseFeaMapRow>(sql)
             .bind(last_update)
+            .bind(last_update)
+            .bind(cursor.after_id(SYNC_OP_MODIFY))
+            .bind(limit)
+            .fetch_all(self.pool.deref())
+            .await?;
+
+        for v in list.iter_mut() {
+            mysql_util::fix_read_dt(&mut v.modify_time, &self.tz);
+        }
+
+        Ok(list)
+    }
+
+    //------------------------------------------------
+    pub async fn get_plate_list(
+        &self,
+        cursor: &SyncCursor,
+        limit: u32,
+    ) -> Result<Vec<BasePlate>, AppError> {
+        let _timer = metrics::op_timer("mysql", "get_plate_list");
+        let sql = "select * from base_plate where modify_time >= ? and (modify_time > ? or id > ?) order by modify_time asc, id asc limit ?";
+        let last_update = mysql_util::fix_write_dt(&cursor.ts, &self.tz);
+
+        let mut list = sqlx::query_as::<_, BasePlate>(sql)
+            .bind(last_update)
+            .bind(last_update)
+            .bind(cursor.after_id(SYNC_OP_MODIFY))
             .bind(limit)
             .fetch_all(self.pool.deref())
             .await?;
 
+        for v in list.iter_mut() {
+            mysql_util::fix_read_dt_option(&mut v.valid_from, &self.tz);
+            mysql_util::fix_read_dt_option(&mut v.valid_to, &self.tz);
+            mysql_util::fix_read_dt(&mut v.modify_time, &self.tz);
+            mysql_util::fix_read_dt(&mut v.create_time, &self.tz);
+        }
+
+        Ok(list)
+    }
+
+    pub async fn get_platedel_list(
+        &self,
+        cursor: &SyncCursor,
+        limit: u32,
+    ) -> Result<Vec<BasePlateDel>, AppError> {
+        let _timer = metrics::op_timer("mysql", "get_platedel_list");
+        let sql =
+            "select * from base_plate_del where modify_time >= ? and (modify_time > ? or id > ?) order by modify_time asc, id asc limit ?";
+        let last_update = mysql_util::fix_write_dt(&cursor.ts, &self.tz);
+
+        let mut list = sqlx::query_as::<_, BasePlateDel>(sql)
+            .bind(last_update)
+            .bind(last_update)
+            .bind(cursor.after_id(SYNC_OP_DEL))
+            .bind(limit)
+            .fetch_all(self.pool.deref())
+            .await?;
+
+        for v in list.iter_mut() {
+            mysql_util::fix_read_dt(&mut v.modify_time, &self.tz);
+            mysql_util::fix_read_dt(&mut v.create_time, &self.tz);
+        }
+
+        Ok(list)
+    }
+
+    //------------------------------------------------
+    // 对账用，取全部记录
+    pub async fn get_all_dbs(&self) -> Result<Vec<BaseDb>, AppError> {
+        let _timer = metrics::op_timer("mysql", "get_all_dbs");
+        let sql = "select * from base_db order by id asc";
+
+        let mut list = sqlx::query_as::<_, BaseDb>(sql)
+            .fetch_all(self.pool.deref())
+            .await?;
+
+        for v in list.iter_mut() {
+            mysql_util::fix_read_dt(&mut v.modify_time, &self.tz);
+            mysql_util::fix_read_dt(&mut v.create_time, &self.tz);
+        }
+
+        Ok(list)
+    }
+
+    pub async fn get_all_cameras(&self, box_deviceid: &str) -> Result<Vec<BaseCamera>, AppError> {
+        let _timer = metrics::op_timer("mysql", "get_all_cameras");
+        let sql = "select * from base_camera where box_deviceid = ? order by id asc";
+
+        let mut list = sqlx::query_as::<_, BaseCamera>(sql)
+            .bind(box_deviceid)
+            .fetch_all(self.pool.deref())
+            .await?;
+
+        for v in list.iter_mut() {
+            mysql_util::fix_read_dt(&mut v.modify_time, &self.tz);
+            mysql_util::fix_read_dt(&mut v.create_time, &self.tz);
+        }
+
+        Ok(list)
+    }
+
+    // 和增量同步一致，没有特征值的人员不下发
+    pub async fn get_person_ids(&self, db_uuid: &str) -> Result<Vec<String>, AppError> {
+        let _timer = metrics::op_timer("mysql", "get_person_ids");
+        let sql = r#"select distinct a.uuid from base_fea a
+    JOIN base_fea_map b on a.uuid = b.uuid where a.db_uuid = ?"#;
+
+        let list = sqlx::query_scalar::<_, String>(sql)
+            .bind(db_uuid)
+            .fetch_all(self.pool.deref())
+            .await?;
+
+        Ok(list)
+    }
+
+    pub async fn get_feamap_rows_by_uuids(
+        &self,
+        uuids: &[String],
+    ) -> Result<Vec<BaseFeaMapRow>, AppError> {
+        let _timer = metrics::op_timer("mysql", "get_feamap_rows_by_uuids");
+        if uuids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let holders = vec!["?"; uuids.len()].join(",");
+        let sql = format!(
+            r#"select a.id,a.db_uuid,a.uuid,b.face_id,b.feature,b.quality,a.modify_time,a.feature as aggregate
+    from base_fea a LEFT JOIN base_fea_map b on a.uuid = b.uuid
+    where b.id is NOT NULL and a.uuid in ({})
+    ORDER BY a.id"#,
+            holders
+        );
+
+        let mut query = sqlx::query_as::<_, BaseFeaMapRow>(&sql);
+        for uuid in uuids.iter() {
+            query = query.bind(uuid);
+        }
+        let mut list = query.fetch_all(self.pool.deref()).await?;
+
         for v in list.iter_mut() {
             mysql_util::fix_read_dt(&mut v.modify_time, &self.tz);
         }
@@ -214,6 +377,7 @@ impl Dao {
         hw_id: &str,
         ts: DateTime<Local>,
     ) -> Result<bool, AppError> {
+        let _timer = metrics::op_timer("mysql", "update_latest_online");
         let sql = "update base_box set latest_online = ? where hw_id = ?";
 
         let ts = mysql_util::fix_write_dt(&ts, &self.tz);
@@ -227,6 +391,7 @@ impl Dao {
     }
 
     pub async fn clean_boxlog(&self, ts: DateTime<Local>) -> Result<u64, AppError> {
+        let _timer = metrics::op_timer("mysql", "clean_boxlog");
         let sql = "delete from base_box_log where create_time < ?";
 
         let ts = mysql_util::fix_write_dt(&ts, &self.tz);
@@ -25,7 +25,7 @@ use crate::app_ctx::AppCtx;
 use crate::error::AppError;
 use crate::service::rabbitmq::process_message::process_boxlog_message;
 use fy_base::util::rabbitmq::shutdown_rabbitmq;
-use fy_base::util::{rabbitmq::init_conn_props, service::Service};
+use fy_base::util::{health, metrics, rabbitmq::init_conn_props, service::Service};
 
 pub struct RabbitmqService {
     pub ctx: Arc<AppCtx>,
@@ -184,6 +184,8 @@ impl RabbitmqService {
                     "error, RabbitmqService, init_rabbitmq_connection, err: {:?}",
                     e
                 );
+                metrics::inc_reconnect("rabbitmq");
+                health::set_err("rabbitmq", &e);
                 let wait = self.increate_wait();
                 let exited = Self::wait_a_moment(Duration::from_secs(wait), exit_rx.clone()).await;
                 if exited {
@@ -203,6 +205,8 @@ impl RabbitmqService {
                     "error, RabbitmqService, init_rabbitmq_exchange_queue, err: {:?}",
                     e
                 );
+                metrics::inc_reconnect("rabbitmq");
+                health::set_err("rabbitmq", &e);
                 let wait = self.increate_wait();
                 let exited = Self::wait_a_moment(Duration::from_secs(wait), exit_rx.clone()).await;
                 if exited {
@@ -229,9 +233,12 @@ impl RabbitmqService {
                 .unwrap();
 
             debug!("RabbitmqService, rabbitmq inited");
+            health::set_ok("rabbitmq");
 
             if let Err(e) = self.loop_message(&mut consumer, exit_rx.clone()).await {
                 error!("error, RabbitmqService, loop_message, err: {:?}", e);
+                metrics::inc_reconnect("rabbitmq");
+                health::set_err("rabbitmq", &e);
                 let wait = self.increate_wait();
                 let exited = Self::wait_a_moment(Duration::from_secs(wait), exit_rx.clone()).await;
                 if exited {
@@ -254,6 +261,7 @@ impl RabbitmqService {
 impl Service for RabbitmqService {
     fn run(self, exit_rx: Receiver<i64>) -> JoinHandle<()> {
         let conn_props = init_conn_props();
+        health::register("rabbitmq");
         let this = self;
         tokio::spawn(this.do_run(conn_props, exit_rx))
     }
@@ -1,6 +1,7 @@
 use crate::dao::base_model::BaseBoxLog;
 use crate::dao::Dao;
 use fy_base::sync::rabbitmq_type::BoxLogMessage;
+use fy_base::util::metrics;
 use lapin::message::Delivery;
 
 use tracing::{error, warn};
@@ -10,6 +11,7 @@ pub async fn process_boxlog_message(dao: Dao, delivery: Delivery) {
     let message = match serde_json::from_reader::<_, BoxLogMessage>(delivery.data.as_slice()) {
         Ok(v) => v,
         Err(e) => {
+            metrics::inc_failed("boxlog");
             error!(
                 "error, RabbitmqService, process_boxlog_message, err: {:?}",
                 e
@@ -22,11 +24,16 @@ pub async fn process_boxlog_message(dao: Dao, delivery: Delivery) {
     let obj: BaseBoxLog = message.into();
 
     // 保存
-    match obj.insert(&dao.pool, &dao.tz).await {
+    let timer = metrics::op_timer("mysql", "insert_boxlog");
+    let saved = obj.insert(&dao.pool, &dao.tz).await;
+    timer.observe_duration();
+    match saved {
         Ok(_v) => {
             // 保存成功
+            metrics::inc_processed("boxlog");
         }
         Err(e) => {
+            metrics::inc_failed("boxlog");
             error!(
                 "error, RabbitmqService, process_boxlog_message, err: {:?}",
                 e
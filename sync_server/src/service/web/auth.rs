@@ -0,0 +1,76 @@
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use tracing::warn;
+
+use fy_base::api::sync_api::{ResponseData, RES_STATUS_AUTH_ERR};
+use fy_base::util::sign::{self, AuthBox, AuthError, VerifyError};
+
+use crate::error::AppError;
+use crate::service::web::model::build_fail_response_data;
+use crate::service::web::WebState;
+
+// 盒子调用的都是 GET 接口
+const MAX_BODY: u64 = 1024 * 64;
+
+fn build_auth_err_response(e: VerifyError) -> Response {
+    let msg = format!("auth failed: {:?}", e);
+    (
+        StatusCode::UNAUTHORIZED,
+        build_fail_response_data(RES_STATUS_AUTH_ERR, &msg),
+    )
+        .into_response()
+}
+
+/// 校验盒子身份，见 sign::verify_box。盒子只能查询自己的数据(hw_id)
+pub async fn verify_box(req: Request<Body>, next: Next<Body>) -> Response {
+    let state = match req.extensions().get::<Arc<WebState>>() {
+        Some(v) => v.clone(),
+        None => {
+            return next.run(req).await;
+        }
+    };
+
+    let ctx = state.ctx.clone();
+    let find_secret = |box_id: String| async move {
+        let secret = ctx
+            .dao
+            .find_box(box_id)
+            .await?
+            .map(|x| x.secret.unwrap_or_default());
+        Ok::<_, AppError>(secret)
+    };
+    let verifier = state.verifier.as_ref();
+    let req = match sign::verify_box(req, verifier, &state.secrets, MAX_BODY, find_secret).await {
+        Ok(v) => v,
+        Err(AuthError::Verify(e)) => {
+            return build_auth_err_response(e);
+        }
+        Err(AuthError::TooLarge) => {
+            return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+        }
+        Err(AuthError::ReadBody(_)) => {
+            return StatusCode::BAD_REQUEST.into_response();
+        }
+        Err(AuthError::Lookup(e)) => {
+            return ResponseData::<()>::from(e).into_response();
+        }
+    };
+
+    if let Some(AuthBox(box_id)) = req.extensions().get::<AuthBox>() {
+        let hw_id = req.uri().query().and_then(|x| {
+            url::form_urlencoded::parse(x.as_bytes())
+                .find(|(k, _)| k == "hw_id")
+                .map(|(_, v)| v.to_string())
+        });
+        if hw_id.is_some_and(|x| x != *box_id) {
+            warn!("warn, verify_box, box: {} query other hw_id", box_id);
+            return build_auth_err_response(VerifyError::UnknownBox(box_id.clone()));
+        }
+    }
+
+    next.run(req).await
+}
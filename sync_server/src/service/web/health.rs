@@ -0,0 +1,18 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::response::Response;
+use axum::Extension;
+
+use crate::service::web::WebState;
+use fy_base::util::health;
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+//-----------------------------------
+/// mysql 在这里检查，rabbitmq 由 RabbitmqService 上报
+pub async fn readyz(Extension(web_state): Extension<Arc<WebState>>) -> Response {
+    health::probe("mysql", PROBE_TIMEOUT, web_state.ctx.dao.ping()).await;
+
+    health::readyz_response()
+}
@@ -1,15 +1,32 @@
+use axum::extract::connect_info::Connected;
 use axum::routing::get;
 use axum::{middleware, Router, Server};
-use hyper::server::conn::AddrIncoming;
+use hyper::server::accept::Accept;
 use hyper::server::Builder;
 use std::net::{AddrParseError, SocketAddr};
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::{
     app_ctx::AppCtx,
-    service::web::sync::{get_camera_update, get_db_update, get_person_update},
+    service::web::auth::verify_box,
+    service::web::health::readyz,
+    service::web::sync::{
+        get_camera_all, get_camera_update, get_db_all, get_db_update, get_person_ids,
+        get_person_list, get_person_update, get_plate_update,
+    },
 };
-use fy_base::util::{axum_log::access_log, axum_log::time_use, service::Service};
+use fy_base::util::{
+    axum_log::access_log,
+    axum_log::time_use,
+    health::healthz_handler,
+    metrics::metrics_handler,
+    service::Service,
+    sign::{SecretCache, Verifier},
+    tls::{self, PeerInfo},
+};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
 use tokio::sync::watch::Receiver;
 use tokio::task::JoinHandle;
 use tower::limit::GlobalConcurrencyLimitLayer;
@@ -18,11 +35,15 @@ use tower_http::add_extension::AddExtensionLayer;
 
 use tracing::{error, info};
 
+pub mod auth;
+pub mod health;
 pub mod model;
 pub mod sync;
 
 pub struct WebState {
     pub ctx: Arc<AppCtx>,
+    pub verifier: Option<Verifier>, // 没有开启 auth 时为 None
+    pub secrets: SecretCache,
 }
 
 pub struct WebService {
@@ -37,14 +58,30 @@ impl WebService {
     pub async fn init_router(&self) -> Router {
         let max_request_conn = self.ctx.cfg.http.max_conn as usize;
 
+        let auth = &self.ctx.cfg.auth;
         let web_state = Arc::new(WebState {
             ctx: self.ctx.clone(),
+            verifier: auth.enable.then(|| Verifier::new(auth.window)),
+            secrets: SecretCache::new(Duration::from_secs(auth.cache_ttl)),
         });
 
-        Router::new()
+        // 盒子调用的接口需要校验身份
+        let box_router = Router::new()
             .route("/db_sync", get(get_db_update))
             .route("/person_sync", get(get_person_update))
             .route("/camera_sync", get(get_camera_update))
+            .route("/plate_sync", get(get_plate_update))
+            .route("/db_list", get(get_db_all))
+            .route("/camera_list", get(get_camera_all))
+            .route("/person_ids", get(get_person_ids))
+            .route("/person_list", get(get_person_list))
+            .route_layer(middleware::from_fn(verify_box));
+
+        Router::new()
+            .merge(box_router)
+            .route("/metrics", get(metrics_handler))
+            .route("/healthz", get(healthz_handler))
+            .route("/readyz", get(readyz))
             .layer(
                 ServiceBuilder::new()
                     // 限制请求的并发数量
@@ -68,7 +105,13 @@ impl WebService {
         addr.parse::<SocketAddr>()
     }
 
-    async fn run(self, server: Builder<AddrIncoming>, exit_rx: Receiver<i64>) {
+    async fn run<I>(self, server: Builder<I>, exit_rx: Receiver<i64>)
+    where
+        I: Accept + Send + 'static,
+        I::Conn: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        I::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        for<'a> PeerInfo: Connected<&'a I::Conn>,
+    {
         let mut exit_rx = exit_rx;
         let mut exit_rx_cl = exit_rx.clone();
 
@@ -76,7 +119,7 @@ impl WebService {
         let app = self.init_router().await;
 
         let graceful = server
-            .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+            .serve(app.into_make_service_with_connect_info::<PeerInfo>())
             .with_graceful_shutdown(async move {
                 let _ = exit_rx_cl.changed().await;
                 info!("with_graceful_shutdown.");
@@ -102,6 +145,21 @@ impl Service for WebService {
             .unwrap_or_else(|_| panic!("cant parse http addr: {}", self.ctx.cfg.http.addr));
         info!("http bind: {:?}", addr);
 
+        // https，可以要求客户端证书
+        let tls_cfg = &self.ctx.cfg.http.tls;
+        if tls_cfg.enable {
+            let config = tls_cfg.load().unwrap_or_else(|e| {
+                error!("error, load http tls, err: {}", e);
+                panic!("error, load http tls, err: {}", e)
+            });
+            let listener = bind_listener(addr).unwrap_or_else(|e| {
+                error!("error, https can't bind: {:?}, err: {:?}", addr, e);
+                panic!("error, https can't bind: {:?}, err: {:?}", addr, e)
+            });
+            let server = Server::builder(tls::incoming(listener, config));
+            return tokio::spawn(self.run(server, exit_rx));
+        }
+
         let server = Server::try_bind(&addr).unwrap_or_else(|e| {
             error!("error, http can't bind: {:?}, err: {:?}", addr, e);
             panic!("error, http can't bind: {:?}, err: {:?}", addr, e)
@@ -110,3 +168,10 @@ impl Service for WebService {
         tokio::spawn(self.run(server, exit_rx))
     }
 }
+
+//-----------------------
+fn bind_listener(addr: SocketAddr) -> std::io::Result<TcpListener> {
+    let listener = std::net::TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    TcpListener::from_std(listener)
+}
@@ -2,13 +2,15 @@ use chrono::prelude::*;
 
 use std::collections::HashMap;
 
-use crate::dao::base_model::{BaseCamera, BaseCameraDel, BaseDb, BaseDbDel, BaseFeaDel};
+use crate::dao::base_model::{
+    BaseCamera, BaseCameraDel, BaseDb, BaseDbDel, BaseFeaDel, BasePlate, BasePlateDel,
+};
 use crate::error::AppError;
 use serde::{Deserialize, Serialize};
 
 use fy_base::api::sync_api::{
-    Camera, CameraInfo, Db, Person, PersonInfoFace, ResponseData, RES_STATUS_ERROR, SYNC_OP_DEL,
-    SYNC_OP_MODIFY,
+    Camera, CameraInfo, Db, Person, PersonInfoFace, Plate, PlateInfo, ResponseData,
+    RES_STATUS_ERROR, SYNC_OP_DEL, SYNC_OP_MODIFY,
 };
 
 //----------------------------------
@@ -21,6 +23,8 @@ pub struct BaseFeaMapRow {
     pub feature: String,
     pub quality: f32,
     pub modify_time: DateTime<Local>,
+    // base_fea 中的聚合特征
+    pub aggregate: Option<String>,
 }
 
 //----------------------------------
@@ -31,6 +35,7 @@ impl From<AppError> for ResponseData<()> {
             message: Some(e.msg),
             ts: Local::now(),
             data: None,
+            next: None,
         }
     }
 }
@@ -55,6 +60,7 @@ pub fn build_fail_response_data(status: i32, message: &str) -> ResponseData<()>
         message: Some(message.to_string()),
         ts: Local::now(),
         data: None,
+        next: None,
     }
 }
 
@@ -63,6 +69,7 @@ impl From<BaseDb> for Db {
     fn from(obj: BaseDb) -> Db {
         Db {
             id: obj.id.to_string(),
+            seq: Some(obj.id),
             uuid: obj.uuid,
             op: SYNC_OP_MODIFY,
             last_update: obj.modify_time,
@@ -75,6 +82,7 @@ impl From<BaseDbDel> for Db {
     fn from(obj: BaseDbDel) -> Db {
         Db {
             id: obj.origin_id.to_string(),
+            seq: Some(obj.id),
             uuid: obj.uuid,
             op: SYNC_OP_DEL,
             last_update: obj.modify_time,
@@ -87,6 +95,7 @@ impl From<BaseCamera> for Camera {
     fn from(obj: BaseCamera) -> Camera {
         Camera {
             id: obj.id.to_string(),
+            seq: Some(obj.id),
             uuid: obj.uuid.clone(),
             op: SYNC_OP_MODIFY,
             last_update: obj.modify_time,
@@ -105,6 +114,7 @@ impl From<BaseCameraDel> for Camera {
     fn from(obj: BaseCameraDel) -> Camera {
         Camera {
             id: obj.origin_id.to_string(),
+            seq: Some(obj.id),
             uuid: obj.uuid.clone(),
             op: SYNC_OP_DEL,
             last_update: obj.modify_time,
@@ -115,12 +125,48 @@ impl From<BaseCameraDel> for Camera {
     }
 }
 
+impl From<BasePlate> for Plate {
+    fn from(obj: BasePlate) -> Plate {
+        Plate {
+            id: obj.id.to_string(),
+            seq: Some(obj.id),
+            uuid: obj.uuid.clone(),
+            op: SYNC_OP_MODIFY,
+            last_update: obj.modify_time,
+
+            detail: Some(PlateInfo {
+                uuid: obj.uuid,
+                plate: obj.plate,
+                tag: obj.tag,
+                memo: obj.memo,
+                valid_from: obj.valid_from,
+                valid_to: obj.valid_to,
+            }),
+        }
+    }
+}
+
+impl From<BasePlateDel> for Plate {
+    fn from(obj: BasePlateDel) -> Plate {
+        Plate {
+            id: obj.origin_id.to_string(),
+            seq: Some(obj.id),
+            uuid: obj.uuid,
+            op: SYNC_OP_DEL,
+            last_update: obj.modify_time,
+
+            detail: None,
+        }
+    }
+}
+
 //----------------------------
 
 impl From<BaseFeaDel> for Person {
     fn from(obj: BaseFeaDel) -> Person {
         Person {
             id: obj.origin_id.to_string(),
+            seq: Some(obj.id),
             uuid: obj.uuid,
             db_id: obj.db_uuid,
             op: SYNC_OP_DEL,
@@ -137,17 +183,14 @@ pub fn get_personinfo_from_map(rows: Vec<BaseFeaMapRow>) -> Vec<Person> {
     let mut map: HashMap<String, Person> = HashMap::new();
 
     for v in rows.iter() {
-        let face = PersonInfoFace {
-            fea: v.feature.clone(),
-            quality: v.quality,
-            id: v.face_id.to_string(),
-        };
+        let face = PersonInfoFace::new(v.face_id.to_string(), v.feature.clone(), v.quality);
         let person = map.get_mut(&v.uuid);
         match person {
             None => {
                 // 不存在，new一个
                 let mut person_new = Person {
                     id: v.id.to_string(),
+                    seq: Some(v.id),
                     uuid: v.uuid.clone(),
                     db_id: v.db_uuid.clone(),
                     op: SYNC_OP_MODIFY,
@@ -155,6 +198,10 @@ pub fn get_personinfo_from_map(rows: Vec<BaseFeaMapRow>) -> Vec<Person> {
                     detail: None,
                 };
                 person_new.add_face(face);
+                if let Some(ref mut detail) = person_new.detail {
+                    // 没有聚合特征时该列为空字符串
+                    detail.aggregate = v.aggregate.clone().filter(|x| !x.trim().is_empty());
+                }
                 map.insert(v.uuid.clone(), person_new);
             }
             Some(vv) => {
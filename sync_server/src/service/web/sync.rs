@@ -1,7 +1,9 @@
+use crate::dao::base_model::BaseBox;
 use crate::service::web::model::{build_fail_response_data, get_personinfo_from_map};
 
 use fy_base::api::sync_api::{
-    Camera, Db, Person, ResponseData, RES_STATUS_BIZ_ERR, RES_STATUS_INVALID_PARA,
+    Camera, Db, Person, Plate, ResponseData, SyncCursor, SyncItem, RES_STATUS_BIZ_ERR,
+    RES_STATUS_INVALID_PARA,
 };
 
 use crate::service::web::WebState;
@@ -15,12 +17,15 @@ use serde::Deserialize;
 use std::sync::Arc;
 use tracing::{debug, error};
 
-fn build_success_response<T>(list: Vec<T>) -> ResponseData<T> {
+// next 为最后一条记录的游标
+fn build_success_response<T: SyncItem>(list: Vec<T>) -> ResponseData<T> {
+    let next = list.last().map(|x| x.cursor().encode());
     ResponseData {
         status: 0,
         message: Some("success".to_string()),
         ts: Local::now(),
         data: Some(list),
+        next,
     }
 }
 
@@ -56,11 +61,30 @@ fn check_para_lastupdate(para: &Option<String>) -> bool {
     }
 }
 
+fn check_para_cursor(para: &Option<String>) -> bool {
+    match para {
+        Some(ref v) if !v.is_empty() => SyncCursor::decode(v).is_some(),
+        _ => true,
+    }
+}
+
+// 新版本的盒子带 cursor，旧版本的只有 last_update
+fn get_cursor(cursor: Option<String>, last_update: String) -> SyncCursor {
+    match cursor.as_deref().and_then(SyncCursor::decode) {
+        Some(v) => v,
+        None => {
+            let ts = utils::parse_localtime_str(&last_update, DATETIME_FMT_LONG).unwrap();
+            SyncCursor::from_ts(ts)
+        }
+    }
+}
+
 //----------------------------- db sync  --------------------------------------
 #[derive(Debug, Deserialize)]
 pub struct DbUpdateParas {
     hw_id: Option<String>,
     last_update: Option<String>,
+    cursor: Option<String>,
 }
 
 fn check_dbupdate_paras(paras: &DbUpdateParas) -> Result<(), ResponseData<()>> {
@@ -70,6 +94,9 @@ fn check_dbupdate_paras(paras: &DbUpdateParas) -> Result<(), ResponseData<()>> {
     if !check_para_lastupdate(&paras.last_update) {
         return Err(build_invalid_paras_response("invalid last_update"));
     }
+    if !check_para_cursor(&paras.cursor) {
+        return Err(build_invalid_paras_response("invalid cursor"));
+    }
 
     Ok(())
 }
@@ -84,8 +111,7 @@ pub async fn get_db_update(
     let _ = check_dbupdate_paras(&paras)?;
 
     let hw_id = paras.hw_id.unwrap();
-    let last_update =
-        utils::parse_localtime_str(paras.last_update.unwrap().as_str(), DATETIME_FMT_LONG).unwrap();
+    let cursor = get_cursor(paras.cursor, paras.last_update.unwrap());
 
     // 检查 box，是否需要同步 db
     let base_box = match state.ctx.dao.find_box(hw_id.clone()).await {
@@ -109,7 +135,7 @@ pub async fn get_db_update(
     };
 
     let limit = state.ctx.cfg.sync_batch;
-    let db_update = match state.ctx.dao.get_db_list(last_update, limit).await {
+    let db_update = match state.ctx.dao.get_db_list(&cursor, limit).await {
         Ok(v) => v,
         Err(e) => {
             error!("error, get_db_list({}), err: {:?}", hw_id, e);
@@ -117,7 +143,7 @@ pub async fn get_db_update(
         }
     };
 
-    let db_del_update = match state.ctx.dao.get_dbdel_list(last_update, limit).await {
+    let db_del_update = match state.ctx.dao.get_dbdel_list(&cursor, limit).await {
         Ok(v) => v,
         Err(e) => {
             error!("error, get_dbdel_list({}), err: {:?}", hw_id, e);
@@ -141,8 +167,8 @@ pub async fn get_db_update(
         list.push(v.into());
     }
 
-    // 根据 last_update排序
-    list.sort_by(|a, b| a.last_update.cmp(&b.last_update));
+    // 根据 (last_update, op, id)排序
+    list.sort_by_key(|x| x.cursor());
 
     // 取前N条记录
     list.truncate(limit as usize);
@@ -158,6 +184,7 @@ pub async fn get_db_update(
 pub struct PersonUpdateParas {
     hw_id: Option<String>,
     last_update: Option<String>,
+    cursor: Option<String>,
 }
 
 fn check_personupdate_paras(paras: &PersonUpdateParas) -> Result<(), ResponseData<()>> {
@@ -167,6 +194,9 @@ fn check_personupdate_paras(paras: &PersonUpdateParas) -> Result<(), ResponseDat
     if !check_para_lastupdate(&paras.last_update) {
         return Err(build_invalid_paras_response("invalid last_update"));
     }
+    if !check_para_cursor(&paras.cursor) {
+        return Err(build_invalid_paras_response("invalid cursor"));
+    }
 
     Ok(())
 }
@@ -181,8 +211,7 @@ pub async fn get_person_update(
     let _ = check_personupdate_paras(&paras)?;
 
     let hw_id = paras.hw_id.unwrap();
-    let last_update =
-        utils::parse_localtime_str(paras.last_update.unwrap().as_str(), DATETIME_FMT_LONG).unwrap();
+    let cursor = get_cursor(paras.cursor, paras.last_update.unwrap());
 
     // 检查 box，是否需要同步 person
     let base_box = match state.ctx.dao.find_box(hw_id.clone()).await {
@@ -206,10 +235,10 @@ pub async fn get_person_update(
     };
 
     let limit = state.ctx.cfg.sync_batch;
-    let list_update = match state.ctx.dao.get_feamap_row_list(last_update, limit).await {
+    let list_update = match state.ctx.dao.get_feamap_row_list(&cursor, limit).await {
         Ok(v) => v,
         Err(e) => {
-            error!("error, get_feamap_row_list({}), err: {:?}", last_update, e);
+            error!("error, get_feamap_row_list({:?}), err: {:?}", cursor, e);
             return Err(e.into());
         }
     };
@@ -217,10 +246,10 @@ pub async fn get_person_update(
     // 从fea_map 转成 Person
     let mut list_update = get_personinfo_from_map(list_update);
 
-    let list_del_update = match state.ctx.dao.get_feadel_list(last_update, limit).await {
+    let list_del_update = match state.ctx.dao.get_feadel_list(&cursor, limit).await {
         Ok(v) => v,
         Err(e) => {
-            error!("error, get_feadel_list({}), err: {:?}", last_update, e);
+            error!("error, get_feadel_list({:?}), err: {:?}", cursor, e);
             return Err(e.into());
         }
     };
@@ -245,8 +274,8 @@ pub async fn get_person_update(
         list.push(v.into());
     }
 
-    // 根据 last_update排序
-    list.sort_by(|a, b| a.last_update.cmp(&b.last_update));
+    // 根据 (last_update, op, id)排序
+    list.sort_by_key(|x| x.cursor());
 
     // 取前N条记录
     list.truncate(limit as usize);
@@ -260,6 +289,7 @@ pub async fn get_person_update(
 pub struct CameraUpdateParas {
     hw_id: Option<String>,
     last_update: Option<String>,
+    cursor: Option<String>,
 }
 
 fn check_cameraupdate_paras(paras: &CameraUpdateParas) -> Result<(), ResponseData<()>> {
@@ -269,6 +299,9 @@ fn check_cameraupdate_paras(paras: &CameraUpdateParas) -> Result<(), ResponseDat
     if !check_para_lastupdate(&paras.last_update) {
         return Err(build_invalid_paras_response("invalid last_update"));
     }
+    if !check_para_cursor(&paras.cursor) {
+        return Err(build_invalid_paras_response("invalid cursor"));
+    }
 
     Ok(())
 }
@@ -283,8 +316,7 @@ pub async fn get_camera_update(
     let _ = check_cameraupdate_paras(&paras)?;
 
     let hw_id = paras.hw_id.unwrap();
-    let last_update =
-        utils::parse_localtime_str(paras.last_update.unwrap().as_str(), DATETIME_FMT_LONG).unwrap();
+    let cursor = get_cursor(paras.cursor, paras.last_update.unwrap());
 
     // 检查 box，是否需要同步 camera
     let base_box = match state.ctx.dao.find_box(hw_id.clone()).await {
@@ -312,7 +344,7 @@ pub async fn get_camera_update(
     let list_update = match state
         .ctx
         .dao
-        .get_camera_list(last_update, &device_id, limit)
+        .get_camera_list(&cursor, &device_id, limit)
         .await
     {
         Ok(v) => v,
@@ -324,7 +356,7 @@ pub async fn get_camera_update(
     let list_del_update = match state
         .ctx
         .dao
-        .get_cameradel_list(last_update, &device_id, limit)
+        .get_cameradel_list(&cursor, &device_id, limit)
         .await
     {
         Ok(v) => v,
@@ -354,8 +386,8 @@ pub async fn get_camera_update(
         list.push(v.into());
     }
 
-    // 根据 last_update排序
-    list.sort_by(|a, b| a.last_update.cmp(&b.last_update));
+    // 根据 (last_update, op, id)排序
+    list.sort_by_key(|x| x.cursor());
 
     // 取前N条记录
     list.truncate(limit as usize);
@@ -364,3 +396,261 @@ pub async fn get_camera_update(
     // 返回值
     Ok(build_success_response(list))
 }
+
+//----------------------------- plate sync  --------------------------------------
+#[derive(Debug, Deserialize)]
+pub struct PlateUpdateParas {
+    hw_id: Option<String>,
+    last_update: Option<String>,
+    cursor: Option<String>,
+}
+
+fn check_plateupdate_paras(paras: &PlateUpdateParas) -> Result<(), ResponseData<()>> {
+    if !check_para_exist(&paras.hw_id) {
+        return Err(build_invalid_paras_response("invalid hw_id"));
+    }
+    if !check_para_lastupdate(&paras.last_update) {
+        return Err(build_invalid_paras_response("invalid last_update"));
+    }
+    if !check_para_cursor(&paras.cursor) {
+        return Err(build_invalid_paras_response("invalid cursor"));
+    }
+
+    Ok(())
+}
+
+pub async fn get_plate_update(
+    Extension(state): Extension<Arc<WebState>>,
+    Query(paras): Query<PlateUpdateParas>,
+) -> Result<ResponseData<Plate>, ResponseData<()>> {
+    debug!("get_plate_update, paras: {:?}", paras);
+
+    // 检查参数
+    check_plateupdate_paras(&paras)?;
+
+    let hw_id = paras.hw_id.unwrap();
+    let cursor = get_cursor(paras.cursor, paras.last_update.unwrap());
+
+    // 检查 box，是否需要同步，车牌布控是所有盒子共用的
+    let base_box = match state.ctx.dao.find_box(hw_id.clone()).await {
+        Ok(v) => v,
+        Err(e) => {
+            error!("error, find_box({}), err: {:?}", hw_id, e);
+            return Err(e.into());
+        }
+    };
+    match base_box {
+        None => {
+            // 不在硬件表中
+            return Err(build_device_notfound_response(hw_id.as_str()));
+        }
+        Some(ref v) => {
+            // 不需要同步
+            if v.sync_flag == 0 {
+                return Ok(build_success_response(vec![]));
+            }
+        }
+    };
+
+    let limit = state.ctx.cfg.sync_batch;
+    let list_update = match state.ctx.dao.get_plate_list(&cursor, limit).await {
+        Ok(v) => v,
+        Err(e) => {
+            error!("error, get_plate_list({}), err: {:?}", hw_id, e);
+            return Err(e.into());
+        }
+    };
+
+    let list_del_update = match state.ctx.dao.get_platedel_list(&cursor, limit).await {
+        Ok(v) => v,
+        Err(e) => {
+            error!("error, get_platedel_list({}), err: {:?}", hw_id, e);
+            return Err(e.into());
+        }
+    };
+
+    debug!("get_plate_update, {}, update: {}", hw_id, list_update.len());
+    debug!(
+        "get_plate_update, {}, del_update: {}",
+        hw_id,
+        list_del_update.len()
+    );
+
+    // 合并 plate和 plate_del的记录
+    let mut list: Vec<Plate> = vec![];
+    for v in list_update {
+        list.push(v.into());
+    }
+    for v in list_del_update {
+        list.push(v.into());
+    }
+
+    // 根据 (last_update, op, id)排序
+    list.sort_by_key(|x| x.cursor());
+
+    // 取前N条记录
+    list.truncate(limit as usize);
+    debug!("get_plate_update, final list: {}", list.len());
+
+    // 返回值
+    Ok(build_success_response(list))
+}
+
+//----------------------------- reconcile  --------------------------------------
+// 对账接口，返回服务端的全部记录，盒子按这些记录修复差异。
+// 盒子不需要同步的返回 data: None，和空列表区分开，避免盒子删除本地的数据
+
+fn build_list_response<T>(list: Option<Vec<T>>) -> ResponseData<T> {
+    ResponseData {
+        status: 0,
+        message: Some("success".to_string()),
+        ts: Local::now(),
+        data: list,
+        next: None,
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListParas {
+    hw_id: Option<String>,
+    db_id: Option<String>,
+    uuids: Option<String>, // 逗号分隔
+}
+
+async fn find_list_box(state: &WebState, paras: &ListParas) -> Result<BaseBox, ResponseData<()>> {
+    if !check_para_exist(&paras.hw_id) {
+        return Err(build_invalid_paras_response("invalid hw_id"));
+    }
+    let hw_id = paras.hw_id.as_ref().unwrap();
+
+    match state.ctx.dao.find_box(hw_id.clone()).await {
+        Ok(Some(v)) => Ok(v),
+        Ok(None) => Err(build_device_notfound_response(hw_id.as_str())),
+        Err(e) => {
+            error!("error, find_box({}), err: {:?}", hw_id, e);
+            Err(e.into())
+        }
+    }
+}
+
+pub async fn get_db_all(
+    Extension(state): Extension<Arc<WebState>>,
+    Query(paras): Query<ListParas>,
+) -> Result<ResponseData<Db>, ResponseData<()>> {
+    debug!("get_db_all, paras: {:?}", paras);
+
+    let base_box = find_list_box(&state, &paras).await?;
+    if base_box.has_db == 0 || base_box.sync_flag == 0 {
+        return Ok(build_list_response(None));
+    }
+
+    let list = match state.ctx.dao.get_all_dbs().await {
+        Ok(v) => v,
+        Err(e) => {
+            error!("error, get_all_dbs({}), err: {:?}", base_box.hw_id, e);
+            return Err(e.into());
+        }
+    };
+    let list: Vec<Db> = list.into_iter().map(|x| x.into()).collect();
+    debug!("get_db_all, {}, list: {}", base_box.hw_id, list.len());
+
+    Ok(build_list_response(Some(list)))
+}
+
+pub async fn get_camera_all(
+    Extension(state): Extension<Arc<WebState>>,
+    Query(paras): Query<ListParas>,
+) -> Result<ResponseData<Camera>, ResponseData<()>> {
+    debug!("get_camera_all, paras: {:?}", paras);
+
+    let base_box = find_list_box(&state, &paras).await?;
+    if base_box.has_camera == 0 || base_box.sync_flag == 0 {
+        return Ok(build_list_response(None));
+    }
+
+    let list = match state.ctx.dao.get_all_cameras(&base_box.device_id).await {
+        Ok(v) => v,
+        Err(e) => {
+            error!(
+                "error, get_all_cameras({}), err: {:?}",
+                base_box.device_id, e
+            );
+            return Err(e.into());
+        }
+    };
+    let list: Vec<Camera> = list.into_iter().map(|x| x.into()).collect();
+    debug!("get_camera_all, {}, list: {}", base_box.hw_id, list.len());
+
+    Ok(build_list_response(Some(list)))
+}
+
+pub async fn get_person_ids(
+    Extension(state): Extension<Arc<WebState>>,
+    Query(paras): Query<ListParas>,
+) -> Result<ResponseData<String>, ResponseData<()>> {
+    debug!("get_person_ids, paras: {:?}", paras);
+
+    let base_box = find_list_box(&state, &paras).await?;
+    if !check_para_exist(&paras.db_id) {
+        return Err(build_invalid_paras_response("invalid db_id"));
+    }
+    if base_box.has_db == 0 || base_box.sync_flag == 0 {
+        return Ok(build_list_response(None));
+    }
+
+    let db_id = paras.db_id.unwrap();
+    let list = match state.ctx.dao.get_person_ids(&db_id).await {
+        Ok(v) => v,
+        Err(e) => {
+            error!("error, get_person_ids({}), err: {:?}", db_id, e);
+            return Err(e.into());
+        }
+    };
+    debug!(
+        "get_person_ids, {}, {}, list: {}",
+        base_box.hw_id,
+        db_id,
+        list.len()
+    );
+
+    Ok(build_list_response(Some(list)))
+}
+
+pub async fn get_person_list(
+    Extension(state): Extension<Arc<WebState>>,
+    Query(paras): Query<ListParas>,
+) -> Result<ResponseData<Person>, ResponseData<()>> {
+    debug!("get_person_list, paras: {:?}", paras);
+
+    let base_box = find_list_box(&state, &paras).await?;
+    if base_box.has_db == 0 || base_box.sync_flag == 0 {
+        return Ok(build_list_response(None));
+    }
+
+    let uuids: Vec<String> = paras
+        .uuids
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .filter(|x| !x.is_empty())
+        .map(|x| x.to_string())
+        .collect();
+    if uuids.is_empty() || uuids.len() > state.ctx.cfg.sync_batch as usize {
+        return Err(build_invalid_paras_response("invalid uuids"));
+    }
+
+    let rows = match state.ctx.dao.get_feamap_rows_by_uuids(&uuids).await {
+        Ok(v) => v,
+        Err(e) => {
+            error!(
+                "error, get_feamap_rows_by_uuids({}), err: {:?}",
+                base_box.hw_id, e
+            );
+            return Err(e.into());
+        }
+    };
+    let list = get_personinfo_from_map(rows);
+    debug!("get_person_list, {}, list: {}", base_box.hw_id, list.len());
+
+    Ok(build_list_response(Some(list)))
+}
@@ -1,8 +1,11 @@
 use crate::error::{AppError, AppResult};
 
+use fy_base::util::image::ImageProfile;
 use fy_base::util::mysql_util;
+use fy_base::util::tls::{TlsClientCfg, TlsServerCfg};
 
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
 use std::net::SocketAddr;
 use std::path::Path;
@@ -35,6 +38,7 @@ pub struct AppCfgSearch {
     // 是否比对，
     pub enable: bool,
     pub recg_url: String,
+    pub tls: TlsClientCfg, // recg_url 为 https 时使用
     pub ignore_dbs: Vec<String>,
     pub cache_ttl: u64,
     pub top: u64,
@@ -46,6 +50,7 @@ pub struct AppCfgTrackDb {
     // facetrack是否加入到路人库，
     pub enable: bool,
     pub recg_url: String,
+    pub tls: TlsClientCfg, // 同 search.tls
     pub facetrack_db: String,
 }
 
@@ -53,6 +58,15 @@ pub struct AppCfgTrackDb {
 pub struct AppCfgHttp {
     pub addr: String,
     pub max_conn: u64,
+    pub max_unzip: u64, // MB，批量上传解压后的最大长度
+    pub tls: TlsServerCfg,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AppCfgAuth {
+    pub enable: bool,   // 是否校验盒子请求签名
+    pub window: u64,    // 秒，时间戳允许的偏差，也是防重放的时间窗口
+    pub cache_ttl: u64, // 秒，盒子 secret 缓存时间
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -68,6 +82,23 @@ pub struct AppCfgRabbitMq {
     pub url: String,
     pub face: AppCfgRabbitMqItem,
     pub car: AppCfgRabbitMqItem,
+    pub alarm: AppCfgRabbitMqItem,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AppCfgMinioImage {
+    pub profiles: HashMap<String, ImageProfile>,
+    // 各类图片使用的 profile 名称，为空则原样保存
+    pub background: String,
+    pub aligned: String,
+    pub display: String,
+    pub vehicle: String,
+}
+
+impl AppCfgMinioImage {
+    pub fn profile(&self, name: &str) -> Option<&ImageProfile> {
+        self.profiles.get(name)
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -79,6 +110,7 @@ pub struct AppCfgMinio {
     pub secret_key: String,
     pub facetrack_bucket: String,
     pub cartrack_bucket: String,
+    pub image: AppCfgMinioImage,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -97,6 +129,7 @@ pub struct AppCfg {
     pub search: AppCfgSearch,
     pub track_db: AppCfgTrackDb,
     pub http: AppCfgHttp,
+    pub auth: AppCfgAuth,
     pub rabbitmq: AppCfgRabbitMq,
     pub minio: AppCfgMinio,
     pub clean: AppCfgClean,
@@ -116,6 +149,41 @@ impl AppCfg {
             return Err(AppError::from_debug(e));
         };
 
+        if self.http.max_unzip == 0 {
+            return Err(AppError::new("http.max_unzip must be > 0"));
+        }
+        if let Err(e) = self.search.tls.validate() {
+            return Err(AppError::new(&format!("search.tls: {}", e)));
+        }
+        if let Err(e) = self.track_db.tls.validate() {
+            return Err(AppError::new(&format!("track_db.tls: {}", e)));
+        }
+        if let Err(e) = self.http.tls.validate() {
+            return Err(AppError::new(&format!("http.tls: {}", e)));
+        }
+
+        if self.auth.enable && self.auth.window == 0 {
+            return Err(AppError::new("auth.window must be > 0"));
+        }
+
+        let image = &self.minio.image;
+        for (name, profile) in image.profiles.iter() {
+            if let Err(e) = profile.validate() {
+                return Err(AppError::new(&format!(
+                    "minio.image.profiles.{}, {}",
+                    name, e
+                )));
+            }
+        }
+        for name in [&image.background, &image.aligned, &image.display, &image.vehicle] {
+            if !name.is_empty() && image.profile(name).is_none() {
+                return Err(AppError::new(&format!(
+                    "minio.image, profile not found: {}",
+                    name
+                )));
+            }
+        }
+
         Ok(())
     }
 }
@@ -3,7 +3,10 @@ use tokio::sync::watch::Receiver;
 use crate::app_cfg::AppCfg;
 
 use crate::dao::Dao;
-use fy_base::{api::bm_api::RecognitionApi, util::service::SignalProduce};
+use fy_base::{
+    api::bm_api::RecognitionApi,
+    util::{service::SignalProduce, tls::ClientTls},
+};
 
 //---------------------
 
@@ -13,14 +16,23 @@ pub struct AppCtx {
 
     pub search_recg_api: RecognitionApi,
     pub trackdb_recg_api: RecognitionApi,
+    // 访问 recg_url 用的 tls 配置
+    pub search_tls: ClientTls,
+    pub trackdb_tls: ClientTls,
 
     pub dao: Dao,
 }
 
 impl AppCtx {
-    pub fn new(cfg: AppCfg, exit_rx: Receiver<i64>, dao: Dao) -> Self {
-        let search_recg_api = RecognitionApi::new(&cfg.search.recg_url);
-        let trackdb_recg_api = RecognitionApi::new(&cfg.track_db.recg_url);
+    pub fn new(
+        cfg: AppCfg,
+        exit_rx: Receiver<i64>,
+        dao: Dao,
+        search_tls: ClientTls,
+        trackdb_tls: ClientTls,
+    ) -> Self {
+        let search_recg_api = RecognitionApi::new(&cfg.search.recg_url, &search_tls);
+        let trackdb_recg_api = RecognitionApi::new(&cfg.track_db.recg_url, &trackdb_tls);
 
         Self {
             cfg,
@@ -28,6 +40,8 @@ impl AppCtx {
 
             search_recg_api,
             trackdb_recg_api,
+            search_tls,
+            trackdb_tls,
             dao,
         }
     }
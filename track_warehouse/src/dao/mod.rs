@@ -18,6 +18,12 @@ pub struct Dao {
 //--------------------------------
 
 impl Dao {
+    /// 检查连接池是否可用
+    pub async fn ping(&self) -> Result<(), AppError> {
+        sqlx::query("select 1").execute(self.pool.as_ref()).await?;
+        Ok(())
+    }
+
     pub async fn save_facetrack(&self, facetrack: &Facetrack) -> Result<u64, AppError> {
         let new_id = facetrack.insert(&self.pool, &self.tz).await?;
 
@@ -29,4 +35,37 @@ impl Dao {
 
         Ok(new_id)
     }
+
+    /// 盒子的请求签名密钥，base_box 与 sync_server 共用。
+    /// 盒子不存在时返回 None，没有配置密钥时返回空字符串
+    pub async fn find_box_secret(&self, hw_id: &str) -> Result<Option<String>, AppError> {
+        let sql = "select secret from base_box where hw_id = ?";
+        let row: Option<(Option<String>,)> = sqlx::query_as(sql)
+            .bind(hw_id)
+            .fetch_optional(self.pool.as_ref())
+            .await?;
+
+        Ok(row.map(|x| x.0.unwrap_or_default()))
+    }
+
+    /// 摄像头所属盒子的 hw_id，已删除的摄像头从 base_camera_del 中查找，
+    /// 盒子上可能还有删除前采集的 track 没有上传
+    pub async fn find_camera_box(&self, uuid: &str) -> Result<Option<String>, AppError> {
+        let sql = "select box_deviceid from base_camera where uuid = ?";
+        let row: Option<(String,)> = sqlx::query_as(sql)
+            .bind(uuid)
+            .fetch_optional(self.pool.as_ref())
+            .await?;
+        if let Some(v) = row {
+            return Ok(Some(v.0));
+        }
+
+        let sql =
+            "select box_deviceid from base_camera_del where uuid = ? order by id desc limit 1";
+        let row: Option<(String,)> = sqlx::query_as(sql)
+            .bind(uuid)
+            .fetch_optional(self.pool.as_ref())
+            .await?;
+        Ok(row.map(|x| x.0))
+    }
 }
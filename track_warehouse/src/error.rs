@@ -1,3 +1,6 @@
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use fy_base::api::upload_api::ResponseData;
 use s3::error::S3Error;
 use std::error::Error;
 use std::fmt::{Debug, Display, Formatter};
@@ -92,3 +95,14 @@ impl From<S3Error> for AppError {
         AppError::from_debug(e)
     }
 }
+
+// web 接口的内部错误，返回 http 500，盒子会重传
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let data = ResponseData {
+            status: 500,
+            message: Some(self.msg),
+        };
+        (StatusCode::INTERNAL_SERVER_ERROR, data).into_response()
+    }
+}
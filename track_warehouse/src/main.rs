@@ -6,9 +6,9 @@ use tokio::sync::watch;
 use tracing::{error, info};
 use track_warehouse::{app_cfg::AppCfg, app_ctx::AppCtx, service::signal_service::SignalService};
 
-use fy_base::util::{logger, mysql_util, service::ServiceRepo};
+use fy_base::util::{logger, metrics, mysql_util, service::ServiceRepo, tls::ClientTls};
 use track_warehouse::dao::Dao;
-use track_warehouse::queue_item::{CarQueue, FaceQueue};
+use track_warehouse::queue_item::{AlarmQueue, CarQueue, FaceQueue};
 use track_warehouse::service::face_search::FaceSearchService;
 use track_warehouse::service::minio::MinioService;
 use track_warehouse::service::mysql_service::MysqlService;
@@ -100,9 +100,30 @@ async fn main() {
         tz,
     };
 
+    let search_tls = match ClientTls::load(&app_config.search.tls) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("error, load search tls, err:{}", e);
+            return;
+        }
+    };
+    let trackdb_tls = match ClientTls::load(&app_config.track_db.tls) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("error, load track_db tls, err:{}", e);
+            return;
+        }
+    };
+
     // 初始化 context
     let (exit_tx, exit_rx) = watch::channel(0);
-    let app_context = Arc::new(AppCtx::new(app_config, exit_rx, dao));
+    let app_context = Arc::new(AppCtx::new(
+        app_config,
+        exit_rx,
+        dao,
+        search_tls,
+        trackdb_tls,
+    ));
 
     // 创建服务集
     let mut service_repo = ServiceRepo::new(app_context.clone());
@@ -110,6 +131,7 @@ async fn main() {
     // 创建队列
     let face_queue: Arc<FaceQueue> = Arc::new(Queue::new());
     let car_queue: Arc<CarQueue> = Arc::new(Queue::new());
+    let alarm_queue: Arc<AlarmQueue> = Arc::new(Queue::new());
 
     let face_search_queue: Arc<FaceQueue> = Arc::new(Queue::new());
 
@@ -121,11 +143,27 @@ async fn main() {
     let rabbitmq_face_queue: Arc<FaceQueue> = Arc::new(Queue::new());
     let rabbitmq_car_queue: Arc<CarQueue> = Arc::new(Queue::new());
 
+    // 队列长度输出到 /metrics
+    metrics::register_deadqueue("face", &face_queue);
+    metrics::register_deadqueue("car", &car_queue);
+    metrics::register_deadqueue("alarm", &alarm_queue);
+    metrics::register_deadqueue("face_search", &face_search_queue);
+    metrics::register_deadqueue("face_mysql", &face_mysql_queue);
+    metrics::register_deadqueue("car_mysql", &car_mysql_queue);
+    metrics::register_deadqueue("face_trackdb", &face_trackdb_queue);
+    metrics::register_deadqueue("rabbitmq_face", &rabbitmq_face_queue);
+    metrics::register_deadqueue("rabbitmq_car", &rabbitmq_car_queue);
+
     // 初始退出信号服务
     let exit_service = SignalService::new(exit_tx);
 
     // 初始web服务
-    let web_service = WebService::new(app_context.clone(), face_queue.clone(), car_queue.clone());
+    let web_service = WebService::new(
+        app_context.clone(),
+        face_queue.clone(),
+        car_queue.clone(),
+        alarm_queue.clone(),
+    );
 
     // 初始 minio 服务 ，多个worker
     let mut minio_workers = vec![];
@@ -165,6 +203,7 @@ async fn main() {
         app_context.clone(),
         rabbitmq_face_queue.clone(),
         rabbitmq_car_queue.clone(),
+        alarm_queue.clone(),
     );
 
     // 初始 trackdb 服务
@@ -1,6 +1,7 @@
 use deadqueue::unlimited::Queue;
-use fy_base::api::upload_api::{NotifyCarQueueItem, NotifyFaceQueueItem};
+use fy_base::api::upload_api::{NotifyAlarmItem, NotifyCarQueueItem, NotifyFaceQueueItem};
 
 //-----------------------
 pub type FaceQueue = Queue<NotifyFaceQueueItem>;
 pub type CarQueue = Queue<NotifyCarQueueItem>;
+pub type AlarmQueue = Queue<NotifyAlarmItem>;
@@ -8,7 +8,7 @@ use tokio::sync::watch::Receiver;
 use tokio::task::JoinHandle as TkJoinHandle;
 
 use fy_base::api::bm_api::{ApiFeatureQuality, RecognitionApi, SearchResPerson};
-use fy_base::util::utils;
+use fy_base::util::{metrics, utils};
 
 use crate::app_ctx::AppCtx;
 use crate::error::AppError;
@@ -32,7 +32,7 @@ pub struct FaceSearchService {
 
 impl FaceSearchService {
     pub fn new(num: i64, ctx: Arc<AppCtx>, queue: Arc<FaceQueue>, out: Arc<FaceQueue>) -> Self {
-        let api = RecognitionApi::new(ctx.cfg.search.recg_url.as_str());
+        let api = RecognitionApi::new(ctx.cfg.search.recg_url.as_str(), &ctx.search_tls);
         let skip_search = !ctx.cfg.search.enable;
         let last_cache_ts = Instant::now();
         let dbs_cache = Cache::new(10);
@@ -191,6 +191,7 @@ impl FaceSearchService {
         };
 
         let mut persons = Vec::new();
+        let mut search_failed = false;
 
         debug!(
             "FaceSearchWorker[{}], process_batch: {}",
@@ -241,6 +242,7 @@ impl FaceSearchService {
                         // 填充 比对结果
                         self.fill_with_matchinfo(&mut items, &res.persons);
                     } else {
+                        search_failed = true;
                         error!(
                             "error, FaceSearchWorker[{}], search, code:{}, msg:{}",
                             self.num, res.code, res.msg
@@ -248,6 +250,7 @@ impl FaceSearchService {
                     }
                 }
                 Err(e) => {
+                    search_failed = true;
                     error!("error, FaceSearchWorker[{}], search, {:?}", self.num, e);
                 }
             }
@@ -255,6 +258,11 @@ impl FaceSearchService {
 
         // 放入 queue 中
         for v in items {
+            if search_failed {
+                metrics::inc_failed("face_search");
+            } else {
+                metrics::inc_processed("face_search");
+            }
             self.out.push(v);
         }
     }
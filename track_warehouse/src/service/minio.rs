@@ -2,6 +2,7 @@ use crate::app_ctx::AppCtx;
 use crate::queue_item::{CarQueue, FaceQueue};
 use bytes::Bytes;
 use fy_base::api::upload_api::{NotifyCarQueueItem, NotifyFaceQueueItem};
+use fy_base::util::image::{self, ImageProfile};
 use fy_base::util::service::Service;
 use log::error;
 use s3::Bucket;
@@ -9,7 +10,7 @@ use std::sync::Arc;
 use std::time::Instant;
 
 use crate::error::AppError;
-use fy_base::util::minio;
+use fy_base::util::{metrics, minio};
 use fy_base::util::minio::new_bucket;
 use tokio::sync::watch::Receiver;
 use tokio::task::JoinHandle;
@@ -76,11 +77,13 @@ impl MinioService {
         debug!("MinioService, process_face: {}", item.uuid);
         let saved = self.save_facetrack_to_minio(&mut item).await;
         if let Err(e) = saved {
+            metrics::inc_failed("minio");
             error!(
                 "error, MinioService, save_facetrack_to_minio:{}, err: {:?}",
                 item.uuid, e
             );
         } else {
+            metrics::inc_processed("minio");
             debug!("MinioService, save_facetrack_to_minio, ok, {}", item.uuid);
         }
 
@@ -94,11 +97,13 @@ impl MinioService {
         debug!("MinioService, process_car: {}", item.uuid);
         let saved = self.save_cartrack_to_minio(&mut item).await;
         if let Err(e) = saved {
+            metrics::inc_failed("minio");
             error!(
                 "error, MinioService, save_cartrack_to_minio:{}, err: {:?}",
                 item.uuid, e
             );
         } else {
+            metrics::inc_processed("minio");
             debug!("MinioService, save_cartrack_to_minio, ok, {}", item.uuid);
         }
 
@@ -144,7 +149,9 @@ impl MinioService {
         content_type: &str,
         clean: bool,
     ) -> Result<(), AppError> {
+        let timer = metrics::op_timer("minio", "put_object");
         let rst = minio::save_to_minio(bucket, path, content, content_type).await;
+        timer.observe_duration();
 
         if clean && !content.is_empty() {
             *content = Bytes::new();
@@ -166,6 +173,67 @@ impl MinioService {
         Ok(())
     }
 
+    // 按 profile 转码后保存，返回实际保存的路径(扩展名随格式变化)和转码后的宽高
+    // 有缩略图时另存为 xxx_thumb.jpg，没有配置 profile 或转码失败时保存原图
+    async fn save_image_to_minio(
+        bucket: &Bucket,
+        path: &str,
+        content: &mut Bytes,
+        profile: Option<&ImageProfile>,
+    ) -> Result<(String, Option<(u32, u32)>), AppError> {
+        let transcoded = match profile {
+            Some(profile) => {
+                let buf = content.clone();
+                let to = profile.clone();
+                match tokio::task::spawn_blocking(move || image::transcode(buf, &to)).await {
+                    Ok(Ok(v)) => Some((profile.format, v)),
+                    Ok(Err(e)) => {
+                        metrics::inc_failed("transcode");
+                        error!("error, MinioService, transcode: {}, err: {:?}", path, e);
+                        None
+                    }
+                    Err(e) => {
+                        metrics::inc_failed("transcode");
+                        error!("error, MinioService, transcode: {}, err: {:?}", path, e);
+                        None
+                    }
+                }
+            }
+            None => None,
+        };
+
+        let (format, mut transcoded) = match transcoded {
+            Some(v) => v,
+            None => {
+                Self::save_minio_from_bytes(bucket, path, content, "image/jpeg", true).await?;
+                return Ok((path.to_string(), None));
+            }
+        };
+        *content = Bytes::new();
+
+        let path = minio::replace_extension(path, format.extension());
+        if let Some(ref mut thumbnail) = transcoded.thumbnail {
+            let thumbnail_path = minio::get_thumbnail_path(&path);
+            Self::save_minio_from_bytes(
+                bucket,
+                &thumbnail_path,
+                thumbnail,
+                format.content_type(),
+                true,
+            )
+                .await?;
+        }
+        Self::save_minio_from_bytes(
+            bucket,
+            &path,
+            &mut transcoded.buf,
+            format.content_type(),
+            true,
+        )
+            .await?;
+        Ok((path, Some((transcoded.width, transcoded.height))))
+    }
+
     // 保存只minio，修改 item的 file name
     async fn save_facetrack_to_minio(
         &self,
@@ -173,32 +241,36 @@ impl MinioService {
     ) -> Result<(), AppError> {
         let uuid = item.uuid.as_str();
         let ts = item.ts;
+        let image_cfg = &self.ctx.cfg.minio.image;
 
         // 先将 image_file置空，后赋值为minio path，然后清掉 Bytes
 
-        // 保存背景图
-        item.notify.background.image_file = "".into();
-        let path = minio::get_facetrack_relate_bg_path(uuid, ts);
-        let _saved = Self::save_minio_from_bytes(
-            &self.facetrack_bucket,
-            &path,
-            &mut item.notify.background.image_buf,
-            "image/jpeg",
-            true,
-        )
-            .await?;
-        item.notify.background.image_file = self.get_facetrack_minio_url(&path);
+        // 保存背景图，隐私模式下盒子可能不上传背景图
+        if !item.notify.background.image_buf.is_empty() {
+            item.notify.background.image_file = "".into();
+            let path = minio::get_facetrack_relate_bg_path(uuid, ts);
+            let (path, size) = Self::save_image_to_minio(
+                &self.facetrack_bucket,
+                &path,
+                &mut item.notify.background.image_buf,
+                image_cfg.profile(&image_cfg.background),
+            )
+                .await?;
+            if let Some((width, height)) = size {
+                item.notify.background.resized(width as i64, height as i64);
+            }
+            item.notify.background.image_file = self.get_facetrack_minio_url(&path);
+        }
 
         for (face_id, face) in item.notify.faces.iter_mut().enumerate() {
             // 小图
             face.aligned_file = "".into();
             let path = minio::get_facetrack_relate_small_path(uuid, ts, face_id as u8 + 1);
-            let _saved = Self::save_minio_from_bytes(
+            let (path, _) = Self::save_image_to_minio(
                 &self.facetrack_bucket,
                 &path,
                 &mut face.aligned_buf,
-                "image/jpeg",
-                true,
+                image_cfg.profile(&image_cfg.aligned),
             )
                 .await?;
             face.aligned_file = self.get_facetrack_minio_url(&path);
@@ -206,12 +278,11 @@ impl MinioService {
             // 大图
             face.display_file = "".into();
             let path = minio::get_facetrack_relate_large_path(uuid, ts, face_id as u8 + 1);
-            let _saved = Self::save_minio_from_bytes(
+            let (path, _) = Self::save_image_to_minio(
                 &self.facetrack_bucket,
                 &path,
                 &mut face.display_buf,
-                "image/jpeg",
-                true,
+                image_cfg.profile(&image_cfg.display),
             )
                 .await?;
             face.display_file = self.get_facetrack_minio_url(&path);
@@ -241,32 +312,36 @@ impl MinioService {
     async fn save_cartrack_to_minio(&self, item: &mut NotifyCarQueueItem) -> Result<(), AppError> {
         let uuid = item.uuid.as_str();
         let ts = item.ts;
+        let image_cfg = &self.ctx.cfg.minio.image;
 
         // 先将 image_file置空，后赋值为minio path，然后清掉 Bytes
 
-        // 保存背景图
-        item.notify.background.image_file = "".into();
-        let path = minio::get_cartrack_relate_bg_path(uuid, ts);
-        let _saved = Self::save_minio_from_bytes(
-            &self.cartrack_bucket,
-            &path,
-            &mut item.notify.background.image_buf,
-            "image/jpeg",
-            true,
-        )
-            .await?;
-        item.notify.background.image_file = self.get_cartrack_minio_url(&path);
+        // 保存背景图，隐私模式下盒子可能不上传背景图
+        if !item.notify.background.image_buf.is_empty() {
+            item.notify.background.image_file = "".into();
+            let path = minio::get_cartrack_relate_bg_path(uuid, ts);
+            let (path, size) = Self::save_image_to_minio(
+                &self.cartrack_bucket,
+                &path,
+                &mut item.notify.background.image_buf,
+                image_cfg.profile(&image_cfg.background),
+            )
+                .await?;
+            if let Some((width, height)) = size {
+                item.notify.background.resized(width as i64, height as i64);
+            }
+            item.notify.background.image_file = self.get_cartrack_minio_url(&path);
+        }
 
         // 车辆图
         for (car_id, car) in item.notify.vehicles.iter_mut().enumerate() {
             car.image_file = "".into();
             let path = minio::get_cartrack_relate_car_path(uuid, ts, car_id as u8 + 1);
-            let _saved = Self::save_minio_from_bytes(
+            let (path, _) = Self::save_image_to_minio(
                 &self.cartrack_bucket,
                 &path,
                 &mut car.img_buf,
-                "image/jpeg",
-                true,
+                image_cfg.profile(&image_cfg.vehicle),
             )
                 .await?;
             car.image_file = self.get_cartrack_minio_url(&path);
@@ -1,5 +1,6 @@
 use chrono::Local;
 use fy_base::api::upload_api::{NotifyCarQueueItem, NotifyFaceQueueItem};
+use fy_base::util::metrics;
 use fy_base::util::service::Service;
 use std::sync::Arc;
 use std::time::Instant;
@@ -49,7 +50,10 @@ impl MysqlService {
         debug!("MysqlService, process_face: {}", item.uuid);
 
         if let Err(e) = self.save_facetrack_to_mysql(&item).await {
+            metrics::inc_failed("mysql");
             error!("error, MysqlService, save_facetrack_to_mysql, err: {:?}", e);
+        } else {
+            metrics::inc_processed("mysql");
         }
 
         // self.face_trackdb_queue.push(item.clone());
@@ -61,7 +65,10 @@ impl MysqlService {
         let begin_ts = Instant::now();
         debug!("MysqlService, process_car: {}", item.uuid);
         if let Err(e) = self.save_cartrack_to_mysql(&item).await {
+            metrics::inc_failed("mysql");
             error!("error, MysqlService, save_cartrack_to_mysql, err: {:?}", e);
+        } else {
+            metrics::inc_processed("mysql");
         }
         self.rabbitmq_car_queue.push(item);
         info!("MysqlService, process car, use: {}", begin_ts.elapsed().as_millis());
@@ -97,6 +104,7 @@ impl MysqlService {
     async fn save_facetrack_to_mysql(&self, item: &NotifyFaceQueueItem) -> Result<(), AppError> {
         let facetrack = Self::from_queueitem_to_facetrack(item);
 
+        let _timer = metrics::op_timer("mysql", "save_facetrack");
         let id = self.ctx.dao.save_facetrack(&facetrack).await?;
 
         debug!(
@@ -110,6 +118,7 @@ impl MysqlService {
     async fn save_cartrack_to_mysql(&self, item: &NotifyCarQueueItem) -> Result<(), AppError> {
         let cartrack = Self::from_queueitem_to_cartrack(item);
 
+        let _timer = metrics::op_timer("mysql", "save_cartrack");
         let id = self.ctx.dao.save_cartrack(&cartrack).await?;
 
         debug!("MysqlService, save cartrack ok, {}, {}", id, cartrack.uuid);
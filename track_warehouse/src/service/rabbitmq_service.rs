@@ -3,6 +3,7 @@ use std::time::{Duration, Instant};
 
 use crate::app_ctx::AppCtx;
 
+use fy_base::util::{health, metrics};
 use fy_base::util::rabbitmq::init_conn_props;
 use fy_base::util::service::Service;
 use tokio::sync::watch::Receiver;
@@ -16,23 +17,30 @@ use lapin::{
 };
 use tokio::time::sleep;
 
-use crate::queue_item::{CarQueue, FaceQueue};
-use fy_base::api::upload_api::{NotifyCarQueueItem, NotifyFaceQueueItem};
+use crate::queue_item::{AlarmQueue, CarQueue, FaceQueue};
+use fy_base::api::upload_api::{NotifyAlarmItem, NotifyCarQueueItem, NotifyFaceQueueItem};
 use tracing::{debug, error, info};
 
 pub struct RabbitmqService {
     pub ctx: Arc<AppCtx>,
     pub face_queue: Arc<FaceQueue>,
     pub car_queue: Arc<CarQueue>,
+    pub alarm_queue: Arc<AlarmQueue>,
     wait: u64, // retry interval, second
 }
 
 impl RabbitmqService {
-    pub fn new(ctx: Arc<AppCtx>, face_queue: Arc<FaceQueue>, car_queue: Arc<CarQueue>) -> Self {
+    pub fn new(
+        ctx: Arc<AppCtx>,
+        face_queue: Arc<FaceQueue>,
+        car_queue: Arc<CarQueue>,
+        alarm_queue: Arc<AlarmQueue>,
+    ) -> Self {
         Self {
             ctx,
             face_queue,
             car_queue,
+            alarm_queue,
             wait: 2,
         }
     }
@@ -141,6 +149,14 @@ impl RabbitmqService {
             )
             .await?;
 
+        self.init_rabbitmq_durable_queue(
+            &channel,
+            self.ctx.cfg.rabbitmq.alarm.exchange.as_str(),
+            self.ctx.cfg.rabbitmq.alarm.queue.as_str(),
+            self.ctx.cfg.rabbitmq.alarm.route_key.as_str(),
+        )
+        .await?;
+
         Ok(channel)
     }
 
@@ -170,6 +186,8 @@ impl RabbitmqService {
             }
             Err(e) => {
                 error!("error, RabbitmqService, {}, err: {:?}", msg, e);
+                metrics::inc_reconnect("rabbitmq");
+                health::set_err("rabbitmq", &e);
 
                 let wait = self.increate_wait();
                 if Self::wait_a_moment(Duration::from_secs(wait), exit_rx).await {
@@ -218,9 +236,13 @@ impl RabbitmqService {
             };
 
             debug!("RabbitmqService, rabbitmq inited");
+            health::set_ok("rabbitmq");
 
             // loop message
             let loop_rst = self.loop_message(&channel, exit_rx.clone()).await;
+            if loop_rst.is_err() {
+                metrics::inc_failed("rabbitmq");
+            }
 
             let (rst, _) = self
                 .check_rabbitmq_error("loop_message", loop_rst, exit_rx.clone())
@@ -250,10 +272,16 @@ impl RabbitmqService {
     ) -> Result<(), lapin::Error> {
         loop {
             tokio::select! {
+                // 告警优先
+                biased;
+
                 _ = exit_rx.changed() => {
                     info!("RabbitmqService, recv signal, will exit");
                     break;
                 }
+                item = self.alarm_queue.pop() => {
+                    self.process_out_alarm_rabbitmsg(channel, item).await?;
+                }
                 item = self.face_queue.pop() => {
                     let _ = self.process_out_face_rabbitmsg(channel,item).await?;
                 }
@@ -288,6 +316,7 @@ impl RabbitmqService {
 
         let expire = self.ctx.cfg.rabbitmq.face.expire * 60 * 1000; // 分钟 * 60* 1000
 
+        let timer = metrics::op_timer("rabbitmq", "publish_face");
         let publish_confirm = channel
             .basic_publish(
                 exchange,
@@ -298,6 +327,8 @@ impl RabbitmqService {
             )
             .await?
             .await?;
+        timer.observe_duration();
+        metrics::inc_processed("rabbitmq");
         debug!(
             "RabbitmqService, publish_confirm, {:?}, {}",
             publish_confirm, item.uuid
@@ -307,6 +338,48 @@ impl RabbitmqService {
         Ok(())
     }
 
+    async fn process_out_alarm_rabbitmsg(
+        &mut self,
+        channel: &Channel,
+        mut item: NotifyAlarmItem,
+    ) -> Result<(), lapin::Error> {
+        let exchange = self.ctx.cfg.rabbitmq.alarm.exchange.as_str();
+        let route_key = self.ctx.cfg.rabbitmq.alarm.route_key.as_str();
+
+        // 告警带上人脸图
+        item.face_image = Some(base64::encode(&item.face_buf));
+        let payload = serde_json::to_string(&item);
+        if let Err(e) = payload {
+            error!(
+                "error, rabbitmq_service, serde_json::to_string, err: {:?}",
+                e
+            );
+            return Ok(());
+        }
+        let payload = payload.unwrap();
+
+        let expire = self.ctx.cfg.rabbitmq.alarm.expire * 60 * 1000; // 分钟 * 60* 1000
+
+        let timer = metrics::op_timer("rabbitmq", "publish_alarm");
+        let publish_confirm = channel
+            .basic_publish(
+                exchange,
+                route_key,
+                BasicPublishOptions::default(),
+                payload.as_bytes(),
+                BasicProperties::default().with_expiration(expire.to_string().into()),
+            )
+            .await?
+            .await?;
+        timer.observe_duration();
+        metrics::inc_processed("rabbitmq");
+        debug!(
+            "RabbitmqService, publish_confirm, {:?}, {}",
+            publish_confirm, item.uuid
+        );
+        Ok(())
+    }
+
     async fn process_out_car_rabbitmsg(
         &mut self,
         channel: &Channel,
@@ -327,6 +400,7 @@ impl RabbitmqService {
 
         let expire = self.ctx.cfg.rabbitmq.car.expire * 60 * 1000; // 分钟 * 60* 1000
 
+        let timer = metrics::op_timer("rabbitmq", "publish_car");
         let publish_confirm = channel
             .basic_publish(
                 exchange,
@@ -337,6 +411,8 @@ impl RabbitmqService {
             )
             .await?
             .await?;
+        timer.observe_duration();
+        metrics::inc_processed("rabbitmq");
         debug!(
             "RabbitmqService, publish_confirm, {:?}, {}",
             publish_confirm, item.uuid
@@ -349,6 +425,7 @@ impl RabbitmqService {
 impl Service for RabbitmqService {
     fn run(self, exit_rx: Receiver<i64>) -> JoinHandle<()> {
         let conn_props = init_conn_props();
+        health::register("rabbitmq");
         let this = self;
         tokio::spawn(this.do_run(conn_props, exit_rx))
     }
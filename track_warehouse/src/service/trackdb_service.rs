@@ -19,7 +19,7 @@ pub struct TrackDbService {
 
 impl TrackDbService {
     pub fn new(ctx: Arc<AppCtx>, face_queue: Arc<FaceQueue>) -> Self {
-        let api = RecognitionApi::new(ctx.cfg.track_db.recg_url.as_str());
+        let api = RecognitionApi::new(ctx.cfg.track_db.recg_url.as_str(), &ctx.trackdb_tls);
 
         TrackDbService {
             ctx,
@@ -0,0 +1,81 @@
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use tracing::warn;
+
+use fy_base::api::upload_api::{ResponseData, RES_STATUS_AUTH_ERR};
+use fy_base::util::sign::{self, AuthBox, AuthError, VerifyError};
+
+use crate::error::AppError;
+use crate::service::web::WebState;
+
+// 与 /upload_batch 的 ContentLengthLimit 一致
+const MAX_BODY: u64 = 1024 * 1024 * 64;
+
+fn build_auth_err_response(e: VerifyError) -> Response {
+    let data = ResponseData {
+        status: RES_STATUS_AUTH_ERR,
+        message: Some(format!("auth failed: {:?}", e)),
+    };
+    (StatusCode::UNAUTHORIZED, data).into_response()
+}
+
+/// 校验盒子身份，见 sign::verify_box。
+/// 上传的 track 是否属于该盒子在解析后由 check_source 检查
+pub async fn verify_box(req: Request<Body>, next: Next<Body>) -> Response {
+    let state = match req.extensions().get::<Arc<WebState>>() {
+        Some(v) => v.clone(),
+        None => {
+            return next.run(req).await;
+        }
+    };
+
+    let ctx = state.ctx.clone();
+    let find_secret = |box_id: String| async move { ctx.dao.find_box_secret(&box_id).await };
+    let verifier = state.verifier.as_ref();
+    match sign::verify_box(req, verifier, &state.secrets, MAX_BODY, find_secret).await {
+        Ok(v) => next.run(v).await,
+        Err(AuthError::Verify(e)) => build_auth_err_response(e),
+        Err(AuthError::TooLarge) => StatusCode::PAYLOAD_TOO_LARGE.into_response(),
+        Err(AuthError::ReadBody(_)) => StatusCode::BAD_REQUEST.into_response(),
+        Err(AuthError::Lookup(e)) => e.into_response(),
+    }
+}
+
+/// 盒子只能上传自己摄像头的 track，没有校验盒子身份(auth 为 None)时不检查
+pub async fn check_source(
+    state: &WebState,
+    auth: Option<&AuthBox>,
+    source: &str,
+) -> Result<bool, AppError> {
+    let box_id = match auth {
+        Some(v) => v.0.as_str(),
+        None => {
+            return Ok(true);
+        }
+    };
+
+    let owner = match state.camera_boxes.get(&source.to_string()) {
+        Some(v) => v,
+        None => {
+            let v = state.ctx.dao.find_camera_box(source).await?;
+            state
+                .camera_boxes
+                .insert(source.to_string(), v.clone())
+                .await;
+            v
+        }
+    };
+    if owner.as_deref() == Some(box_id) {
+        return Ok(true);
+    }
+
+    warn!(
+        "warn, check_source, box: {}, source: {}, owner: {:?}",
+        box_id, source, owner
+    );
+    Ok(false)
+}
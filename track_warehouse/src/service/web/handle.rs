@@ -2,15 +2,22 @@ use std::sync::Arc;
 use std::time::Instant;
 
 use axum::extract::{ContentLengthLimit, Multipart};
+use axum::http::{header, HeaderMap};
 use axum::Extension;
 use bytes::Bytes;
-use chrono::Local;
-use fy_base::api::upload_api::{NotifyCarQueueItem, NotifyFaceQueueItem, ResponseData};
+use chrono::{DateTime, Local};
+use fy_base::api::upload_api::{
+    BatchItemResult, BatchResponseData, NotifyAlarmItem, NotifyCarQueueItem, NotifyFaceQueueItem,
+    ResponseData, QI, RES_STATUS_REJECTED,
+};
+use fy_base::api::upload_codec;
 use fy_base::util::image as image_util;
 use fy_base::util::multipart_form::{parse_multi_form, MultipartFormValues};
+use fy_base::util::sign::AuthBox;
 use serde_json::{self, Result as JsonResult};
 use tracing::{debug, error, info};
 
+use crate::service::web::auth::check_source;
 use crate::service::web::WebState;
 
 //----------------------------------------
@@ -21,6 +28,14 @@ fn build_err_response(err_msg: &str) -> ResponseData {
     }
 }
 
+// track 内容错误，盒子收到后不再重传
+fn build_reject_response(err_msg: &str) -> ResponseData {
+    ResponseData {
+        status: RES_STATUS_REJECTED,
+        message: Some(err_msg.to_string()),
+    }
+}
+
 fn build_ok_response() -> ResponseData {
     ResponseData {
         status: 0,
@@ -28,9 +43,29 @@ fn build_ok_response() -> ResponseData {
     }
 }
 
+// 摄像头不属于上传的盒子时拒收
+async fn check_track_source(
+    data: &WebState,
+    auth: Option<&AuthBox>,
+    source: &str,
+) -> Result<(), ResponseData> {
+    match check_source(data, auth, source).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(build_reject_response(&format!(
+            "error, source: {} not belong to box",
+            source
+        ))),
+        Err(e) => {
+            error!("error, check_source: {}, err: {:?}", source, e);
+            Err(build_err_response(&format!("error, {:?}", e)))
+        }
+    }
+}
+
 //-----------------------------------
 pub async fn track_upload(
     Extension(web_state): Extension<Arc<WebState>>,
+    auth: Option<Extension<AuthBox>>,
     ContentLengthLimit(parts): ContentLengthLimit<Multipart, { 1024 * 1024 * 10 }>,
 ) -> ResponseData {
 
@@ -46,26 +81,31 @@ pub async fn track_upload(
 
     if let Some(track_type) = part_values.get_string_value("type") {
         match track_type.as_str() {
-            "facetrack" => handle_face(web_state, part_values,begin_ts).await,
-            "vehicletrack" => handle_car(web_state, part_values,begin_ts).await,
+            "facetrack" => handle_face(web_state, auth.map(|x| x.0), part_values,begin_ts).await,
+            "vehicletrack" => handle_car(web_state, auth.map(|x| x.0), part_values,begin_ts).await,
             _ => {
                 error!("error, track_upload, unknown type: {}", track_type);
-                build_err_response(&format!("error, unknown type: {}", track_type))
+                build_reject_response(&format!("error, unknown type: {}", track_type))
             }
         }
     } else {
         error!("error, track_upload, field: type not found");
-        build_err_response("error, field: type not found")
+        build_reject_response("error, field: type not found")
     }
 }
 
-async fn handle_face(data: Arc<WebState>, values: MultipartFormValues,begin_ts: Instant) -> ResponseData {
+async fn handle_face(
+    data: Arc<WebState>,
+    auth: Option<AuthBox>,
+    values: MultipartFormValues,
+    begin_ts: Instant,
+) -> ResponseData {
     let now = Local::now();
     let json_str = match values.get_string_value("json") {
         Some(v) => v,
         None => {
             error!("error, field: json not found");
-            return build_err_response("error, field: json not found");
+            return build_reject_response("error, field: json not found");
         }
     };
 
@@ -75,33 +115,39 @@ async fn handle_face(data: Arc<WebState>, values: MultipartFormValues,begin_ts:
         serde_json::from_reader(json_str.as_bytes());
     if let Err(e) = face_queue_item {
         error!("error, json parse error, err: {:?}", e);
-        return build_err_response(&format!("json parse error, err: {:?}", e));
+        return build_reject_response(&format!("json parse error, err: {:?}", e));
     }
 
     let mut face_queue_item = face_queue_item.unwrap();
+    let source = face_queue_item.notify.source.as_str();
+    if let Err(e) = check_track_source(&data, auth.as_ref(), source).await {
+        return e;
+    }
     face_queue_item.ts = now;
     let item = &mut face_queue_item.notify;
 
     debug!("recv track, {}, index:{}, ft", item.id, item.index);
 
-    // 处理图片
-    item.background.image_buf = match values.get_file_value(item.background.image_file.as_str()) {
-        Some((_, v)) => v,
-        None => {
-            error!("error, can't find para: {}", item.background.image_file);
-            return build_err_response(&format!(
-                "error, can't find field: {}",
-                item.background.image_file
-            ));
-        }
-    };
+    // 处理图片，隐私模式下盒子可能不上传背景图
+    if !item.background.image_file.is_empty() {
+        item.background.image_buf = match values.get_file_value(item.background.image_file.as_str()) {
+            Some((_, v)) => v,
+            None => {
+                error!("error, can't find para: {}", item.background.image_file);
+                return build_reject_response(&format!(
+                    "error, can't find field: {}",
+                    item.background.image_file
+                ));
+            }
+        };
+    }
 
     for x in item.faces.iter_mut() {
         x.aligned_buf = match get_jpg_file_value(&values, x.aligned_file.as_str()) {
             Ok(v) => v,
             Err(e) => {
                 error!("error, {}", e);
-                return build_err_response(&e);
+                return build_reject_response(&e);
             }
         };
 
@@ -109,7 +155,7 @@ async fn handle_face(data: Arc<WebState>, values: MultipartFormValues,begin_ts:
             Ok(v) => v,
             Err(e) => {
                 error!("error, {}", e);
-                return build_err_response(&e);
+                return build_reject_response(&e);
             }
         };
 
@@ -119,7 +165,7 @@ async fn handle_face(data: Arc<WebState>, values: MultipartFormValues,begin_ts:
                     Some((_, v)) => Some(v),
                     None => {
                         error!("error, can't find field: {}", feature_file);
-                        return build_err_response(&format!("can't find field: {}", feature_file));
+                        return build_reject_response(&format!("can't find field: {}", feature_file));
                     }
                 }
             } else {
@@ -138,14 +184,19 @@ async fn handle_face(data: Arc<WebState>, values: MultipartFormValues,begin_ts:
     build_ok_response()
 }
 
-async fn handle_car(data: Arc<WebState>, values: MultipartFormValues,begin_ts: Instant) -> ResponseData {
+async fn handle_car(
+    data: Arc<WebState>,
+    auth: Option<AuthBox>,
+    values: MultipartFormValues,
+    begin_ts: Instant,
+) -> ResponseData {
     let now = Local::now();
 
     let json_str = match values.get_string_value("json") {
         Some(v) => v,
         None => {
             error!("error, field: json not found");
-            return build_err_response("error, field: json not found");
+            return build_reject_response("error, field: json not found");
         }
     };
     debug!("->car:{}", json_str);
@@ -154,32 +205,38 @@ async fn handle_car(data: Arc<WebState>, values: MultipartFormValues,begin_ts: I
         serde_json::from_reader(json_str.as_bytes());
     if let Err(e) = car_queue_item {
         error!("error, json parse error, err: {:?}", e);
-        return build_err_response(&format!("json parse error, err: {:?}", e));
+        return build_reject_response(&format!("json parse error, err: {:?}", e));
     }
     let mut car_queue_item = car_queue_item.unwrap();
+    let source = car_queue_item.notify.source.as_str();
+    if let Err(e) = check_track_source(&data, auth.as_ref(), source).await {
+        return e;
+    }
     car_queue_item.ts = now;
     let item = &mut car_queue_item.notify;
 
     debug!("recv track, {}, index:{}, ct", item.id, item.index);
 
-    // 处理图片
-    item.background.image_buf = match values.get_file_value(item.background.image_file.as_str()) {
-        Some((_, v)) => v,
-        None => {
-            error!("error, can't find field: {}", item.background.image_file);
-            return build_err_response(&format!(
-                "error, can't find field: {}",
-                item.background.image_file
-            ));
-        }
-    };
+    // 处理图片，隐私模式下盒子可能不上传背景图
+    if !item.background.image_file.is_empty() {
+        item.background.image_buf = match values.get_file_value(item.background.image_file.as_str()) {
+            Some((_, v)) => v,
+            None => {
+                error!("error, can't find field: {}", item.background.image_file);
+                return build_reject_response(&format!(
+                    "error, can't find field: {}",
+                    item.background.image_file
+                ));
+            }
+        };
+    }
 
     for x in item.vehicles.iter_mut() {
         x.img_buf = match get_jpg_file_value(&values, x.image_file.as_str()) {
             Ok(v) => v,
             Err(e) => {
                 error!("error, {}", e);
-                return build_err_response(&e);
+                return build_reject_response(&e);
             }
         };
     }
@@ -192,7 +249,7 @@ async fn handle_car(data: Arc<WebState>, values: MultipartFormValues,begin_ts: I
                 Ok(v) => v,
                 Err(e) => {
                     error!("error, {}", e);
-                    return build_err_response(&e);
+                    return build_reject_response(&e);
                 }
             };
         } else {
@@ -207,7 +264,7 @@ async fn handle_car(data: Arc<WebState>, values: MultipartFormValues,begin_ts: I
                 Ok(v) => v,
                 Err(e) => {
                     error!("error, {}", e);
-                    return build_err_response(&e);
+                    return build_reject_response(&e);
                 }
             };
         } else {
@@ -221,6 +278,199 @@ async fn handle_car(data: Arc<WebState>, values: MultipartFormValues,begin_ts: I
     build_ok_response()
 }
 
+// 批量上传，body 为 upload_codec 的批量格式，Content-Encoding: gzip 时先解压
+// 按track返回结果，盒子只重传失败的
+pub async fn batch_upload(
+    Extension(web_state): Extension<Arc<WebState>>,
+    auth: Option<Extension<AuthBox>>,
+    headers: HeaderMap,
+    ContentLengthLimit(body): ContentLengthLimit<Bytes, { 1024 * 1024 * 64 }>,
+) -> BatchResponseData {
+    let begin_ts = Instant::now();
+
+    let gzip = headers
+        .get(header::CONTENT_ENCODING)
+        .is_some_and(|x| x.as_bytes() == b"gzip");
+    let body = if gzip {
+        let max_unzip = web_state.ctx.cfg.http.max_unzip * 1024 * 1024;
+        match upload_codec::gunzip(&body, max_unzip) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("error, batch_upload, gunzip, err: {}", e);
+                return build_batch_err_response(&format!("error, gunzip, {}", e));
+            }
+        }
+    } else {
+        body
+    };
+
+    let items = match upload_codec::decode_batch(body) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("error, batch_upload, decode, err: {}", e);
+            return build_batch_err_response(&format!("error, decode, {}", e));
+        }
+    };
+
+    let now = Local::now();
+    let auth = auth.map(|x| x.0);
+    let mut results = Vec::with_capacity(items.len());
+    for (uuid, item) in items {
+        // 解码或图片转换失败，重传也不会成功
+        let rst = match item {
+            Ok(v) => push_batch_item(&web_state, auth.as_ref(), v, now).await,
+            Err(e) => Err(build_reject_response(&e)),
+        };
+
+        results.push(match rst {
+            Ok(_) => BatchItemResult {
+                uuid,
+                status: 0,
+                message: None,
+            },
+            Err(e) => {
+                error!("error, batch_upload, {}, err: {:?}", uuid, e.message);
+                BatchItemResult {
+                    uuid,
+                    status: e.status,
+                    message: e.message,
+                }
+            }
+        });
+    }
+
+    info!(
+        "batch_upload, tracks: {}, use: {}",
+        results.len(),
+        begin_ts.elapsed().as_millis()
+    );
+    BatchResponseData {
+        status: 0,
+        message: Some("success".to_string()),
+        results,
+    }
+}
+
+async fn push_batch_item(
+    data: &WebState,
+    auth: Option<&AuthBox>,
+    item: QI,
+    now: DateTime<Local>,
+) -> Result<(), ResponseData> {
+    match item {
+        QI::FT(mut v) => {
+            check_track_source(data, auth, &v.notify.source).await?;
+            v.ts = now;
+            prepare_face(&mut v).map_err(|e| build_reject_response(&e))?;
+            debug!("recv track, {}, index:{}, ft, batch", v.uuid, v.notify.index);
+            data.face_queue.push(*v);
+        }
+        QI::CT(mut v) => {
+            check_track_source(data, auth, &v.notify.source).await?;
+            v.ts = now;
+            prepare_car(&mut v).map_err(|e| build_reject_response(&e))?;
+            debug!("recv track, {}, index:{}, ct, batch", v.uuid, v.notify.index);
+            data.car_queue.push(*v);
+        }
+    }
+    Ok(())
+}
+
+fn build_batch_err_response(err_msg: &str) -> BatchResponseData {
+    BatchResponseData {
+        status: 500,
+        message: Some(err_msg.to_string()),
+        results: vec![],
+    }
+}
+
+// 和 /upload 一样，小图转成jpg，空的特征值文件名置为 None
+fn prepare_face(item: &mut NotifyFaceQueueItem) -> std::result::Result<(), String> {
+    for x in item.notify.faces.iter_mut() {
+        x.aligned_buf = escape_bmp(&x.aligned_file, x.aligned_buf.clone())?;
+        x.display_buf = escape_bmp(&x.display_file, x.display_buf.clone())?;
+        if x.feature_buf.is_none() {
+            x.feature_file = None;
+        }
+    }
+    Ok(())
+}
+
+fn prepare_car(item: &mut NotifyCarQueueItem) -> std::result::Result<(), String> {
+    for x in item.notify.vehicles.iter_mut() {
+        x.img_buf = escape_bmp(&x.image_file, x.img_buf.clone())?;
+    }
+    if let Some(ref mut x) = item.notify.plate_info {
+        if !x.img_buf.is_empty() {
+            x.img_buf = escape_bmp("plate", x.img_buf.clone())?;
+        }
+        if !x.binary_buf.is_empty() {
+            x.binary_buf = escape_bmp("plate binary", x.binary_buf.clone())?;
+        }
+    }
+    Ok(())
+}
+
+fn escape_bmp(name: &str, buf: Bytes) -> std::result::Result<Bytes, String> {
+    match image_util::escape_bmp(buf) {
+        Ok(v) => Ok(v),
+        Err(e) => Err(format!("can't escape bmp: {}, {:?}", name, e)),
+    }
+}
+
+// 布控告警，直接转发到rabbitmq，不经过minio/mysql
+pub async fn alarm_upload(
+    Extension(web_state): Extension<Arc<WebState>>,
+    auth: Option<Extension<AuthBox>>,
+    ContentLengthLimit(parts): ContentLengthLimit<Multipart, { 1024 * 1024 * 2 }>,
+) -> ResponseData {
+    let values = match parse_multi_form(parts).await {
+        Ok(v) => v,
+        Err(e) => {
+            error!("error, alarm_upload, parse_multi_form, err: {:?}", e);
+            return build_err_response(&format!("error, {:?}", e));
+        }
+    };
+
+    let json_str = match values.get_string_value("json") {
+        Some(v) => v,
+        None => {
+            error!("error, field: json not found");
+            return build_reject_response("error, field: json not found");
+        }
+    };
+    debug!("->alarm:{}", json_str);
+
+    let mut item: NotifyAlarmItem = match serde_json::from_reader(json_str.as_bytes()) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("error, json parse error, err: {:?}", e);
+            return build_reject_response(&format!("json parse error, err: {:?}", e));
+        }
+    };
+
+    let auth = auth.map(|x| x.0);
+    if let Err(e) = check_track_source(&web_state, auth.as_ref(), &item.source).await {
+        return e;
+    }
+
+    item.face_buf = match values.get_file_value(item.face_file.as_str()) {
+        Some((_, v)) => v,
+        None => {
+            error!("error, can't find field: {}", item.face_file);
+            return build_reject_response(&format!("error, can't find field: {}", item.face_file));
+        }
+    };
+
+    info!(
+        "recv alarm, {}, person: {}, score: {}",
+        item.track_uuid, item.matched.uuid, item.matched.score
+    );
+    web_state.alarm_queue.push(item);
+
+    build_ok_response()
+}
+
 fn get_jpg_file_value(
     values: &MultipartFormValues,
     name: &str,
@@ -233,8 +483,5 @@ fn get_jpg_file_value(
     };
 
     // 转成jpg
-    match image_util::escape_bmp(buf) {
-        Ok(v) => Ok(v),
-        Err(e) => Err(format!("can't escape bmp: {}, {:?}", name, e)),
-    }
+    escape_bmp(name, buf)
 }
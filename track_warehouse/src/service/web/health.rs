@@ -0,0 +1,74 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::response::Response;
+use axum::Extension;
+use fy_base::util::health;
+use fy_base::util::minio::new_bucket;
+
+use crate::app_ctx::AppCtx;
+use crate::error::AppError;
+use crate::service::web::WebState;
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+//-----------------------------------
+/// mysql, minio, 识别接口在这里检查，rabbitmq 由 RabbitmqService 上报
+pub async fn readyz(Extension(web_state): Extension<Arc<WebState>>) -> Response {
+    let ctx = &web_state.ctx;
+    let cfg = &ctx.cfg;
+
+    tokio::join!(
+        health::probe("mysql", PROBE_TIMEOUT, ctx.dao.ping()),
+        health::probe(
+            "minio_facetrack",
+            PROBE_TIMEOUT,
+            check_bucket(ctx, &cfg.minio.facetrack_bucket)
+        ),
+        health::probe(
+            "minio_cartrack",
+            PROBE_TIMEOUT,
+            check_bucket(ctx, &cfg.minio.cartrack_bucket)
+        ),
+    );
+
+    // 没有启用的不检查
+    if cfg.search.enable {
+        health::probe(
+            "recognition_search",
+            PROBE_TIMEOUT,
+            ctx.search_recg_api.get_dbs(),
+        )
+        .await;
+    }
+    if cfg.track_db.enable {
+        health::probe(
+            "recognition_trackdb",
+            PROBE_TIMEOUT,
+            ctx.trackdb_recg_api.get_dbs(),
+        )
+        .await;
+    }
+
+    health::readyz_response()
+}
+
+async fn check_bucket(ctx: &AppCtx, bucket_name: &str) -> Result<(), AppError> {
+    let bucket = new_bucket(
+        &ctx.cfg.minio.endpoint,
+        &ctx.cfg.minio.access_key,
+        &ctx.cfg.minio.secret_key,
+        bucket_name,
+    )?;
+
+    let (_, code) = bucket
+        .list_page("".to_string(), None, None, None, Some(1))
+        .await?;
+    if code != 200 {
+        return Err(AppError::new(&format!(
+            "bucket: {}, code: {}",
+            bucket_name, code
+        )));
+    }
+    Ok(())
+}
@@ -1,16 +1,29 @@
+pub mod auth;
 pub mod handle;
+pub mod health;
 
-use axum::routing::post;
+use axum::routing::{get, post};
 use axum::{middleware, Router, Server};
-use hyper::server::conn::AddrIncoming;
+use axum::extract::connect_info::Connected;
+use hyper::server::accept::Accept;
 use hyper::server::Builder;
+use moka::future::Cache;
 use std::net::{AddrParseError, SocketAddr};
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::app_ctx::AppCtx;
-use crate::queue_item::{CarQueue, FaceQueue};
-use crate::service::web::handle::track_upload;
-use fy_base::util::{axum_log::time_use, service::Service};
+use crate::queue_item::{AlarmQueue, CarQueue, FaceQueue};
+use crate::service::web::auth::verify_box;
+use crate::service::web::handle::{alarm_upload, batch_upload, track_upload};
+use crate::service::web::health::readyz;
+use fy_base::util::{
+    axum_log::time_use, health::healthz_handler, metrics::metrics_handler, service::Service,
+    sign::{SecretCache, Verifier},
+    tls::{self, PeerInfo},
+};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
 use tokio::sync::watch::Receiver;
 use tokio::task::JoinHandle;
 use tower::limit::GlobalConcurrencyLimitLayer;
@@ -24,33 +37,64 @@ pub struct WebState {
     pub ctx: Arc<AppCtx>,
     pub face_queue: Arc<FaceQueue>,
     pub car_queue: Arc<CarQueue>,
+    pub alarm_queue: Arc<AlarmQueue>,
+    pub verifier: Option<Verifier>, // 没有开启 auth 时为 None
+    pub secrets: SecretCache,
+    pub camera_boxes: Cache<String, Option<String>>, // 摄像头 uuid -> 所属盒子的 hw_id
 }
 
 pub struct WebService {
     pub ctx: Arc<AppCtx>,
     pub face_queue: Arc<FaceQueue>,
     pub car_queue: Arc<CarQueue>,
+    pub alarm_queue: Arc<AlarmQueue>,
 }
 
 impl WebService {
-    pub fn new(ctx: Arc<AppCtx>, face_queue: Arc<FaceQueue>, car_queue: Arc<CarQueue>) -> Self {
+    pub fn new(
+        ctx: Arc<AppCtx>,
+        face_queue: Arc<FaceQueue>,
+        car_queue: Arc<CarQueue>,
+        alarm_queue: Arc<AlarmQueue>,
+    ) -> Self {
         Self {
             ctx,
             face_queue,
             car_queue,
+            alarm_queue,
         }
     }
 
     pub async fn init_router(&self) -> Router {
         let max_request_conn = 10000;
 
+        let auth = &self.ctx.cfg.auth;
         let web_state = Arc::new(WebState {
             ctx: self.ctx.clone(),
             face_queue: self.face_queue.clone(),
             car_queue: self.car_queue.clone(),
+            alarm_queue: self.alarm_queue.clone(),
+            verifier: auth.enable.then(|| Verifier::new(auth.window)),
+            secrets: SecretCache::new(Duration::from_secs(auth.cache_ttl)),
+            camera_boxes: Cache::builder()
+                .max_capacity(10000)
+                .time_to_live(Duration::from_secs(auth.cache_ttl))
+                .build(),
         });
 
-        Router::new().route("/upload", post(track_upload)).layer(
+        // 盒子上传的接口需要校验身份
+        let box_router = Router::new()
+            .route("/upload", post(track_upload))
+            .route("/upload_batch", post(batch_upload))
+            .route("/alarm", post(alarm_upload))
+            .route_layer(middleware::from_fn(verify_box));
+
+        Router::new()
+            .merge(box_router)
+            .route("/metrics", get(metrics_handler))
+            .route("/healthz", get(healthz_handler))
+            .route("/readyz", get(readyz))
+            .layer(
             ServiceBuilder::new()
                 // 限制请求的并发数量
                 .layer(GlobalConcurrencyLimitLayer::new(max_request_conn))
@@ -73,7 +117,13 @@ impl WebService {
         addr.parse::<SocketAddr>()
     }
 
-    async fn run(self, server: Builder<AddrIncoming>, exit_rx: Receiver<i64>) {
+    async fn run<I>(self, server: Builder<I>, exit_rx: Receiver<i64>)
+    where
+        I: Accept + Send + 'static,
+        I::Conn: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        I::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        for<'a> PeerInfo: Connected<&'a I::Conn>,
+    {
         let mut exit_rx = exit_rx;
         let mut exit_rx_cl = exit_rx.clone();
 
@@ -81,7 +131,7 @@ impl WebService {
         let app = self.init_router().await;
 
         let graceful = server
-            .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+            .serve(app.into_make_service_with_connect_info::<PeerInfo>())
             .with_graceful_shutdown(async move {
                 let _ = exit_rx_cl.changed().await;
                 info!("with_graceful_shutdown.");
@@ -107,6 +157,21 @@ impl Service for WebService {
             .unwrap_or_else(|_| panic!("cant parse http addr: {}", self.ctx.cfg.http.addr));
         info!("http bind: {:?}", addr);
 
+        // https，可以要求客户端证书
+        let tls_cfg = &self.ctx.cfg.http.tls;
+        if tls_cfg.enable {
+            let config = tls_cfg.load().unwrap_or_else(|e| {
+                error!("error, load http tls, err: {}", e);
+                panic!("error, load http tls, err: {}", e)
+            });
+            let listener = bind_listener(addr).unwrap_or_else(|e| {
+                error!("error, https can't bind: {:?}, err: {:?}", addr, e);
+                panic!("error, https can't bind: {:?}, err: {:?}", addr, e)
+            });
+            let server = Server::builder(tls::incoming(listener, config));
+            return tokio::spawn(self.run(server, exit_rx));
+        }
+
         let server = Server::try_bind(&addr).unwrap_or_else(|e| {
             error!("error, http can't bind: {:?}, err: {:?}", addr, e);
             panic!("error, http can't bind: {:?}, err: {:?}", addr, e)
@@ -115,3 +180,10 @@ impl Service for WebService {
         tokio::spawn(self.run(server, exit_rx))
     }
 }
+
+//-----------------------
+fn bind_listener(addr: SocketAddr) -> std::io::Result<TcpListener> {
+    let listener = std::net::TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    TcpListener::from_std(listener)
+}